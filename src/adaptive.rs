@@ -0,0 +1,97 @@
+use crate::pixels::PixelData;
+
+/// Texture below which a pixel counts as flat when no threshold is given
+pub const DEFAULT_TEXTURE_THRESHOLD: u32 = 24;
+
+/// Measures how busy the area around every pixel is: for each of the first `color_channels`
+/// channels the absolute differences to the four neighbours are summed, and the largest sum
+/// is kept. Samples are scaled to 8 bits with their least significant bit cleared first, so
+/// LSB replacement never changes the result and a decoder sees the same texture.
+pub fn texture(pixels: &PixelData, color_channels: usize) -> Vec<u32> {
+    let (width, height) = (pixels.width() as usize, pixels.height() as usize);
+    let channels = pixels.channels();
+    let max = pixels.header().max_sample() as u32;
+    let samples = pixels.samples();
+
+    let level = |x: usize, y: usize, channel: usize| -> i64 {
+        ((samples[(y * width + x) * channels + channel] as u32 & !1) * 255 / max) as i64
+    };
+
+    let mut texture = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let mut neighbours = Vec::with_capacity(4);
+            if x > 0 {
+                neighbours.push((x - 1, y));
+            }
+            if x + 1 < width {
+                neighbours.push((x + 1, y));
+            }
+            if y > 0 {
+                neighbours.push((x, y - 1));
+            }
+            if y + 1 < height {
+                neighbours.push((x, y + 1));
+            }
+
+            let busiest = (0..color_channels)
+                .map(|channel| {
+                    let center = level(x, y, channel);
+                    neighbours
+                        .iter()
+                        .map(|&(nx, ny)| center.abs_diff(level(nx, ny, channel)) as u32)
+                        .sum::<u32>()
+                })
+                .max()
+                .unwrap_or(0);
+            texture.push(busiest);
+        }
+    }
+
+    texture
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixels::{ColorType, ImageHeader};
+
+    fn half_flat_pixels(bit_depth: u8) -> PixelData {
+        let header = ImageHeader {
+            width: 8,
+            height: 4,
+            bit_depth,
+            color_type: ColorType::Grayscale,
+            interlaced: false,
+        };
+        let max = header.max_sample();
+        // left half is a checkerboard, right half a single gray
+        let samples = (0..8 * 4)
+            .map(|i| match (i % 8 < 4, (i % 8 + i / 8) % 2 == 0) {
+                (true, true) => max,
+                (true, false) => 0,
+                (false, _) => max / 2,
+            })
+            .collect();
+        PixelData::new(header, samples).unwrap()
+    }
+
+    #[test]
+    fn test_texture_finds_busy_regions() {
+        for bit_depth in [8, 16] {
+            let texture = texture(&half_flat_pixels(bit_depth), 1);
+            assert!(texture[8 + 1] > DEFAULT_TEXTURE_THRESHOLD);
+            assert_eq!(texture[8 + 6], 0);
+        }
+    }
+
+    #[test]
+    fn test_texture_ignores_lsb() {
+        let mut pixels = half_flat_pixels(8);
+        let before = texture(&pixels, 1);
+        for sample in pixels.samples_mut() {
+            *sample ^= 1;
+        }
+        assert_eq!(texture(&pixels, 1), before);
+    }
+}
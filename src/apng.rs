@@ -0,0 +1,809 @@
+use crate::chunk::Chunk;
+use crate::pixels::{ImageHeader, PixelData};
+use crate::png::{ChunkPosition, Png, UnsafeChunkPolicy};
+use crate::{Error, Result, chunk_types::ChunkType};
+
+use std::fmt;
+
+/// The contents of an `acTL` chunk. See the APNG spec for more details
+/// https://wiki.mozilla.org/APNG_Specification
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnimationControl {
+    pub num_frames: u32,
+    /// Number of times to loop the animation, 0 means forever
+    pub num_plays: u32,
+}
+
+impl AnimationControl {
+    /// Returns this animation control as an `acTL` chunk
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = Vec::with_capacity(8);
+        data.extend_from_slice(&self.num_frames.to_be_bytes());
+        data.extend_from_slice(&self.num_plays.to_be_bytes());
+
+        Chunk::new(ChunkType::new(b"acTL"), data)
+    }
+}
+
+impl TryFrom<&[u8]> for AnimationControl {
+    type Error = Error;
+
+    fn try_from(data: &[u8]) -> Result<Self> {
+        if data.len() != 8 {
+            return Err("acTL must be 8 bytes long".into());
+        }
+
+        let num_frames = read_u32(data, 0);
+        if num_frames == 0 {
+            return Err("acTL must declare at least one frame".into());
+        }
+
+        Ok(AnimationControl {
+            num_frames,
+            num_plays: read_u32(data, 4),
+        })
+    }
+}
+
+/// How the frame area is disposed of before rendering the next frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisposeOp {
+    /// Leave the canvas as it is
+    None,
+    /// Clear the frame area to fully transparent black
+    Background,
+    /// Restore the frame area to what it was before this frame was rendered
+    Previous,
+}
+
+/// How the frame is combined with the canvas
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendOp {
+    /// Replace the frame area, including alpha
+    Source,
+    /// Alpha composite the frame over the canvas
+    Over,
+}
+
+/// The contents of an `fcTL` chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameControl {
+    pub sequence_number: u32,
+    pub width: u32,
+    pub height: u32,
+    pub x_offset: u32,
+    pub y_offset: u32,
+    pub delay_num: u16,
+    pub delay_den: u16,
+    pub dispose_op: DisposeOp,
+    pub blend_op: BlendOp,
+}
+
+impl FrameControl {
+    /// How long the frame is shown, in seconds. A denominator of 0 means hundredths of a second.
+    pub fn delay(&self) -> f64 {
+        let den = if self.delay_den == 0 {
+            100
+        } else {
+            self.delay_den
+        };
+        self.delay_num as f64 / den as f64
+    }
+
+    /// Returns this frame control as an `fcTL` chunk
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = Vec::with_capacity(26);
+        data.extend_from_slice(&self.sequence_number.to_be_bytes());
+        data.extend_from_slice(&self.width.to_be_bytes());
+        data.extend_from_slice(&self.height.to_be_bytes());
+        data.extend_from_slice(&self.x_offset.to_be_bytes());
+        data.extend_from_slice(&self.y_offset.to_be_bytes());
+        data.extend_from_slice(&self.delay_num.to_be_bytes());
+        data.extend_from_slice(&self.delay_den.to_be_bytes());
+        data.push(match self.dispose_op {
+            DisposeOp::None => 0,
+            DisposeOp::Background => 1,
+            DisposeOp::Previous => 2,
+        });
+        data.push(match self.blend_op {
+            BlendOp::Source => 0,
+            BlendOp::Over => 1,
+        });
+
+        Chunk::new(ChunkType::new(b"fcTL"), data)
+    }
+}
+
+impl TryFrom<&[u8]> for FrameControl {
+    type Error = Error;
+
+    fn try_from(data: &[u8]) -> Result<Self> {
+        if data.len() != 26 {
+            return Err("fcTL must be 26 bytes long".into());
+        }
+
+        let dispose_op = match data[24] {
+            0 => DisposeOp::None,
+            1 => DisposeOp::Background,
+            2 => DisposeOp::Previous,
+            op => return Err(format!("Invalid dispose op {}", op).into()),
+        };
+        let blend_op = match data[25] {
+            0 => BlendOp::Source,
+            1 => BlendOp::Over,
+            op => return Err(format!("Invalid blend op {}", op).into()),
+        };
+
+        let control = FrameControl {
+            sequence_number: read_u32(data, 0),
+            width: read_u32(data, 4),
+            height: read_u32(data, 8),
+            x_offset: read_u32(data, 12),
+            y_offset: read_u32(data, 16),
+            delay_num: u16::from_be_bytes([data[20], data[21]]),
+            delay_den: u16::from_be_bytes([data[22], data[23]]),
+            dispose_op,
+            blend_op,
+        };
+
+        if control.width == 0 || control.height == 0 {
+            return Err("Frame dimensions must be non-zero".into());
+        }
+
+        Ok(control)
+    }
+}
+
+impl fmt::Display for FrameControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x{} at ({}, {}), {:.3}s, dispose {:?}, blend {:?}",
+            self.width,
+            self.height,
+            self.x_offset,
+            self.y_offset,
+            self.delay(),
+            self.dispose_op,
+            self.blend_op
+        )
+    }
+}
+
+/// A single animation frame with its compressed image data gathered from `IDAT` or `fdAT`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub control: FrameControl,
+    /// The zlib stream of the frame, without the `fdAT` sequence numbers
+    pub data: Vec<u8>,
+    /// True if this frame is the default image stored in `IDAT`
+    pub is_default_image: bool,
+}
+
+impl Frame {
+    /// Decodes the pixels of this frame. `header` is the `IHDR` of the whole image.
+    pub fn decode(&self, header: &ImageHeader) -> Result<PixelData> {
+        let frame_header = ImageHeader {
+            width: self.control.width,
+            height: self.control.height,
+            ..*header
+        };
+        PixelData::decode_stream(frame_header, &self.data)
+    }
+}
+
+/// The animation stored in an APNG file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Animation {
+    control: AnimationControl,
+    frames: Vec<Frame>,
+}
+
+impl Animation {
+    /// Parses the animation chunks of `png`, checking the sequence numbers, the frame count
+    /// and that every frame fits on the canvas.
+    pub fn from_png(png: &Png) -> Result<Self> {
+        let control = AnimationControl::try_from(
+            png.chunk_by_type("acTL")
+                .ok_or("Not an animated PNG, no acTL chunk found")?
+                .data(),
+        )?;
+        let header = ImageHeader::from_png(png)?;
+
+        let mut frames: Vec<Frame> = Vec::new();
+        let mut next_sequence = 0;
+        let mut seen_idat = false;
+
+        for chunk in png.chunks() {
+            match &chunk.chunk_type().bytes() {
+                b"fcTL" => {
+                    let frame_control = FrameControl::try_from(chunk.data())?;
+                    check_sequence(&mut next_sequence, frame_control.sequence_number)?;
+                    frames.push(Frame {
+                        control: frame_control,
+                        data: Vec::new(),
+                        is_default_image: !seen_idat,
+                    });
+                }
+                b"IDAT" => {
+                    seen_idat = true;
+                    if let Some(frame) = frames.last_mut().filter(|f| f.is_default_image) {
+                        frame.data.extend_from_slice(chunk.data());
+                    }
+                }
+                b"fdAT" => {
+                    if chunk.data().len() < 4 {
+                        return Err("fdAT chunk is too short".into());
+                    }
+                    check_sequence(&mut next_sequence, read_u32(chunk.data(), 0))?;
+                    match frames.last_mut() {
+                        Some(frame) if !frame.is_default_image => {
+                            frame.data.extend_from_slice(&chunk.data()[4..])
+                        }
+                        _ => return Err("fdAT chunk without a preceding fcTL".into()),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if frames.len() != control.num_frames as usize {
+            return Err(format!(
+                "acTL declares {} frames but {} were found",
+                control.num_frames,
+                frames.len()
+            )
+            .into());
+        }
+
+        for (index, frame) in frames.iter().enumerate() {
+            let c = &frame.control;
+            if frame.data.is_empty() {
+                return Err(format!("Frame {} has no image data", index).into());
+            }
+            if c.x_offset as u64 + c.width as u64 > header.width as u64
+                || c.y_offset as u64 + c.height as u64 > header.height as u64
+            {
+                return Err(format!("Frame {} doesn't fit on the canvas", index).into());
+            }
+        }
+
+        let first = &frames[0].control;
+        if first.width != header.width
+            || first.height != header.height
+            || first.x_offset != 0
+            || first.y_offset != 0
+        {
+            return Err("The first frame must cover the whole canvas".into());
+        }
+
+        Ok(Animation { control, frames })
+    }
+
+    /// The `acTL` contents of this animation
+    pub fn control(&self) -> &AnimationControl {
+        &self.control
+    }
+
+    /// The frames of this animation in display order
+    pub fn frames(&self) -> impl Iterator<Item = &Frame> {
+        self.frames.iter()
+    }
+
+    /// Returns true if the default `IDAT` image is the first frame of the animation
+    pub fn default_image_is_frame(&self) -> bool {
+        self.frames[0].is_default_image
+    }
+
+    /// Replaces the compressed image data of the frame at `index`
+    pub fn set_frame_data(&mut self, index: usize, data: Vec<u8>) -> Result<()> {
+        let frame = self
+            .frames
+            .get_mut(index)
+            .ok_or("Frame index out of range")?;
+        frame.data = data;
+        Ok(())
+    }
+
+    /// Writes the frames of this animation back into `png`, replacing its `fcTL` and `fdAT`
+    /// chunks and renumbering them. Every frame gets a single data chunk placed right after
+    /// the `IDAT` chunks. If the default image is a frame and its data changed, unknown unsafe
+    /// chunks are handled according to `policy`, see `Png::replace_critical_chunks`.
+    pub fn apply(&self, png: &mut Png, policy: UnsafeChunkPolicy) -> Result<Vec<Chunk>> {
+        for chunk_type in ["fcTL", "fdAT"] {
+            if png.chunk_by_type(chunk_type).is_some() {
+                png.remove_chunks(chunk_type)?;
+            }
+        }
+
+        let mut dropped = Vec::new();
+        let mut sequence_number = 0;
+        let mut frames = self.frames.iter();
+
+        if self.default_image_is_frame() {
+            let frame = frames.next().unwrap();
+            dropped = png.replace_critical_chunks(
+                vec![Chunk::new(ChunkType::new(b"IDAT"), frame.data.clone())],
+                policy,
+            )?;
+            let control = FrameControl {
+                sequence_number,
+                ..frame.control
+            };
+            png.insert_chunk(control.to_chunk(), ChunkPosition::BeforeFirstIdat)?;
+            sequence_number += 1;
+        }
+
+        let mut anchor = ChunkType::new(b"IDAT");
+        for frame in frames {
+            let control = FrameControl {
+                sequence_number,
+                ..frame.control
+            };
+            png.insert_chunk(control.to_chunk(), ChunkPosition::After(anchor))?;
+
+            let mut data = (sequence_number + 1).to_be_bytes().to_vec();
+            data.extend_from_slice(&frame.data);
+            png.insert_chunk(
+                Chunk::new(ChunkType::new(b"fdAT"), data),
+                ChunkPosition::After(ChunkType::new(b"fcTL")),
+            )?;
+
+            sequence_number += 2;
+            anchor = ChunkType::new(b"fdAT");
+        }
+
+        Ok(dropped)
+    }
+
+    /// Renders every frame of the animation onto an 8-bit RGBA canvas the size of the image,
+    /// applying the blend and dispose operations. `png` must be the file this animation
+    /// was parsed from.
+    pub fn render(&self, png: &Png) -> Result<Vec<PixelData>> {
+        let header = ImageHeader::from_png(png)?;
+        let palette = png.chunk_by_type("PLTE").map(|c| c.data());
+        let transparency = png.chunk_by_type("tRNS").map(|c| c.data());
+
+        let canvas_width = header.width as usize;
+        let canvas_header = ImageHeader {
+            bit_depth: 8,
+            color_type: crate::pixels::ColorType::Rgba,
+            interlaced: false,
+            ..header
+        };
+        let mut canvas = vec![0u16; canvas_width * header.height as usize * 4];
+        let mut rendered = Vec::with_capacity(self.frames.len());
+
+        for (index, frame) in self.frames.iter().enumerate() {
+            let c = &frame.control;
+            let pixels = frame.decode(&header)?.to_rgba8(palette, transparency)?;
+
+            // a first frame disposed to "previous" is treated as "background" by the spec
+            let dispose = match c.dispose_op {
+                DisposeOp::Previous if index == 0 => DisposeOp::Background,
+                op => op,
+            };
+            let saved = (dispose == DisposeOp::Previous).then(|| canvas.clone());
+
+            for y in 0..c.height as usize {
+                for x in 0..c.width as usize {
+                    let src = &pixels.samples()[(y * c.width as usize + x) * 4..][..4];
+                    let dst_index =
+                        ((c.y_offset as usize + y) * canvas_width + c.x_offset as usize + x) * 4;
+                    let dst = &mut canvas[dst_index..dst_index + 4];
+
+                    match c.blend_op {
+                        BlendOp::Source => dst.copy_from_slice(src),
+                        BlendOp::Over => blend_over(dst, src),
+                    }
+                }
+            }
+
+            rendered.push(PixelData::new(canvas_header, canvas.clone())?);
+
+            match dispose {
+                DisposeOp::None => {}
+                DisposeOp::Background => {
+                    for y in 0..c.height as usize {
+                        let start =
+                            ((c.y_offset as usize + y) * canvas_width + c.x_offset as usize) * 4;
+                        canvas[start..start + c.width as usize * 4].fill(0);
+                    }
+                }
+                DisposeOp::Previous => canvas = saved.unwrap(),
+            }
+        }
+
+        Ok(rendered)
+    }
+}
+
+impl fmt::Display for Animation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plays = match self.control.num_plays {
+            0 => String::from("forever"),
+            n => format!("{} times", n),
+        };
+        writeln!(
+            f,
+            "Animation: {} frames, plays {}{}",
+            self.control.num_frames,
+            plays,
+            if self.default_image_is_frame() {
+                ""
+            } else {
+                " (default image not part of the animation)"
+            }
+        )?;
+        for (index, frame) in self.frames.iter().enumerate() {
+            writeln!(f, "Frame {}: {}", index, frame.control)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Settings used by `assemble`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AssembleOptions {
+    /// Delay of every frame in milliseconds. Either empty for the default of 100ms, a single
+    /// value used for all frames or one value per frame.
+    pub delays_ms: Vec<u16>,
+    /// Number of times to loop the animation, 0 means forever
+    pub num_plays: u32,
+}
+
+/// The delay used for every frame when `AssembleOptions::delays_ms` is empty
+pub const DEFAULT_DELAY_MS: u16 = 100;
+
+/// Builds an animated PNG from `frames`. All frames must share the same image header and
+/// palette. When `default_image` is given it is shown by decoders without APNG support and is
+/// not part of the animation, otherwise the first frame is the default image.
+/// Ancillary chunks of the default image are kept.
+pub fn assemble(
+    frames: &[Png],
+    default_image: Option<&Png>,
+    options: &AssembleOptions,
+) -> Result<Png> {
+    let first = frames.first().ok_or("At least one frame is required")?;
+    let base = default_image.unwrap_or(first);
+    let header = ImageHeader::from_png(base)?;
+    let palette = base.chunk_by_type("PLTE").map(|c| c.data());
+
+    for (index, frame) in frames.iter().enumerate() {
+        if ImageHeader::from_png(frame)? != header {
+            return Err(format!("Frame {} has a different image header", index).into());
+        }
+        if frame.chunk_by_type("PLTE").map(|c| c.data()) != palette {
+            return Err(format!("Frame {} has a different palette", index).into());
+        }
+    }
+
+    let delays = match options.delays_ms.len() {
+        0 => vec![DEFAULT_DELAY_MS; frames.len()],
+        1 => vec![options.delays_ms[0]; frames.len()],
+        n if n == frames.len() => options.delays_ms.clone(),
+        n => {
+            return Err(format!("Got {} delays for {} frames", n, frames.len()).into());
+        }
+    };
+
+    let is_animation_chunk = |t: &[u8; 4]| matches!(t, b"acTL" | b"fcTL" | b"fdAT");
+    let first_idat = base
+        .chunks()
+        .iter()
+        .position(|c| c.chunk_type().bytes() == *b"IDAT")
+        .ok_or("Default image has no IDAT chunk")?;
+    let (before_idat, after_idat) = base.chunks().split_at(first_idat);
+
+    let mut chunks = vec![header.to_chunk()];
+    chunks.push(
+        AnimationControl {
+            num_frames: frames.len() as u32,
+            num_plays: options.num_plays,
+        }
+        .to_chunk(),
+    );
+    chunks.extend(
+        before_idat
+            .iter()
+            .filter(|c| {
+                !matches!(&c.chunk_type().bytes(), b"IHDR")
+                    && !is_animation_chunk(&c.chunk_type().bytes())
+            })
+            .cloned(),
+    );
+
+    let mut sequence_number = 0;
+    for (index, (frame, delay)) in frames.iter().zip(delays).enumerate() {
+        let control = FrameControl {
+            sequence_number,
+            width: header.width,
+            height: header.height,
+            x_offset: 0,
+            y_offset: 0,
+            delay_num: delay,
+            delay_den: 1000,
+            dispose_op: DisposeOp::None,
+            blend_op: BlendOp::Source,
+        };
+        sequence_number += 1;
+
+        if index == 0 && default_image.is_none() {
+            chunks.push(control.to_chunk());
+            chunks.push(Chunk::new(ChunkType::new(b"IDAT"), frame.image_data()));
+            continue;
+        }
+        if index == 0 {
+            chunks.push(Chunk::new(ChunkType::new(b"IDAT"), base.image_data()));
+        }
+
+        chunks.push(control.to_chunk());
+        let mut data = sequence_number.to_be_bytes().to_vec();
+        data.extend_from_slice(&frame.image_data());
+        chunks.push(Chunk::new(ChunkType::new(b"fdAT"), data));
+        sequence_number += 1;
+    }
+
+    chunks.extend(
+        after_idat
+            .iter()
+            .filter(|c| {
+                let t = c.chunk_type().bytes();
+                !matches!(&t, b"IDAT" | b"IEND") && !is_animation_chunk(&t)
+            })
+            .cloned(),
+    );
+    chunks.push(Chunk::new(ChunkType::new(b"IEND"), Vec::new()));
+
+    let png = Png::from_chunks(chunks);
+    Animation::from_png(&png)?;
+
+    Ok(png)
+}
+
+/// Checks that `sequence_number` is the next one expected
+fn check_sequence(next_sequence: &mut u32, sequence_number: u32) -> Result<()> {
+    if sequence_number != *next_sequence {
+        return Err(format!(
+            "Expected sequence number {}, found {}",
+            next_sequence, sequence_number
+        )
+        .into());
+    }
+    *next_sequence += 1;
+    Ok(())
+}
+
+/// Alpha composites the 8-bit RGBA pixel `src` over `dst`
+fn blend_over(dst: &mut [u16], src: &[u16]) {
+    let src_alpha = src[3] as u32;
+    if src_alpha == 255 {
+        dst.copy_from_slice(src);
+        return;
+    }
+    if src_alpha == 0 {
+        return;
+    }
+
+    let dst_alpha = dst[3] as u32 * (255 - src_alpha) / 255;
+    let out_alpha = src_alpha + dst_alpha;
+    for i in 0..3 {
+        dst[i] = ((src[i] as u32 * src_alpha + dst[i] as u32 * dst_alpha) / out_alpha) as u16;
+    }
+    dst[3] = out_alpha as u16;
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixels::ColorType;
+
+    fn testing_header() -> ImageHeader {
+        ImageHeader {
+            width: 2,
+            height: 2,
+            bit_depth: 8,
+            color_type: ColorType::Rgba,
+            interlaced: false,
+        }
+    }
+
+    fn frame_control(sequence_number: u32, width: u32, x_offset: u32) -> FrameControl {
+        FrameControl {
+            sequence_number,
+            width,
+            height: 2,
+            x_offset,
+            y_offset: 0,
+            delay_num: 1,
+            delay_den: 10,
+            dispose_op: DisposeOp::None,
+            blend_op: BlendOp::Source,
+        }
+    }
+
+    fn encoded(width: u32, rgba: [u16; 4]) -> Vec<u8> {
+        let header = ImageHeader {
+            width,
+            ..testing_header()
+        };
+        let samples = rgba.repeat(width as usize * 2);
+        PixelData::new(header, samples).unwrap().encode().unwrap()
+    }
+
+    fn fd_at(sequence_number: u32, data: &[u8]) -> Chunk {
+        let mut chunk_data = sequence_number.to_be_bytes().to_vec();
+        chunk_data.extend_from_slice(data);
+        Chunk::new(ChunkType::new(b"fdAT"), chunk_data)
+    }
+
+    fn testing_apng() -> Png {
+        let mut second = frame_control(1, 1, 1);
+        second.blend_op = BlendOp::Over;
+
+        Png::from_chunks(vec![
+            testing_header().to_chunk(),
+            AnimationControl {
+                num_frames: 2,
+                num_plays: 0,
+            }
+            .to_chunk(),
+            frame_control(0, 2, 0).to_chunk(),
+            Chunk::new(ChunkType::new(b"IDAT"), encoded(2, [255, 0, 0, 255])),
+            second.to_chunk(),
+            fd_at(2, &encoded(1, [0, 0, 255, 255])),
+            Chunk::new(ChunkType::new(b"IEND"), Vec::new()),
+        ])
+    }
+
+    #[test]
+    fn test_frame_control_round_trip() {
+        let control = frame_control(7, 2, 0);
+        let chunk = control.to_chunk();
+        assert_eq!(FrameControl::try_from(chunk.data()).unwrap(), control);
+        assert_eq!(control.delay(), 0.1);
+    }
+
+    #[test]
+    fn test_parse_animation() {
+        let animation = Animation::from_png(&testing_apng()).unwrap();
+        assert_eq!(animation.frames().count(), 2);
+        assert!(animation.default_image_is_frame());
+        assert_eq!(animation.frames().nth(1).unwrap().control.x_offset, 1);
+    }
+
+    #[test]
+    fn test_bad_sequence_number() {
+        let mut chunks = testing_apng().chunks().to_vec();
+        chunks[5] = fd_at(3, &encoded(1, [0, 0, 255, 255]));
+        assert!(Animation::from_png(&Png::from_chunks(chunks)).is_err());
+    }
+
+    #[test]
+    fn test_frame_count_mismatch() {
+        let mut chunks = testing_apng().chunks().to_vec();
+        chunks[1] = AnimationControl {
+            num_frames: 3,
+            num_plays: 0,
+        }
+        .to_chunk();
+        assert!(Animation::from_png(&Png::from_chunks(chunks)).is_err());
+    }
+
+    #[test]
+    fn test_not_animated() {
+        let mut chunks = testing_apng().chunks().to_vec();
+        chunks.remove(1);
+        assert!(Animation::from_png(&Png::from_chunks(chunks)).is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let png = testing_apng();
+        let frames = Animation::from_png(&png).unwrap().render(&png).unwrap();
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].pixel(1, 0), &[255, 0, 0, 255]);
+        assert_eq!(frames[1].pixel(0, 0), &[255, 0, 0, 255]);
+        assert_eq!(frames[1].pixel(1, 1), &[0, 0, 255, 255]);
+    }
+
+    fn still_png(rgba: [u16; 4]) -> Png {
+        let pixels = PixelData::new(testing_header(), rgba.repeat(4)).unwrap();
+        let mut png = Png::from_pixels(&pixels).unwrap();
+        png.append_chunk(Chunk::new(
+            ChunkType::new(b"tEXt"),
+            b"Title\0frame".to_vec(),
+        ));
+        png
+    }
+
+    #[test]
+    fn test_assemble() {
+        let frames = [still_png([255, 0, 0, 255]), still_png([0, 255, 0, 255])];
+        let options = AssembleOptions {
+            delays_ms: vec![40, 80],
+            num_plays: 3,
+        };
+        let png = assemble(&frames, None, &options).unwrap();
+        let animation = Animation::from_png(&png).unwrap();
+
+        assert!(animation.default_image_is_frame());
+        assert_eq!(animation.control().num_plays, 3);
+        let delays: Vec<f64> = animation.frames().map(|f| f.control.delay()).collect();
+        assert_eq!(delays, vec![0.04, 0.08]);
+
+        let rendered = animation.render(&png).unwrap();
+        assert_eq!(rendered[1].pixel(0, 0), &[0, 255, 0, 255]);
+        assert!(png.chunk_by_type("tEXt").is_some());
+    }
+
+    #[test]
+    fn test_assemble_separate_default_image() {
+        let frames = [still_png([255, 0, 0, 255]), still_png([0, 255, 0, 255])];
+        let default_image = still_png([0, 0, 0, 255]);
+        let png = assemble(&frames, Some(&default_image), &AssembleOptions::default()).unwrap();
+        let animation = Animation::from_png(&png).unwrap();
+
+        assert!(!animation.default_image_is_frame());
+        assert_eq!(animation.frames().count(), 2);
+        assert_eq!(
+            PixelData::decode(&png).unwrap().pixel(0, 0),
+            &[0, 0, 0, 255]
+        );
+        let rendered = animation.render(&png).unwrap();
+        assert_eq!(rendered[0].pixel(1, 1), &[255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_assemble_mismatched_frames() {
+        let other = Png::from_pixels(
+            &PixelData::new(
+                ImageHeader {
+                    width: 1,
+                    ..testing_header()
+                },
+                vec![0; 8],
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let frames = [still_png([255, 0, 0, 255]), other];
+        assert!(assemble(&frames, None, &AssembleOptions::default()).is_err());
+
+        let options = AssembleOptions {
+            delays_ms: vec![1, 2, 3],
+            num_plays: 0,
+        };
+        let frames = [still_png([255, 0, 0, 255])];
+        assert!(assemble(&frames, None, &options).is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut png = testing_apng();
+        let mut animation = Animation::from_png(&png).unwrap();
+        let new_data = encoded(1, [0, 255, 0, 255]);
+        animation.set_frame_data(1, new_data.clone()).unwrap();
+        animation.apply(&mut png, UnsafeChunkPolicy::Drop).unwrap();
+
+        let reparsed = Animation::from_png(&png).unwrap();
+        assert_eq!(reparsed.frames().nth(1).unwrap().data, new_data);
+        assert_eq!(reparsed, animation);
+    }
+
+    #[test]
+    fn test_blend_over() {
+        let mut dst = [0, 0, 255, 255];
+        blend_over(&mut dst, &[255, 0, 0, 0]);
+        assert_eq!(dst, [0, 0, 255, 255]);
+
+        blend_over(&mut dst, &[255, 0, 0, 255]);
+        assert_eq!(dst, [255, 0, 0, 255]);
+    }
+}
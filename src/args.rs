@@ -1,4 +1,4 @@
-use clap::{Args as ClapArgs, Parser, Subcommand};
+use clap::{Args as ClapArgs, Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
@@ -14,6 +14,37 @@ pub enum PngMeArgs {
     Decode(DecodeArgs),
     Remove(RemoveArgs),
     Print(PrintArgs),
+    Explode(ExplodeArgs),
+    Implode(ImplodeArgs),
+    Diff(DiffArgs),
+    Strip(StripArgs),
+    Frames(FramesArgs),
+    Animate(AnimateArgs),
+    Detect(DetectArgs),
+    Planes(PlanesArgs),
+    Carve(CarveArgs),
+    Polyglot(PolyglotArgs),
+    Capacity(CapacityArgs),
+    Validate(ValidateArgs),
+    Touch(TouchArgs),
+    Dpi(DpiArgs),
+    Icc(IccArgs),
+    Exif(ExifArgs),
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EmbedMode {
+    /// Store the message in a chunk of the given type
+    #[default]
+    Chunk,
+    /// Hide the message in the least significant bits of the pixels, labelled with the chunk type
+    Lsb,
+    /// Hide the message in the order of the palette entries of an indexed image
+    PaletteOrder,
+    /// Hide the message in the choice between near-duplicate palette colors
+    PaletteParity,
+    /// Hide the message after the end of the zlib stream in the last IDAT chunk
+    Zlib,
 }
 
 #[derive(ClapArgs, Debug)]
@@ -22,12 +53,50 @@ pub struct EncodeArgs {
     pub chunk_type: String,
     pub message: String,
     pub output_file: Option<PathBuf>,
+    #[arg(long, value_enum, default_value_t)]
+    pub mode: EmbedMode,
+    /// Split the message over every frame of an animated PNG
+    #[arg(long)]
+    pub spread: bool,
+    /// Keep unknown unsafe-to-copy chunks even though the image data changes
+    #[arg(long)]
+    pub keep_unsafe: bool,
+    /// Protect the message with this many Reed-Solomon check bytes per codeword
+    #[arg(long)]
+    pub redundancy: Option<u8>,
+    /// Spread the message over at least this many interleaved codewords
+    #[arg(long, default_value_t = 1, requires = "redundancy")]
+    pub interleave: u8,
+    /// Scatter LSB payload bits in an order derived from this passphrase
+    #[arg(long)]
+    pub passphrase: Option<String>,
+    /// Hide this many bits in every group of 2^K - 1 samples with a Hamming code, changing
+    /// at most one sample per group
+    #[arg(long, value_name = "K", value_parser = clap::value_parser!(u8).range(1..=8))]
+    pub matrix: Option<u8>,
+    /// Add or subtract one at random instead of overwriting the least significant bit
+    #[arg(long)]
+    pub matching: bool,
+    /// Only embed in busy pixels whose texture is at least this, skipping flat regions
+    #[arg(long, value_name = "THRESHOLD", num_args = 0..=1, default_missing_value = "24")]
+    pub adaptive: Option<u32>,
+    /// Set the modification time in the tIME chunk to now
+    #[arg(long)]
+    pub touch: bool,
 }
 
 #[derive(ClapArgs, Debug)]
 pub struct DecodeArgs {
     pub file_path: PathBuf,
     pub chunk_type: String,
+    #[arg(long, value_enum, default_value_t)]
+    pub mode: EmbedMode,
+    /// Passphrase the LSB payload was scattered with
+    #[arg(long)]
+    pub passphrase: Option<String>,
+    /// Texture threshold the LSB payload was embedded with
+    #[arg(long, value_name = "THRESHOLD", num_args = 0..=1, default_missing_value = "24")]
+    pub adaptive: Option<u32>,
 }
 
 #[derive(ClapArgs, Debug)]
@@ -39,4 +108,226 @@ pub struct RemoveArgs {
 #[derive(ClapArgs, Debug)]
 pub struct PrintArgs {
     pub file_path: PathBuf,
+    /// Print the chunks and typed metadata as JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct ExplodeArgs {
+    pub file_path: PathBuf,
+    pub output_dir: PathBuf,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct ImplodeArgs {
+    pub input_dir: PathBuf,
+    pub output_file: PathBuf,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct DiffArgs {
+    pub old_file: PathBuf,
+    pub new_file: PathBuf,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum StripMode {
+    /// Remove every ancillary chunk
+    All,
+    /// Remove ancillary chunks that are not safe to copy
+    Unsafe,
+    /// Remove private ancillary chunks
+    Private,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct StripArgs {
+    pub file_path: PathBuf,
+    pub output_file: Option<PathBuf>,
+    /// Which ancillary chunks to remove, all of them if neither this nor a type list is given
+    #[arg(long, value_enum, conflicts_with_all = ["keep", "drop"])]
+    pub policy: Option<StripMode>,
+    /// Remove every ancillary chunk except these types (comma separated)
+    #[arg(long, value_delimiter = ',', conflicts_with = "drop")]
+    pub keep: Vec<String>,
+    /// Remove only these ancillary chunk types (comma separated)
+    #[arg(long, value_delimiter = ',')]
+    pub drop: Vec<String>,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct FramesArgs {
+    pub file_path: PathBuf,
+    /// Write every composited frame as a PNG into this directory
+    #[arg(long)]
+    pub render: Option<PathBuf>,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct AnimateArgs {
+    #[arg(required = true)]
+    pub frames: Vec<PathBuf>,
+    #[arg(short, long)]
+    pub output_file: PathBuf,
+    /// Frame delays in milliseconds, either one for all frames or one per frame (comma separated)
+    #[arg(long, value_delimiter = ',')]
+    pub delay: Vec<u16>,
+    /// Number of times to play the animation, 0 loops forever
+    #[arg(long, default_value_t = 0)]
+    pub loops: u32,
+    /// Image shown by viewers without APNG support, not part of the animation
+    #[arg(long)]
+    pub default_image: Option<PathBuf>,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct DetectArgs {
+    #[arg(required = true)]
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum StreamOrder {
+    /// Left to right, top to bottom
+    Row,
+    /// Top to bottom, left to right
+    Column,
+}
+
+#[derive(ClapArgs, Debug)]
+#[command(group = clap::ArgGroup::new("output").required(true).multiple(true))]
+pub struct PlanesArgs {
+    pub file_path: PathBuf,
+    /// Write every selected bit plane as a black and white PNG into this directory
+    #[arg(group = "output")]
+    pub output_dir: Option<PathBuf>,
+    /// Channels to extract, by name or first letter (comma separated, default all)
+    #[arg(long, value_delimiter = ',')]
+    pub channels: Vec<String>,
+    /// Bits to extract, 0 being the least significant (comma separated). Planes default
+    /// to every bit and the stream to bit 0.
+    #[arg(long, value_delimiter = ',')]
+    pub bits: Vec<u8>,
+    /// Dump the selected bits of the selected channels into this file
+    #[arg(long, group = "output")]
+    pub stream: Option<PathBuf>,
+    #[arg(long, value_enum, default_value = "row")]
+    pub order: StreamOrder,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct CarveArgs {
+    /// Any file that may contain PNGs, e.g. a disk image or memory dump
+    pub file_path: PathBuf,
+    pub output_dir: PathBuf,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum ZipPlacementMode {
+    /// Append the archive after the IEND chunk
+    Trailing,
+    /// Store the archive in an ancillary chunk before IEND
+    Chunk,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct PolyglotArgs {
+    pub file_path: PathBuf,
+    /// The ZIP archive to combine with the image
+    pub archive: PathBuf,
+    pub output_file: PathBuf,
+    #[arg(long, value_enum, default_value = "trailing")]
+    pub placement: ZipPlacementMode,
+    /// Chunk type holding the archive when placed in a chunk
+    #[arg(long, default_value = "arCh")]
+    pub chunk_type: String,
+    /// Parse the result both as a PNG and as a ZIP archive
+    #[arg(long)]
+    pub verify: bool,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct CapacityArgs {
+    pub file_path: PathBuf,
+    /// Account for this many Reed-Solomon check bytes per codeword
+    #[arg(long)]
+    pub redundancy: Option<u8>,
+    /// Account for at least this many interleaved codewords
+    #[arg(long, default_value_t = 1, requires = "redundancy")]
+    pub interleave: u8,
+    /// Account for matrix embedding of K bits in every group of 2^K - 1 samples
+    #[arg(long, value_name = "K", value_parser = clap::value_parser!(u8).range(1..=8))]
+    pub matrix: Option<u8>,
+    /// Only count busy pixels whose texture is at least this, as adaptive embedding does
+    #[arg(long, value_name = "THRESHOLD", num_args = 0..=1, default_missing_value = "24")]
+    pub adaptive: Option<u32>,
+    /// Account for splitting the message over every frame of an animated PNG
+    #[arg(long)]
+    pub spread: bool,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct ValidateArgs {
+    pub file_path: PathBuf,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct TouchArgs {
+    pub file_path: PathBuf,
+    pub output_file: Option<PathBuf>,
+    /// Modification time to set as YYYY-MM-DDTHH:MM:SS in UTC instead of now
+    #[arg(long, conflicts_with = "remove")]
+    pub time: Option<String>,
+    /// Remove the tIME chunk
+    #[arg(long)]
+    pub remove: bool,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct DpiArgs {
+    pub file_path: PathBuf,
+    pub output_file: Option<PathBuf>,
+    /// Set the pixel density, used for both axes unless --vertical is given
+    #[arg(long, value_name = "DPI", conflicts_with = "remove")]
+    pub set: Option<f64>,
+    /// Set a different vertical pixel density
+    #[arg(long, value_name = "DPI", requires = "set")]
+    pub vertical: Option<f64>,
+    /// Read and write densities in dots per centimeter instead of per inch
+    #[arg(long)]
+    pub dpcm: bool,
+    /// Remove the pHYs chunk
+    #[arg(long)]
+    pub remove: bool,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct IccArgs {
+    pub file_path: PathBuf,
+    pub output_file: Option<PathBuf>,
+    /// Embed this ICC profile in an iCCP chunk, replacing any sRGB chunk
+    #[arg(long, value_name = "PROFILE", conflicts_with_all = ["strip", "extract"])]
+    pub attach: Option<PathBuf>,
+    /// Name to store the attached profile under instead of its file name
+    #[arg(long, requires = "attach")]
+    pub name: Option<String>,
+    /// Remove the iCCP chunk
+    #[arg(long, conflicts_with = "extract")]
+    pub strip: bool,
+    /// Write the embedded ICC profile to this file
+    #[arg(long, value_name = "PROFILE")]
+    pub extract: Option<PathBuf>,
+}
+
+#[derive(ClapArgs, Debug)]
+pub struct ExifArgs {
+    pub file_path: PathBuf,
+    pub output_file: Option<PathBuf>,
+    /// Remove the GPS tags and keep everything else
+    #[arg(long, conflicts_with = "remove")]
+    pub remove_gps: bool,
+    /// Remove the whole eXIf chunk
+    #[arg(long)]
+    pub remove: bool,
 }
@@ -14,7 +14,7 @@ enum Message {
 }
 
 impl Counter {
-    fn view(&self) -> Column<Message> {
+    fn view(&self) -> Column<'_, Message> {
         column![
             button("+").on_press(Message::Increment),
             text(self.value),
@@ -0,0 +1,367 @@
+use crate::Result;
+use crate::apng::Animation;
+use crate::chunk::MAX_CHUNK_LENGTH;
+use crate::fec::FecOptions;
+use crate::palette::{Palette, Transparency};
+use crate::pixels::{ColorType, ImageHeader, PixelData};
+use crate::png::Png;
+use crate::stego::{self, EmbedOptions};
+
+use std::fmt;
+
+/// Shortest text chunk overhead: a one byte keyword and its null separator
+const TEXT_CHUNK_OVERHEAD: usize = 2;
+
+/// How much a single embedding mode can hold
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Capacity {
+    Bytes(usize),
+    Unlimited,
+    /// The mode doesn't work on this image, with the reason why
+    Unavailable(String),
+}
+
+impl fmt::Display for Capacity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Capacity::Bytes(bytes) => write!(f, "{} bytes", bytes),
+            Capacity::Unlimited => write!(f, "unlimited"),
+            Capacity::Unavailable(reason) => write!(f, "n/a ({})", reason),
+        }
+    }
+}
+
+/// Maximum message size of every embedding mode for one image, after payload headers and
+/// error correction. The LSB estimate also accounts for matrix embedding and adaptive
+/// thresholds.
+#[derive(Debug)]
+pub struct CapacityReport {
+    pub header: ImageHeader,
+    pub options: EmbedOptions,
+    /// Whether the message is split over the frames of an animation
+    pub spread: bool,
+    pub modes: Vec<(String, Capacity)>,
+}
+
+impl CapacityReport {
+    pub fn new(png: &Png, options: EmbedOptions, spread: bool) -> Result<Self> {
+        let header = ImageHeader::from_png(png)?;
+        let fec = options.fec;
+        let mut modes = Vec::new();
+
+        // plain chunks have no payload header, protected and spread ones are framed
+        let chunk = match (&fec, spread) {
+            (_, true) => Animation::from_png(png).map(|animation| {
+                animation.frames().count() * stego::message_capacity(MAX_CHUNK_LENGTH, fec.as_ref())
+            }),
+            (Some(fec), false) => Ok(stego::message_capacity(MAX_CHUNK_LENGTH, Some(fec))),
+            (None, false) => Ok(MAX_CHUNK_LENGTH),
+        };
+        modes.push(("Custom chunk".to_string(), chunk.into()));
+        modes.push((
+            "Text chunk".to_string(),
+            Capacity::Bytes(MAX_CHUNK_LENGTH - TEXT_CHUNK_OVERHEAD),
+        ));
+        modes.push(("Trailing data".to_string(), Capacity::Unlimited));
+
+        let lsb = if spread {
+            stego::frames_lsb_capacity(png, &options)
+        } else {
+            PixelData::decode(png).and_then(|pixels| stego::lsb_capacity(&pixels, &options))
+        };
+        modes.push(("LSB".to_string(), lsb.into()));
+
+        let single = |capacity: Capacity| {
+            if spread {
+                Capacity::Unavailable("can't be spread over frames".to_string())
+            } else {
+                capacity
+            }
+        };
+        modes.push((
+            "Palette order".to_string(),
+            single(palette_order_capacity(png, &header, fec.as_ref())),
+        ));
+        modes.push((
+            "Palette parity".to_string(),
+            single(palette_parity_capacity(png, &header, fec.as_ref())),
+        ));
+        modes.push((
+            "Zlib stream".to_string(),
+            single(zlib_capacity(png, fec.as_ref())),
+        ));
+
+        Ok(CapacityReport {
+            header,
+            options,
+            spread,
+            modes,
+        })
+    }
+}
+
+impl fmt::Display for CapacityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Image: {}", self.header)?;
+        if let Some(fec) = &self.options.fec {
+            writeln!(
+                f,
+                "Error correction: {} check bytes per codeword, interleave {}",
+                fec.redundancy, fec.interleave
+            )?;
+        }
+        if self.options.matrix_bits > 1 {
+            writeln!(
+                f,
+                "Matrix embedding: {} bits per {} samples",
+                self.options.matrix_bits,
+                (1u32 << self.options.matrix_bits) - 1
+            )?;
+        }
+        if let Some(threshold) = self.options.adaptive {
+            writeln!(f, "Adaptive threshold: {}", threshold)?;
+        }
+        if self.spread {
+            writeln!(f, "Spread over every animation frame")?;
+        }
+
+        let width = self.modes.iter().map(|(mode, _)| mode.len()).max();
+        for (mode, capacity) in &self.modes {
+            writeln!(
+                f,
+                "  {:width$}  {}",
+                mode,
+                capacity,
+                width = width.unwrap_or(0)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl From<Result<usize>> for Capacity {
+    fn from(bytes: Result<usize>) -> Self {
+        match bytes {
+            Ok(bytes) => Capacity::Bytes(bytes),
+            Err(e) => Capacity::Unavailable(e.to_string()),
+        }
+    }
+}
+
+/// Capacity of the palette order, which needs every entry to be distinct
+fn palette_order_capacity(png: &Png, header: &ImageHeader, fec: Option<&FecOptions>) -> Capacity {
+    if header.color_type != ColorType::Indexed {
+        return Capacity::Unavailable("not an indexed image".to_string());
+    }
+    let Ok(palette) = Palette::from_png(png) else {
+        return Capacity::Unavailable("no valid palette".to_string());
+    };
+
+    let transparency = Transparency::from_png(png, ColorType::Indexed)
+        .ok()
+        .flatten();
+    let mut colors: Vec<_> = palette
+        .entries()
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            (
+                *entry,
+                transparency.as_ref().map_or(255, |t| t.alpha(index)),
+            )
+        })
+        .collect();
+    colors.sort();
+    colors.dedup();
+    if colors.len() < palette.len() {
+        return Capacity::Unavailable("palette has duplicate colors".to_string());
+    }
+
+    Capacity::Bytes(stego::palette_order_capacity(palette.len(), fec))
+}
+
+/// Capacity of the palette parity mode, including the near-duplicates it can add
+fn palette_parity_capacity(png: &Png, header: &ImageHeader, fec: Option<&FecOptions>) -> Capacity {
+    if header.color_type != ColorType::Indexed {
+        return Capacity::Unavailable("not an indexed image".to_string());
+    }
+    stego::palette_parity_capacity(png, fec).into()
+}
+
+/// Capacity after the zlib stream, which has to fit into the last `IDAT` chunk
+fn zlib_capacity(png: &Png, fec: Option<&FecOptions>) -> Capacity {
+    let image_data = png.image_data();
+    match PixelData::stream_len(&image_data) {
+        Ok(len) if len < image_data.len() => {
+            return Capacity::Unavailable("data already follows the zlib stream".to_string());
+        }
+        Ok(_) => {}
+        Err(e) => return Capacity::Unavailable(e.to_string()),
+    }
+
+    let last_len = png
+        .chunks()
+        .iter()
+        .rev()
+        .find(|chunk| chunk.chunk_type().bytes() == *b"IDAT")
+        .map_or(0, |chunk| chunk.data().len());
+    Capacity::Bytes(stego::message_capacity(MAX_CHUNK_LENGTH - last_len, fec))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apng::{AssembleOptions, assemble};
+    use crate::chunk_types::ChunkType;
+    use crate::palette::PaletteEntry;
+    use crate::png::UnsafeChunkPolicy;
+    use crate::png::tests::PNG_FILE;
+
+    fn capacity_of<'a>(report: &'a CapacityReport, mode: &str) -> &'a Capacity {
+        &report
+            .modes
+            .iter()
+            .find(|(name, _)| name == mode)
+            .unwrap()
+            .1
+    }
+
+    #[test]
+    fn test_rgba_capacity() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let report = CapacityReport::new(&png, EmbedOptions::default(), false).unwrap();
+
+        // 50x50 pixels with 3 color channels, the alpha channel isn't used
+        assert_eq!(
+            capacity_of(&report, "LSB"),
+            &Capacity::Bytes(50 * 50 * 3 / 8 - stego::HEADER_LEN)
+        );
+        assert_eq!(
+            capacity_of(&report, "Custom chunk"),
+            &Capacity::Bytes(MAX_CHUNK_LENGTH)
+        );
+        for mode in ["Palette order", "Palette parity"] {
+            assert!(matches!(
+                capacity_of(&report, mode),
+                Capacity::Unavailable(_)
+            ));
+        }
+
+        let last_idat = png
+            .chunks()
+            .iter()
+            .rev()
+            .find(|chunk| chunk.chunk_type().bytes() == *b"IDAT")
+            .unwrap();
+        assert_eq!(
+            capacity_of(&report, "Zlib stream"),
+            &Capacity::Bytes(MAX_CHUNK_LENGTH - last_idat.data().len() - stego::HEADER_LEN)
+        );
+    }
+
+    #[test]
+    fn test_error_correction_lowers_capacity() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let plain = CapacityReport::new(&png, EmbedOptions::default(), false).unwrap();
+        let options = EmbedOptions {
+            fec: Some(FecOptions::new(16, 1).unwrap()),
+            ..Default::default()
+        };
+        let protected = CapacityReport::new(&png, options, false).unwrap();
+
+        let (Capacity::Bytes(plain), Capacity::Bytes(protected)) =
+            (capacity_of(&plain, "LSB"), capacity_of(&protected, "LSB"))
+        else {
+            panic!("LSB capacity missing");
+        };
+        assert!(protected < plain);
+    }
+
+    #[test]
+    fn test_matrix_and_adaptive_options() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let pixels = PixelData::decode(&png).unwrap();
+        for options in [
+            EmbedOptions {
+                matrix_bits: 3,
+                ..Default::default()
+            },
+            EmbedOptions {
+                adaptive: Some(24),
+                ..Default::default()
+            },
+        ] {
+            let report = CapacityReport::new(&png, options.clone(), false).unwrap();
+            assert_eq!(
+                capacity_of(&report, "LSB"),
+                &Capacity::Bytes(stego::lsb_capacity(&pixels, &options).unwrap())
+            );
+        }
+    }
+
+    #[test]
+    fn test_spread_over_frames() {
+        let still = Png::try_from(&PNG_FILE[..]).unwrap();
+        let report = CapacityReport::new(&still, EmbedOptions::default(), true).unwrap();
+        assert!(matches!(
+            capacity_of(&report, "Custom chunk"),
+            Capacity::Unavailable(_)
+        ));
+
+        let frames = [
+            Png::try_from(&PNG_FILE[..]).unwrap(),
+            Png::try_from(&PNG_FILE[..]).unwrap(),
+        ];
+        let animation = assemble(&frames, None, &AssembleOptions::default()).unwrap();
+        let report = CapacityReport::new(&animation, EmbedOptions::default(), true).unwrap();
+        assert_eq!(
+            capacity_of(&report, "Custom chunk"),
+            &Capacity::Bytes(2 * (MAX_CHUNK_LENGTH - stego::HEADER_LEN))
+        );
+        assert_eq!(
+            capacity_of(&report, "LSB"),
+            &Capacity::Bytes(2 * (50 * 50 * 3 / 8 - stego::HEADER_LEN))
+        );
+        assert!(matches!(
+            capacity_of(&report, "Zlib stream"),
+            Capacity::Unavailable(_)
+        ));
+    }
+
+    #[test]
+    fn test_palette_parity_matches_embedding() {
+        let header = ImageHeader {
+            width: 32,
+            height: 32,
+            bit_depth: 8,
+            color_type: ColorType::Indexed,
+            interlaced: false,
+        };
+        let samples = (0..32 * 32).map(|i| (i % 4) as u16).collect();
+        let mut png = Png::from_pixels(&PixelData::new(header, samples).unwrap()).unwrap();
+        let palette = Palette::new(
+            (0..4)
+                .map(|i| PaletteEntry {
+                    red: i * 60,
+                    green: i * 60,
+                    blue: i * 60,
+                })
+                .collect(),
+        )
+        .unwrap();
+        png.replace_critical_chunks(vec![palette.to_chunk()], UnsafeChunkPolicy::Drop)
+            .unwrap();
+
+        let report = CapacityReport::new(&png, EmbedOptions::default(), false).unwrap();
+        let &Capacity::Bytes(capacity) = capacity_of(&report, "Palette parity") else {
+            panic!("palette parity capacity missing");
+        };
+        let label = ChunkType::new(b"ruSt");
+        let embed = |len: usize| {
+            let mut png = Png::from_chunks(png.chunks().to_vec());
+            stego::embed_in_palette_parity(&mut png, &label, &vec![b'x'; len], &Default::default())
+        };
+        assert!(embed(capacity).is_ok());
+        assert!(embed(capacity + 1).is_err());
+    }
+}
@@ -0,0 +1,173 @@
+use crate::chunk::{Chunk, MAX_CHUNK_LENGTH};
+use crate::chunk_types::ChunkType;
+use crate::png::Png;
+
+use std::fmt;
+
+/// A PNG found inside a larger blob
+#[derive(Debug)]
+pub struct CarvedPng {
+    /// Position of the PNG signature in the blob
+    pub offset: usize,
+    /// Number of bytes of the blob that belong to this PNG
+    pub length: usize,
+    /// False if the chunks stopped before `IEND`, in which case one was added
+    pub complete: bool,
+    pub png: Png,
+}
+
+impl fmt::Display for CarvedPng {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "0x{:08x}: {} bytes, {} chunks{}",
+            self.offset,
+            self.length,
+            self.png.chunks().len(),
+            if self.complete {
+                ""
+            } else {
+                " (truncated, IEND added)"
+            }
+        )
+    }
+}
+
+/// Scans `data` for the PNG signature and walks the chunks after each one, checking every
+/// CRC. PNGs that end with `IEND` are complete. PNGs that stop early are kept if they still
+/// have an `IHDR` and some image data, since most viewers can show the decoded part.
+pub fn carve(data: &[u8]) -> Vec<CarvedPng> {
+    let mut carved = Vec::new();
+    let mut position = 0;
+
+    while let Some(found) = find_signature(&data[position..]) {
+        let offset = position + found;
+        match walk_chunks(data, offset) {
+            Some(png) => {
+                position = offset + png.length;
+                carved.push(png);
+            }
+            None => position = offset + Png::STANDARD_HEADER.len(),
+        }
+    }
+
+    carved
+}
+
+fn find_signature(data: &[u8]) -> Option<usize> {
+    data.windows(Png::STANDARD_HEADER.len())
+        .position(|window| window == Png::STANDARD_HEADER)
+}
+
+/// Reads chunks starting at the signature at `offset` until `IEND` or the first chunk
+/// that is cut off, has an invalid type or a wrong CRC
+fn walk_chunks(data: &[u8], offset: usize) -> Option<CarvedPng> {
+    let mut position = offset + Png::STANDARD_HEADER.len();
+    let mut chunks: Vec<Chunk> = Vec::new();
+    let mut complete = false;
+
+    while position + 12 <= data.len() {
+        let length = u32::from_be_bytes(data[position..position + 4].try_into().ok()?) as usize;
+        let end = position + 12 + length;
+        if length > MAX_CHUNK_LENGTH || end > data.len() {
+            break;
+        }
+
+        let type_bytes: [u8; 4] = data[position + 4..position + 8].try_into().ok()?;
+        if !type_bytes.iter().all(u8::is_ascii_alphabetic) {
+            break;
+        }
+        let Ok(chunk) = Chunk::try_from(&data[position..end]) else {
+            break;
+        };
+
+        // the first chunk has to be IHDR, otherwise this was a stray signature
+        if chunks.is_empty() && &type_bytes != b"IHDR" {
+            return None;
+        }
+
+        chunks.push(chunk);
+        position = end;
+        if &type_bytes == b"IEND" {
+            complete = true;
+            break;
+        }
+    }
+
+    let has_image_data = chunks
+        .iter()
+        .any(|chunk| &chunk.chunk_type().bytes() == b"IDAT");
+    if chunks.is_empty() || !has_image_data {
+        return None;
+    }
+
+    if !complete {
+        chunks.push(Chunk::new(ChunkType::new(b"IEND"), Vec::new()));
+    }
+
+    Some(CarvedPng {
+        offset,
+        length: position - offset,
+        complete,
+        png: Png::from_chunks(chunks),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::tests::PNG_FILE;
+
+    #[test]
+    fn test_carve_embedded_pngs() {
+        let mut blob = b"some header bytes".to_vec();
+        blob.extend_from_slice(&PNG_FILE);
+        blob.extend_from_slice(&[0; 100]);
+        blob.extend_from_slice(&PNG_FILE);
+
+        let carved = carve(&blob);
+        assert_eq!(carved.len(), 2);
+        assert_eq!(carved[0].offset, 17);
+        assert_eq!(carved[0].length, PNG_FILE.len());
+        assert!(carved[0].complete);
+        assert_eq!(carved[0].png.as_bytes(), PNG_FILE.to_vec());
+        assert_eq!(carved[1].offset, 17 + PNG_FILE.len() + 100);
+    }
+
+    #[test]
+    fn test_carve_truncated_png() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let iend = png.chunks().last().unwrap().as_bytes().len();
+        let mut blob = PNG_FILE[..PNG_FILE.len() - iend - 10].to_vec();
+        blob.extend_from_slice(b"garbage");
+
+        let carved = carve(&blob);
+        assert_eq!(carved.len(), 1);
+        assert!(!carved[0].complete);
+        assert_eq!(
+            carved[0]
+                .png
+                .chunks()
+                .last()
+                .unwrap()
+                .chunk_type()
+                .to_string(),
+            "IEND"
+        );
+    }
+
+    #[test]
+    fn test_carve_bad_crc() {
+        let mut blob = PNG_FILE.to_vec();
+        // corrupt the CRC of IHDR
+        blob[8 + 8 + 13] ^= 0xff;
+        assert!(carve(&blob).is_empty());
+    }
+
+    #[test]
+    fn test_carve_stray_signature() {
+        let mut blob = Png::STANDARD_HEADER.to_vec();
+        blob.extend_from_slice(b"not a png at all");
+        assert!(carve(&blob).is_empty());
+    }
+}
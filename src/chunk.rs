@@ -5,6 +5,9 @@ use crc::{CRC_32_ISO_HDLC, Crc};
 
 use crate::chunk_types::ChunkType;
 
+/// Largest chunk data length allowed by the PNG spec
+pub const MAX_CHUNK_LENGTH: usize = (1 << 31) - 1;
+
 /// A validated PNG chunk. See the PNG Spec for more details
 /// http://www.libpng.org/pub/png/spec/1.2/PNG-Structure.html
 #[derive(Debug, Clone)]
@@ -26,10 +29,10 @@ impl Chunk {
         let crc = crc_calc.checksum(&to_check);
 
         Chunk {
-            length: length,
+            length,
             chunk_type: c_type,
             chunk_data: data,
-            crc: crc,
+            crc,
         }
     }
 
@@ -103,7 +106,7 @@ impl TryFrom<&[u8]> for Chunk {
         let crc_calc = Crc::<u32>::new(&CRC_32_ISO_HDLC);
         let mut to_check = Vec::new();
         to_check.extend_from_slice(&chunk_type.bytes());
-        to_check.extend_from_slice(&chunk_data_bytes);
+        to_check.extend_from_slice(chunk_data_bytes);
 
         let crc_check = crc_calc.checksum(&to_check);
 
@@ -113,9 +116,9 @@ impl TryFrom<&[u8]> for Chunk {
 
         Ok(Chunk {
             length: len,
-            chunk_type: chunk_type,
+            chunk_type,
             chunk_data: chunk_data_bytes.to_vec(),
-            crc: crc,
+            crc,
         })
     }
 }
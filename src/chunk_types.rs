@@ -8,6 +8,15 @@ use std::{fmt::Display, str::FromStr};
 pub struct ChunkType {
     chunk_bytes: [u8; 4],
 }
+
+/// Chunk types defined by the PNG spec and its APNG extension. Chunks of any other type that
+/// are unsafe to copy get dropped when critical chunks change.
+pub const KNOWN_CHUNK_TYPES: [&[u8; 4]; 25] = [
+    b"IHDR", b"PLTE", b"IDAT", b"IEND", b"cHRM", b"gAMA", b"iCCP", b"sBIT", b"sRGB", b"cICP",
+    b"mDCV", b"cLLI", b"bKGD", b"hIST", b"tRNS", b"eXIf", b"pHYs", b"sPLT", b"tIME", b"iTXt",
+    b"tEXt", b"zTXt", b"acTL", b"fcTL", b"fdAT",
+];
+
 impl ChunkType {
     pub fn new(chunk_bytes: &[u8; 4]) -> ChunkType {
         ChunkType {
@@ -42,6 +51,11 @@ impl ChunkType {
     pub fn is_valid(&self) -> bool {
         self.is_reserved_bit_valid() && self.is_valid_byte()
     }
+    /// Returns true if this is one of the `KNOWN_CHUNK_TYPES`
+    pub fn is_known(&self) -> bool {
+        KNOWN_CHUNK_TYPES.contains(&&self.chunk_bytes)
+    }
+
     /// Valid bytes are represented by the characters A-Z or a-z
     pub fn is_valid_byte(&self) -> bool {
         if !self.chunk_bytes.iter().all(|b| b.is_ascii()) {
@@ -173,6 +187,12 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    pub fn test_chunk_type_is_known() {
+        assert!(ChunkType::from_str("tRNS").unwrap().is_known());
+        assert!(!ChunkType::from_str("RuSt").unwrap().is_known());
+    }
+
     #[test]
     pub fn test_chunk_type_string() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
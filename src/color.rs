@@ -0,0 +1,523 @@
+use crate::chunk::Chunk;
+use crate::chunk_types::ChunkType;
+use crate::png::Png;
+use crate::{Error, Result};
+
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use std::fmt;
+use std::io::{Read, Write};
+
+/// Value of `gAMA` that matches the sRGB transfer function, as required next to `sRGB`
+pub const SRGB_GAMMA: u32 = 45455;
+
+/// `cHRM` values that match sRGB, as required next to `sRGB`
+pub const SRGB_CHROMATICITIES: Chromaticities = Chromaticities {
+    white: (31270, 32900),
+    red: (64000, 33000),
+    green: (30000, 60000),
+    blue: (15000, 6000),
+};
+
+/// Longest keyword allowed as an ICC profile name
+const MAX_PROFILE_NAME_LEN: usize = 79;
+
+/// Bytes an ICC profile header takes up, the smallest possible profile
+const ICC_HEADER_LEN: usize = 128;
+
+/// The contents of a `gAMA` chunk: image gamma times 100000
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gamma(pub u32);
+
+impl Gamma {
+    pub fn value(&self) -> f64 {
+        self.0 as f64 / 100_000.0
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk::new(ChunkType::new(b"gAMA"), self.0.to_be_bytes().to_vec())
+    }
+}
+
+impl TryFrom<&[u8]> for Gamma {
+    type Error = Error;
+
+    fn try_from(data: &[u8]) -> Result<Self> {
+        let bytes: [u8; 4] = data
+            .try_into()
+            .map_err(|_| format!("gAMA must be 4 bytes long, not {}", data.len()))?;
+        match u32::from_be_bytes(bytes) {
+            0 => Err("gAMA can't be zero".into()),
+            gamma => Ok(Gamma(gamma)),
+        }
+    }
+}
+
+/// The contents of a `cHRM` chunk: CIE x and y of the white point and primaries, times 100000
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chromaticities {
+    pub white: (u32, u32),
+    pub red: (u32, u32),
+    pub green: (u32, u32),
+    pub blue: (u32, u32),
+}
+
+impl Chromaticities {
+    pub fn to_chunk(&self) -> Chunk {
+        let data = [self.white, self.red, self.green, self.blue]
+            .iter()
+            .flat_map(|(x, y)| [x.to_be_bytes(), y.to_be_bytes()])
+            .flatten()
+            .collect();
+        Chunk::new(ChunkType::new(b"cHRM"), data)
+    }
+}
+
+impl TryFrom<&[u8]> for Chromaticities {
+    type Error = Error;
+
+    fn try_from(data: &[u8]) -> Result<Self> {
+        if data.len() != 32 {
+            return Err(format!("cHRM must be 32 bytes long, not {}", data.len()).into());
+        }
+        let value =
+            |index: usize| u32::from_be_bytes(data[index * 4..index * 4 + 4].try_into().unwrap());
+
+        Ok(Chromaticities {
+            white: (value(0), value(1)),
+            red: (value(2), value(3)),
+            green: (value(4), value(5)),
+            blue: (value(6), value(7)),
+        })
+    }
+}
+
+/// The rendering intent stored in an `sRGB` chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderingIntent {
+    Perceptual,
+    RelativeColorimetric,
+    Saturation,
+    AbsoluteColorimetric,
+}
+
+impl RenderingIntent {
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk::new(ChunkType::new(b"sRGB"), vec![*self as u8])
+    }
+}
+
+impl TryFrom<&[u8]> for RenderingIntent {
+    type Error = Error;
+
+    fn try_from(data: &[u8]) -> Result<Self> {
+        match data {
+            [0] => Ok(RenderingIntent::Perceptual),
+            [1] => Ok(RenderingIntent::RelativeColorimetric),
+            [2] => Ok(RenderingIntent::Saturation),
+            [3] => Ok(RenderingIntent::AbsoluteColorimetric),
+            [other] => Err(format!("Invalid sRGB rendering intent {}", other).into()),
+            _ => Err(format!("sRGB must be 1 byte long, not {}", data.len()).into()),
+        }
+    }
+}
+
+impl fmt::Display for RenderingIntent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            RenderingIntent::Perceptual => "perceptual",
+            RenderingIntent::RelativeColorimetric => "relative colorimetric",
+            RenderingIntent::Saturation => "saturation",
+            RenderingIntent::AbsoluteColorimetric => "absolute colorimetric",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The contents of an `iCCP` chunk: a named, decompressed ICC profile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IccProfile {
+    pub name: String,
+    pub profile: Vec<u8>,
+}
+
+impl IccProfile {
+    /// Creates a profile, failing if the name isn't a valid keyword or `profile` doesn't
+    /// start with an ICC header
+    pub fn new(name: &str, profile: Vec<u8>) -> Result<Self> {
+        let valid_name = !name.is_empty()
+            && name.len() <= MAX_PROFILE_NAME_LEN
+            && name.chars().all(|c| matches!(c, ' '..='~'))
+            && !name.starts_with(' ')
+            && !name.ends_with(' ')
+            && !name.contains("  ");
+        if !valid_name {
+            return Err(format!("Invalid ICC profile name {:?}", name).into());
+        }
+        if profile.len() < ICC_HEADER_LEN || &profile[36..40] != b"acsp" {
+            return Err("Not an ICC profile".into());
+        }
+
+        Ok(IccProfile {
+            name: name.to_string(),
+            profile,
+        })
+    }
+
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        let mut data = self.name.as_bytes().to_vec();
+        data.extend([0, 0]);
+        let mut encoder = ZlibEncoder::new(data, Compression::default());
+        encoder.write_all(&self.profile)?;
+        Ok(Chunk::new(ChunkType::new(b"iCCP"), encoder.finish()?))
+    }
+
+    /// Color space signature from the profile header, such as `RGB` or `GRAY`
+    pub fn color_space(&self) -> String {
+        String::from_utf8_lossy(&self.profile[16..20])
+            .trim_end()
+            .to_string()
+    }
+}
+
+impl TryFrom<&[u8]> for IccProfile {
+    type Error = Error;
+
+    fn try_from(data: &[u8]) -> Result<Self> {
+        let separator = data
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or("iCCP has no profile name separator")?;
+        let name = String::from_utf8_lossy(&data[..separator]);
+        match data.get(separator + 1) {
+            Some(0) => {}
+            Some(method) => return Err(format!("Unknown iCCP compression {}", method).into()),
+            None => return Err("iCCP has no compression method".into()),
+        }
+
+        let mut profile = Vec::new();
+        ZlibDecoder::new(&data[separator + 2..]).read_to_end(&mut profile)?;
+        IccProfile::new(&name, profile)
+    }
+}
+
+/// The contents of a `cICP` chunk: coding-independent code points from ITU-T H.273
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodingPoints {
+    pub color_primaries: u8,
+    pub transfer_function: u8,
+    pub matrix_coefficients: u8,
+    pub full_range: bool,
+}
+
+impl CodingPoints {
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk::new(
+            ChunkType::new(b"cICP"),
+            vec![
+                self.color_primaries,
+                self.transfer_function,
+                self.matrix_coefficients,
+                self.full_range as u8,
+            ],
+        )
+    }
+}
+
+impl TryFrom<&[u8]> for CodingPoints {
+    type Error = Error;
+
+    fn try_from(data: &[u8]) -> Result<Self> {
+        let [
+            color_primaries,
+            transfer_function,
+            matrix_coefficients,
+            range,
+        ] = *data
+        else {
+            return Err(format!("cICP must be 4 bytes long, not {}", data.len()).into());
+        };
+        // PNG only stores RGB, so no YCbCr matrix may be given
+        if matrix_coefficients != 0 {
+            return Err(format!(
+                "cICP matrix coefficients must be 0, not {}",
+                matrix_coefficients
+            )
+            .into());
+        }
+        if range > 1 {
+            return Err(format!("Invalid cICP full range flag {}", range).into());
+        }
+
+        Ok(CodingPoints {
+            color_primaries,
+            transfer_function,
+            matrix_coefficients,
+            full_range: range == 1,
+        })
+    }
+}
+
+impl fmt::Display for CodingPoints {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let primaries = match self.color_primaries {
+            1 => "BT.709",
+            9 => "BT.2020",
+            11 => "DCI-P3",
+            12 => "Display P3",
+            _ => "unknown",
+        };
+        let transfer = match self.transfer_function {
+            1 | 6 | 14 | 15 => "BT.709",
+            8 => "linear",
+            13 => "sRGB",
+            16 => "PQ",
+            18 => "HLG",
+            _ => "unknown",
+        };
+        write!(
+            f,
+            "primaries {} ({}), transfer {} ({}), {} range",
+            self.color_primaries,
+            primaries,
+            self.transfer_function,
+            transfer,
+            if self.full_range { "full" } else { "narrow" }
+        )
+    }
+}
+
+/// Every color space chunk of an image. Decoders use the first one present out of `cICP`,
+/// `iCCP`, `sRGB` and then `cHRM` with `gAMA`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ColorInfo {
+    pub gamma: Option<Gamma>,
+    pub chromaticities: Option<Chromaticities>,
+    pub srgb: Option<RenderingIntent>,
+    pub icc: Option<IccProfile>,
+    pub cicp: Option<CodingPoints>,
+}
+
+impl ColorInfo {
+    /// Reads the color space chunks of `png`, failing on the first invalid one
+    pub fn from_png(png: &Png) -> Result<Self> {
+        fn parse<T: for<'a> TryFrom<&'a [u8], Error = Error>>(
+            png: &Png,
+            chunk_type: &str,
+        ) -> Result<Option<T>> {
+            png.chunk_by_type(chunk_type)
+                .map(|chunk| {
+                    T::try_from(chunk.data()).map_err(|e| -> Error {
+                        format!("Invalid {} chunk: {}", chunk_type, e).into()
+                    })
+                })
+                .transpose()
+        }
+
+        Ok(ColorInfo {
+            gamma: parse(png, "gAMA")?,
+            chromaticities: parse(png, "cHRM")?,
+            srgb: parse(png, "sRGB")?,
+            icc: parse(png, "iCCP")?,
+            cicp: parse(png, "cICP")?,
+        })
+    }
+
+    /// Describes every combination of chunks that the PNG spec forbids or advises against
+    pub fn conflicts(&self) -> Vec<String> {
+        let mut conflicts = Vec::new();
+
+        if self.srgb.is_some() && self.icc.is_some() {
+            conflicts.push("sRGB and iCCP must not both be present".to_string());
+        }
+        if self.srgb.is_some() {
+            if let Some(gamma) = self.gamma
+                && gamma.0 != SRGB_GAMMA
+            {
+                conflicts.push(format!(
+                    "gAMA {} doesn't match sRGB, which needs {}",
+                    gamma.0, SRGB_GAMMA
+                ));
+            }
+            if self
+                .chromaticities
+                .is_some_and(|chromaticities| chromaticities != SRGB_CHROMATICITIES)
+            {
+                conflicts.push("cHRM doesn't match the sRGB primaries".to_string());
+            }
+        }
+
+        let overridden: Vec<&str> = [
+            ("iCCP", self.icc.is_some()),
+            ("sRGB", self.srgb.is_some()),
+            ("cHRM", self.chromaticities.is_some()),
+            ("gAMA", self.gamma.is_some()),
+        ]
+        .into_iter()
+        .filter(|(_, present)| *present)
+        .map(|(name, _)| name)
+        .collect();
+        if self.cicp.is_some() && !overridden.is_empty() {
+            conflicts.push(format!("cICP overrides {}", overridden.join(", ")));
+        }
+
+        conflicts
+    }
+}
+
+impl fmt::Display for ColorInfo {
+    /// Writes the color space a decoder would use
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(cicp) = &self.cicp {
+            return write!(f, "cICP {}", cicp);
+        }
+        if let Some(icc) = &self.icc {
+            return write!(
+                f,
+                "ICC profile {:?} ({}, {} bytes)",
+                icc.name,
+                icc.color_space(),
+                icc.profile.len()
+            );
+        }
+        if let Some(intent) = &self.srgb {
+            return write!(f, "sRGB, {} rendering intent", intent);
+        }
+
+        match (&self.gamma, &self.chromaticities) {
+            (None, None) => write!(f, "unspecified"),
+            (gamma, chromaticities) => {
+                let mut parts = Vec::new();
+                if let Some(gamma) = gamma {
+                    parts.push(format!("gamma {:.5}", gamma.value()));
+                }
+                if let Some(c) = chromaticities {
+                    parts.push(format!(
+                        "white point {:.4},{:.4}",
+                        c.white.0 as f64 / 100_000.0,
+                        c.white.1 as f64 / 100_000.0
+                    ));
+                }
+                write!(f, "{}", parts.join(", "))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::ChunkPosition;
+    use crate::png::tests::PNG_FILE;
+
+    fn testing_profile() -> Vec<u8> {
+        let mut profile = vec![0u8; ICC_HEADER_LEN + 20];
+        let len = profile.len() as u32;
+        profile[0..4].copy_from_slice(&len.to_be_bytes());
+        profile[16..20].copy_from_slice(b"RGB ");
+        profile[36..40].copy_from_slice(b"acsp");
+        profile
+    }
+
+    fn testing_png(chunks: &[Chunk]) -> Png {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        for chunk_type in ["gAMA", "cHRM", "sRGB", "iCCP", "cICP"] {
+            let _ = png.remove_chunks(chunk_type);
+        }
+        for chunk in chunks {
+            png.insert_chunk(chunk.clone(), ChunkPosition::AfterIhdr)
+                .unwrap();
+        }
+        png
+    }
+
+    #[test]
+    fn test_chunks_round_trip() {
+        let gamma = Gamma(SRGB_GAMMA);
+        assert_eq!(Gamma::try_from(gamma.to_chunk().data()).unwrap(), gamma);
+
+        let chunk = SRGB_CHROMATICITIES.to_chunk();
+        assert_eq!(
+            Chromaticities::try_from(chunk.data()).unwrap(),
+            SRGB_CHROMATICITIES
+        );
+
+        let intent = RenderingIntent::Saturation;
+        assert_eq!(intent.to_chunk().data(), &[2]);
+        assert_eq!(RenderingIntent::try_from(&[2][..]).unwrap(), intent);
+
+        let cicp = CodingPoints {
+            color_primaries: 9,
+            transfer_function: 16,
+            matrix_coefficients: 0,
+            full_range: true,
+        };
+        assert_eq!(cicp.to_chunk().data(), &[9, 16, 0, 1]);
+        assert_eq!(CodingPoints::try_from(&[9, 16, 0, 1][..]).unwrap(), cicp);
+    }
+
+    #[test]
+    fn test_invalid_chunks() {
+        assert!(Gamma::try_from(&[0, 0, 0, 0][..]).is_err());
+        assert!(RenderingIntent::try_from(&[4][..]).is_err());
+        assert!(CodingPoints::try_from(&[9, 16, 1, 1][..]).is_err());
+        assert!(CodingPoints::try_from(&[9, 16, 0, 2][..]).is_err());
+        assert!(Chromaticities::try_from(&[0; 31][..]).is_err());
+    }
+
+    #[test]
+    fn test_icc_profile_round_trip() {
+        let icc = IccProfile::new("Test RGB", testing_profile()).unwrap();
+        let chunk = icc.to_chunk().unwrap();
+        assert!(chunk.data().starts_with(b"Test RGB\0\0"));
+        let parsed = IccProfile::try_from(chunk.data()).unwrap();
+        assert_eq!(parsed, icc);
+        assert_eq!(parsed.color_space(), "RGB");
+    }
+
+    #[test]
+    fn test_icc_profile_validation() {
+        assert!(IccProfile::new("", testing_profile()).is_err());
+        assert!(IccProfile::new(" leading", testing_profile()).is_err());
+        assert!(IccProfile::new(&"x".repeat(80), testing_profile()).is_err());
+        assert!(IccProfile::new("short", vec![0; 64]).is_err());
+    }
+
+    #[test]
+    fn test_precedence() {
+        let icc = IccProfile::new("Test RGB", testing_profile()).unwrap();
+        let png = testing_png(&[
+            Gamma(SRGB_GAMMA).to_chunk(),
+            RenderingIntent::Perceptual.to_chunk(),
+        ]);
+        let info = ColorInfo::from_png(&png).unwrap();
+        assert_eq!(info.to_string(), "sRGB, perceptual rendering intent");
+        assert!(info.conflicts().is_empty());
+
+        let png = testing_png(&[
+            Gamma(100_000).to_chunk(),
+            RenderingIntent::Perceptual.to_chunk(),
+            icc.to_chunk().unwrap(),
+        ]);
+        let info = ColorInfo::from_png(&png).unwrap();
+        assert!(info.to_string().starts_with("ICC profile \"Test RGB\""));
+        assert_eq!(info.conflicts().len(), 2);
+
+        let png = testing_png(&[
+            icc.to_chunk().unwrap(),
+            CodingPoints::try_from(&[1, 13, 0, 1][..])
+                .unwrap()
+                .to_chunk(),
+        ]);
+        let info = ColorInfo::from_png(&png).unwrap();
+        assert!(info.to_string().starts_with("cICP primaries 1 (BT.709)"));
+        assert_eq!(info.conflicts(), ["cICP overrides iCCP"]);
+    }
+
+    #[test]
+    fn test_unspecified_color_space() {
+        let info = ColorInfo::from_png(&testing_png(&[])).unwrap();
+        assert_eq!(info.to_string(), "unspecified");
+    }
+}
@@ -1,13 +1,39 @@
-use crate::args::{DecodeArgs, EncodeArgs, PrintArgs, RemoveArgs};
+use crate::args::{
+    AnimateArgs, CapacityArgs, CarveArgs, DecodeArgs, DetectArgs, DiffArgs, DpiArgs, EmbedMode,
+    EncodeArgs, ExifArgs, ExplodeArgs, FramesArgs, IccArgs, ImplodeArgs, PlanesArgs, PolyglotArgs,
+    PrintArgs, RemoveArgs, StreamOrder, StripArgs, StripMode, TouchArgs, ValidateArgs,
+    ZipPlacementMode,
+};
 use std::fs;
 use std::path::PathBuf;
 
 use crate::Result;
+use crate::apng::{self, Animation, AssembleOptions};
+use crate::capacity::CapacityReport;
+use crate::carve;
 use crate::chunk::Chunk;
+use crate::color::{ColorInfo, IccProfile};
+use crate::detect::DetectionReport;
+use crate::diff::PngDiff;
+use crate::exif::Exif;
+use crate::fec::FecOptions;
+use crate::hdr::{ContentLightLevel, MasteringDisplay};
+use crate::json;
+use crate::manifest::{Manifest, ManifestEntry};
+use crate::phys::PhysicalDimensions;
+use crate::pixels::PixelData;
+use crate::planes::{self, ScanOrder};
+use crate::png::{ChunkPosition, UnsafeChunkPolicy};
+use crate::polyglot::{self, ZipPlacement};
+use crate::stego;
+use crate::strip::StripPolicy;
+use crate::time::Time;
+use crate::validate::ValidationReport;
 use crate::{chunk_types::ChunkType, png::Png};
 use std::convert::TryFrom;
 use std::str::FromStr;
 
+#[derive(Default)]
 pub struct Commands;
 
 impl Commands {
@@ -28,17 +54,103 @@ impl Commands {
 
         let byte_msg = args.message.as_bytes();
 
-        let new_chunk = Chunk::new(new_chunk_type, byte_msg.to_vec());
+        let lsb_only = args.passphrase.is_some()
+            || args.matrix.is_some()
+            || args.matching
+            || args.adaptive.is_some();
+        if lsb_only && args.mode != EmbedMode::Lsb {
+            return Err(
+                "Passphrases, matrix, matching and adaptive embedding only apply to LSB embedding"
+                    .into(),
+            );
+        }
+
+        let options = stego::EmbedOptions {
+            policy: if args.keep_unsafe {
+                UnsafeChunkPolicy::Preserve
+            } else {
+                UnsafeChunkPolicy::Drop
+            },
+            fec: args
+                .redundancy
+                .map(|redundancy| FecOptions::new(redundancy, args.interleave))
+                .transpose()?,
+            key: args
+                .passphrase
+                .map(|passphrase| stego::EmbedKey::from_passphrase(&passphrase, &new_chunk_type)),
+            matrix_bits: args.matrix.unwrap_or(0),
+            lsb_matching: args.matching,
+            adaptive: args.adaptive,
+        };
 
-        png_file.append_chunk(new_chunk);
+        let (dropped, stats) = match (args.mode, args.spread) {
+            (EmbedMode::Chunk, false) => {
+                let data = match &options.fec {
+                    Some(fec) => stego::split_message(&new_chunk_type, byte_msg, 1)?
+                        .remove(0)
+                        .to_frame(Some(fec)),
+                    None => byte_msg.to_vec(),
+                };
+                png_file.append_chunk(Chunk::new(new_chunk_type, data));
+                (Vec::new(), None)
+            }
+            (EmbedMode::Chunk, true) => {
+                stego::embed_in_chunks(&mut png_file, &new_chunk_type, byte_msg, &options)?;
+                (Vec::new(), None)
+            }
+            (EmbedMode::Lsb, false) => {
+                let (dropped, stats) =
+                    stego::embed_in_pixels(&mut png_file, &new_chunk_type, byte_msg, &options)?;
+                (dropped, Some(stats))
+            }
+            (EmbedMode::Lsb, true) => {
+                let (dropped, stats) =
+                    stego::embed_in_frames(&mut png_file, &new_chunk_type, byte_msg, &options)?;
+                (dropped, Some(stats))
+            }
+            (EmbedMode::PaletteOrder | EmbedMode::PaletteParity, true) => {
+                return Err("Palette modes can't spread a message over frames".into());
+            }
+            (EmbedMode::PaletteOrder, false) => (
+                stego::embed_in_palette_order(&mut png_file, &new_chunk_type, byte_msg, &options)?,
+                None,
+            ),
+            (EmbedMode::PaletteParity, false) => (
+                stego::embed_in_palette_parity(&mut png_file, &new_chunk_type, byte_msg, &options)?,
+                None,
+            ),
+            (EmbedMode::Zlib, true) => {
+                return Err("Zlib mode can't spread a message over frames".into());
+            }
+            (EmbedMode::Zlib, false) => (
+                stego::embed_in_zlib(&mut png_file, &new_chunk_type, byte_msg, &options)?,
+                None,
+            ),
+        };
+
+        if args.touch {
+            Time::now()?.apply(&mut png_file)?;
+        }
 
         let output_path = args.output_file.as_ref().unwrap_or(&file_path);
         fs::write(output_path, png_file.as_bytes())?;
 
-        Ok(format!(
-            "Message successfully encoded into {:?}",
-            output_path,
-        ))
+        let mut report = format!("Message successfully encoded into {:?}", output_path);
+        if let Some(stats) = stats {
+            report.push_str(&format!("\n{}", stats));
+        }
+        if !dropped.is_empty() {
+            let dropped_types: Vec<String> = dropped
+                .iter()
+                .map(|chunk| chunk.chunk_type().to_string())
+                .collect();
+            report.push_str(&format!(
+                "\nDropped unsafe-to-copy chunks: {}",
+                dropped_types.join(", ")
+            ));
+        }
+
+        Ok(report)
     }
 
     pub fn decode(&self, args: DecodeArgs) -> Result<String> {
@@ -47,13 +159,60 @@ impl Commands {
         let file_path = args.file_path;
         let png_file = self.open_as_png(&file_path)?;
 
-        if png_file.chunk_by_type(&args.chunk_type).is_none() {
-            return Err("Chunk type not found".into());
+        let chunk_type = ChunkType::from_str(&args.chunk_type)?;
+
+        if (args.passphrase.is_some() || args.adaptive.is_some()) && args.mode != EmbedMode::Lsb {
+            return Err("Passphrases and adaptive embedding only apply to LSB embedding".into());
         }
+        let options = stego::EmbedOptions {
+            key: args
+                .passphrase
+                .map(|passphrase| stego::EmbedKey::from_passphrase(&passphrase, &chunk_type)),
+            adaptive: args.adaptive,
+            ..Default::default()
+        };
+
+        let extracted = match args.mode {
+            EmbedMode::Chunk => {
+                if png_file.chunk_by_type(&args.chunk_type).is_none() {
+                    return Err("Chunk type not found".into());
+                }
+
+                let chunk = png_file.chunk_by_type(&args.chunk_type).unwrap();
+                let plain = || stego::Extracted {
+                    message: chunk.data().to_vec(),
+                    corrected: 0,
+                };
+
+                // spread or protected messages carry a payload header, but a plain message
+                // may just happen to start with the same bytes
+                if chunk.data().starts_with(&stego::MAGIC)
+                    || chunk.data().starts_with(&stego::FEC_MAGIC)
+                {
+                    stego::extract_from_chunks(&png_file, &chunk_type).unwrap_or_else(|_| plain())
+                } else {
+                    plain()
+                }
+            }
+            EmbedMode::Lsb => {
+                if png_file.chunk_by_type("acTL").is_some() {
+                    stego::extract_from_frames(&png_file, &chunk_type, &options)
+                        .or_else(|_| stego::extract_from_pixels(&png_file, &chunk_type, &options))?
+                } else {
+                    stego::extract_from_pixels(&png_file, &chunk_type, &options)?
+                }
+            }
+            EmbedMode::PaletteOrder => stego::extract_from_palette_order(&png_file, &chunk_type)?,
+            EmbedMode::PaletteParity => stego::extract_from_palette_parity(&png_file, &chunk_type)?,
+            EmbedMode::Zlib => stego::extract_from_zlib(&png_file, &chunk_type)?,
+        };
 
-        let chunk = png_file.chunk_by_type(&args.chunk_type).unwrap();
+        let mut report = format!("Message = {:?}", String::from_utf8(extracted.message)?);
+        if extracted.corrected > 0 {
+            report.push_str(&format!("\nCorrected {} byte errors", extracted.corrected));
+        }
 
-        Ok(format!("Message = {:?}", chunk.data_as_string().unwrap()))
+        Ok(report)
     }
 
     pub fn remove(&self, args: RemoveArgs) -> Result<String> {
@@ -65,7 +224,7 @@ impl Commands {
         let file_path = args.file_path;
         let mut png_file = self.open_as_png(&file_path)?;
 
-        if !png_file.chunk_by_type(&args.chunk_type).is_none() {
+        if png_file.chunk_by_type(&args.chunk_type).is_some() {
             return Err("Chunk type not found".into());
         }
 
@@ -78,23 +237,585 @@ impl Commands {
     }
 
     pub fn print(&self, args: PrintArgs) -> Result<String> {
-        println!("Printing chunks from {:?}", args.file_path);
+        // the banner would break JSON output piped into another tool
+        if !args.json {
+            println!("Printing chunks from {:?}", args.file_path);
+        }
 
         let file_path = args.file_path;
         let png_file = self.open_as_png(&file_path)?;
+        if args.json {
+            return Ok(json::describe_png(&png_file).to_string());
+        }
+
+        let mut report = format!("{}", png_file);
+        match Time::from_png(&png_file) {
+            Ok(Some(time)) => report.push_str(&format!("Last modified: {}\n", time)),
+            Ok(None) => {}
+            Err(e) => report.push_str(&format!("Invalid tIME chunk: {}\n", e)),
+        }
+        match PhysicalDimensions::from_png(&png_file) {
+            Ok(Some(dimensions)) => report.push_str(&format!("Pixel density: {}\n", dimensions)),
+            Ok(None) => {}
+            Err(e) => report.push_str(&format!("Invalid pHYs chunk: {}\n", e)),
+        }
+        match ColorInfo::from_png(&png_file) {
+            Ok(color) => {
+                report.push_str(&format!("Color space: {}\n", color));
+                for conflict in color.conflicts() {
+                    report.push_str(&format!("Color warning: {}\n", conflict));
+                }
+            }
+            Err(e) => report.push_str(&format!("{}\n", e)),
+        }
+        match MasteringDisplay::from_png(&png_file) {
+            Ok(Some(display)) => report.push_str(&format!("Mastering display: {}\n", display)),
+            Ok(None) => {}
+            Err(e) => report.push_str(&format!("Invalid mDCV chunk: {}\n", e)),
+        }
+        match ContentLightLevel::from_png(&png_file) {
+            Ok(Some(level)) => report.push_str(&format!("Content light level: {}\n", level)),
+            Ok(None) => {}
+            Err(e) => report.push_str(&format!("Invalid cLLI chunk: {}\n", e)),
+        }
+        match Exif::from_png(&png_file) {
+            Ok(Some(exif)) => {
+                report.push_str("EXIF:\n");
+                for line in exif.to_string().lines() {
+                    report.push_str(&format!("  {}\n", line));
+                }
+            }
+            Ok(None) => {}
+            Err(e) => report.push_str(&format!("Invalid eXIf chunk: {}\n", e)),
+        }
+
+        Ok(report)
+    }
+
+    pub fn explode(&self, args: ExplodeArgs) -> Result<String> {
+        println!(
+            "Exploding chunks from {:?} into {:?}",
+            args.file_path, args.output_dir
+        );
+
+        let png_file = self.open_as_png(&args.file_path)?;
+        fs::create_dir_all(&args.output_dir)?;
+
+        let mut entries = Vec::new();
+        for (index, chunk) in png_file.chunks().iter().enumerate() {
+            let file_name = ManifestEntry::default_file_name(index, chunk.chunk_type());
+            fs::write(args.output_dir.join(&file_name), chunk.data())?;
+
+            entries.push(ManifestEntry {
+                index,
+                chunk_type: chunk.chunk_type().clone(),
+                crc: chunk.crc(),
+                file_name,
+            });
+        }
+
+        let manifest = Manifest::new(entries);
+        fs::write(
+            args.output_dir.join(Manifest::FILE_NAME),
+            manifest.to_string(),
+        )?;
+
+        Ok(format!(
+            "{} chunks exploded into {:?}",
+            manifest.entries().len(),
+            args.output_dir
+        ))
+    }
+
+    pub fn implode(&self, args: ImplodeArgs) -> Result<String> {
+        println!(
+            "Imploding chunks from {:?} into {:?}",
+            args.input_dir, args.output_file
+        );
+
+        let manifest_text = fs::read_to_string(args.input_dir.join(Manifest::FILE_NAME))?;
+        let manifest = Manifest::from_str(&manifest_text)?;
+
+        let mut chunks = Vec::new();
+        let mut modified = Vec::new();
+        for entry in manifest.entries() {
+            let data = fs::read(args.input_dir.join(&entry.file_name))?;
+            let chunk = Chunk::new(entry.chunk_type.clone(), data);
+
+            if chunk.crc() != entry.crc {
+                modified.push(format!("{} ({})", entry.index, entry.chunk_type));
+            }
+            chunks.push(chunk);
+        }
+
+        let png_file = Png::from_chunks(chunks);
+        fs::write(&args.output_file, png_file.as_bytes())?;
+
+        let mut report = format!(
+            "{} chunks imploded into {:?}",
+            png_file.chunks().len(),
+            args.output_file
+        );
+        if !modified.is_empty() {
+            report.push_str(&format!("\nModified chunks: {}", modified.join(", ")));
+        }
+
+        Ok(report)
+    }
+
+    pub fn diff(&self, args: DiffArgs) -> Result<String> {
+        println!("Comparing {:?} with {:?}", args.old_file, args.new_file);
+
+        let old_png = self.open_as_png(&args.old_file)?;
+        let new_png = self.open_as_png(&args.new_file)?;
+
+        Ok(PngDiff::new(&old_png, &new_png).to_string())
+    }
+
+    pub fn strip(&self, args: StripArgs) -> Result<String> {
+        println!("Stripping ancillary chunks from {:?}", args.file_path);
+
+        let policy = if !args.keep.is_empty() {
+            StripPolicy::Keep(self.parse_ancillary_types(&args.keep)?)
+        } else if !args.drop.is_empty() {
+            StripPolicy::Drop(self.parse_ancillary_types(&args.drop)?)
+        } else {
+            match args.policy.unwrap_or(StripMode::All) {
+                StripMode::All => StripPolicy::AllAncillary,
+                StripMode::Unsafe => StripPolicy::UnsafeToCopy,
+                StripMode::Private => StripPolicy::Private,
+            }
+        };
+
+        let mut png_file = self.open_as_png(&args.file_path)?;
+        let removed = png_file.strip(&policy);
+
+        let output_path = args.output_file.as_ref().unwrap_or(&args.file_path);
+        fs::write(output_path, png_file.as_bytes())?;
+
+        let removed_types: Vec<String> = removed
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+
+        Ok(format!(
+            "Removed {} chunks from {:?}: {}",
+            removed.len(),
+            output_path,
+            removed_types.join(", ")
+        ))
+    }
+
+    pub fn frames(&self, args: FramesArgs) -> Result<String> {
+        println!("Reading animation frames from {:?}", args.file_path);
+
+        let png_file = self.open_as_png(&args.file_path)?;
+        let animation = Animation::from_png(&png_file)?;
+        let mut report = animation.to_string();
+
+        if let Some(render_dir) = args.render {
+            fs::create_dir_all(&render_dir)?;
+            let rendered = animation.render(&png_file)?;
+            for (index, frame) in rendered.iter().enumerate() {
+                let frame_path = render_dir.join(format!("frame_{:04}.png", index));
+                fs::write(&frame_path, Png::from_pixels(frame)?.as_bytes())?;
+            }
+            report.push_str(&format!(
+                "{} frames rendered into {:?}",
+                rendered.len(),
+                render_dir
+            ));
+        }
+
+        Ok(report)
+    }
+
+    pub fn animate(&self, args: AnimateArgs) -> Result<String> {
+        println!(
+            "Animating {} frames into {:?}",
+            args.frames.len(),
+            args.output_file
+        );
+
+        let frames = args
+            .frames
+            .iter()
+            .map(|path| self.open_as_png(path))
+            .collect::<Result<Vec<Png>>>()?;
+        let default_image = match &args.default_image {
+            Some(path) => Some(self.open_as_png(path)?),
+            None => None,
+        };
+
+        let options = AssembleOptions {
+            delays_ms: args.delay,
+            num_plays: args.loops,
+        };
+        let png_file = apng::assemble(&frames, default_image.as_ref(), &options)?;
+        fs::write(&args.output_file, png_file.as_bytes())?;
 
-        Ok(format!("{}", png_file))
+        Ok(format!(
+            "Animation with {} frames written to {:?}",
+            frames.len(),
+            args.output_file
+        ))
+    }
+
+    pub fn detect(&self, args: DetectArgs) -> Result<String> {
+        println!("Checking {} files for hidden data", args.files.len());
+
+        let mut reports = Vec::new();
+        for file_path in &args.files {
+            // a file that can't be read shouldn't stop the other files from being checked
+            let report = match fs::read(file_path) {
+                Ok(bytes) => match Png::split_trailing_data(&bytes) {
+                    Ok((png_file, trailing)) => {
+                        DetectionReport::new(&png_file, trailing).to_string()
+                    }
+                    Err(e) => format!("Error: {}\n", e),
+                },
+                Err(e) => format!("Error: {}\n", e),
+            };
+            reports.push(format!("{:?}\n{}", file_path, report));
+        }
+
+        Ok(reports.join("\n"))
+    }
+
+    pub fn planes(&self, args: PlanesArgs) -> Result<String> {
+        println!("Extracting bit planes from {:?}", args.file_path);
+
+        let png_file = self.open_as_png(&args.file_path)?;
+        let pixels = PixelData::decode(&png_file)?;
+        let color_type = pixels.header().color_type;
+
+        let channels = if args.channels.is_empty() {
+            (0..pixels.channels()).collect()
+        } else {
+            args.channels
+                .iter()
+                .map(|name| planes::channel_index(color_type, name))
+                .collect::<Result<Vec<usize>>>()?
+        };
+
+        let mut report = Vec::new();
+
+        if let Some(output_dir) = &args.output_dir {
+            let bits = if args.bits.is_empty() {
+                (0..pixels.header().bit_depth).collect()
+            } else {
+                args.bits.clone()
+            };
+
+            fs::create_dir_all(output_dir)?;
+            for &channel in &channels {
+                for &bit in &bits {
+                    let plane = planes::bit_plane(&pixels, channel, bit)?;
+                    let name = planes::channel_names(color_type)[channel];
+                    let plane_path = output_dir.join(format!("{}_{}.png", name, bit));
+                    fs::write(&plane_path, Png::from_pixels(&plane)?.as_bytes())?;
+                }
+            }
+            report.push(format!(
+                "{} bit planes written to {:?}",
+                channels.len() * bits.len(),
+                output_dir
+            ));
+        }
+
+        if let Some(stream_path) = &args.stream {
+            let bits = if args.bits.is_empty() {
+                vec![0]
+            } else {
+                args.bits.clone()
+            };
+            let order = match args.order {
+                StreamOrder::Row => ScanOrder::Row,
+                StreamOrder::Column => ScanOrder::Column,
+            };
+
+            let stream = planes::bit_stream(&pixels, &channels, &bits, order)?;
+            fs::write(stream_path, &stream)?;
+            report.push(format!(
+                "{} bytes of bit stream written to {:?}",
+                stream.len(),
+                stream_path
+            ));
+        }
+
+        Ok(report.join("\n"))
+    }
+
+    pub fn carve(&self, args: CarveArgs) -> Result<String> {
+        println!(
+            "Carving PNGs from {:?} into {:?}",
+            args.file_path, args.output_dir
+        );
+
+        let data = fs::read(&args.file_path)?;
+        let carved = carve::carve(&data);
+        if carved.is_empty() {
+            return Ok("No PNGs found".to_string());
+        }
+
+        fs::create_dir_all(&args.output_dir)?;
+        let mut report = Vec::new();
+        for found in &carved {
+            let png_path = args.output_dir.join(format!("{:08x}.png", found.offset));
+            fs::write(&png_path, found.png.as_bytes())?;
+            report.push(format!("{} -> {:?}", found, png_path));
+        }
+        report.push(format!("{} PNGs carved", carved.len()));
+
+        Ok(report.join("\n"))
+    }
+
+    pub fn polyglot(&self, args: PolyglotArgs) -> Result<String> {
+        println!(
+            "Combining {:?} and {:?} into {:?}",
+            args.file_path, args.archive, args.output_file
+        );
+
+        let png_file = self.open_as_png(&args.file_path)?;
+        let archive = fs::read(&args.archive)?;
+        let placement = match args.placement {
+            ZipPlacementMode::Trailing => ZipPlacement::Trailing,
+            ZipPlacementMode::Chunk => ZipPlacement::Chunk(ChunkType::from_str(&args.chunk_type)?),
+        };
+
+        let bytes = polyglot::embed_zip(&png_file, &archive, &placement)?;
+        fs::write(&args.output_file, &bytes)?;
+
+        let mut report = format!("Polyglot written to {:?}", args.output_file);
+        if args.verify {
+            report.push_str(&format!("\n{}", polyglot::verify_polyglot(&bytes)?));
+        }
+
+        Ok(report)
+    }
+
+    pub fn capacity(&self, args: CapacityArgs) -> Result<String> {
+        println!("Estimating how much fits into {:?}", args.file_path);
+
+        let png_file = self.open_as_png(&args.file_path)?;
+        let options = stego::EmbedOptions {
+            fec: args
+                .redundancy
+                .map(|redundancy| FecOptions::new(redundancy, args.interleave))
+                .transpose()?,
+            matrix_bits: args.matrix.unwrap_or(0),
+            adaptive: args.adaptive,
+            ..Default::default()
+        };
+
+        Ok(CapacityReport::new(&png_file, options, args.spread)?.to_string())
+    }
+
+    pub fn validate(&self, args: ValidateArgs) -> Result<String> {
+        println!("Validating {:?}", args.file_path);
+
+        let report = ValidationReport::new(&fs::read(&args.file_path)?);
+        if !report.is_valid() {
+            return Err(format!("Invalid PNG: {}", report.errors.join("; ")).into());
+        }
+
+        Ok(report.to_string())
+    }
+
+    pub fn touch(&self, args: TouchArgs) -> Result<String> {
+        println!("Updating the modification time of {:?}", args.file_path);
+
+        let mut png_file = self.open_as_png(&args.file_path)?;
+        let report = if args.remove {
+            if png_file.chunk_by_type("tIME").is_none() {
+                return Ok("No tIME chunk to remove".to_string());
+            }
+            png_file.remove_chunks("tIME")?;
+            "Removed the modification time".to_string()
+        } else {
+            let time = match &args.time {
+                Some(time) => Time::from_str(time)?,
+                None => Time::now()?,
+            };
+            match time.apply(&mut png_file)? {
+                Some(old) => format!("Changed the modification time from {} to {}", old, time),
+                None => format!("Set the modification time to {}", time),
+            }
+        };
+
+        let output_path = args.output_file.as_ref().unwrap_or(&args.file_path);
+        fs::write(output_path, png_file.as_bytes())?;
+
+        Ok(report)
+    }
+
+    pub fn dpi(&self, args: DpiArgs) -> Result<String> {
+        let action = if args.set.is_some() || args.remove {
+            "Updating"
+        } else {
+            "Reading"
+        };
+        println!("{} the pixel density of {:?}", action, args.file_path);
+
+        let mut png_file = self.open_as_png(&args.file_path)?;
+        let unit = if args.dpcm { "dpcm" } else { "dpi" };
+        let density = |dimensions: &PhysicalDimensions| {
+            let converted = if args.dpcm {
+                dimensions.dpcm()
+            } else {
+                dimensions.dpi()
+            };
+            match converted {
+                Some((x, _)) if dimensions.x == dimensions.y => format!("{:.2} {}", x, unit),
+                Some((x, y)) => format!("{:.2}x{:.2} {}", x, y, unit),
+                None => dimensions.to_string(),
+            }
+        };
+
+        let report = if args.remove {
+            if png_file.chunk_by_type("pHYs").is_none() {
+                return Ok("No pHYs chunk to remove".to_string());
+            }
+            png_file.remove_chunks("pHYs")?;
+            "Removed the pixel density".to_string()
+        } else if let Some(x) = args.set {
+            let y = args.vertical.unwrap_or(x);
+            let dimensions = if args.dpcm {
+                PhysicalDimensions::from_dpcm(x, y)?
+            } else {
+                PhysicalDimensions::from_dpi(x, y)?
+            };
+            match dimensions.apply(&mut png_file)? {
+                Some(old) => format!(
+                    "Changed the pixel density from {} to {}",
+                    density(&old),
+                    density(&dimensions)
+                ),
+                None => format!("Set the pixel density to {}", density(&dimensions)),
+            }
+        } else {
+            return Ok(match PhysicalDimensions::from_png(&png_file)? {
+                Some(dimensions) => format!("Pixel density: {}", density(&dimensions)),
+                None => "No pixel density is set".to_string(),
+            });
+        };
+
+        let output_path = args.output_file.as_ref().unwrap_or(&args.file_path);
+        fs::write(output_path, png_file.as_bytes())?;
+
+        Ok(report)
+    }
+
+    pub fn icc(&self, args: IccArgs) -> Result<String> {
+        println!("Managing the ICC profile of {:?}", args.file_path);
+
+        let mut png_file = self.open_as_png(&args.file_path)?;
+
+        let report = if let Some(profile_path) = &args.attach {
+            let name = match &args.name {
+                Some(name) => name.clone(),
+                None => profile_path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .ok_or("Profile has no file name, use --name")?,
+            };
+            let icc = IccProfile::new(&name, fs::read(profile_path)?)?;
+
+            // sRGB and iCCP must not both be present
+            let mut report = format!("Attached ICC profile {:?}", icc.name);
+            if png_file.remove_chunks("sRGB").is_ok() {
+                report.push_str(", removed the sRGB chunk");
+            }
+            png_file.set_chunk(icc.to_chunk()?, ChunkPosition::AfterIhdr)?;
+            if png_file.chunk_by_type("cICP").is_some() {
+                report.push_str("\nThe cICP chunk still takes precedence over the profile");
+            }
+            report
+        } else if args.strip {
+            if png_file.chunk_by_type("iCCP").is_none() {
+                return Ok("No iCCP chunk to remove".to_string());
+            }
+            png_file.remove_chunks("iCCP")?;
+            "Removed the ICC profile".to_string()
+        } else {
+            let color = ColorInfo::from_png(&png_file)?;
+            let icc = color.icc.ok_or("Image has no ICC profile")?;
+            if let Some(profile_path) = &args.extract {
+                fs::write(profile_path, &icc.profile)?;
+                return Ok(format!(
+                    "Wrote ICC profile {:?} to {:?}",
+                    icc.name, profile_path
+                ));
+            }
+            return Ok(format!(
+                "ICC profile {:?}: {}, {} bytes",
+                icc.name,
+                icc.color_space(),
+                icc.profile.len()
+            ));
+        };
+
+        let output_path = args.output_file.as_ref().unwrap_or(&args.file_path);
+        fs::write(output_path, png_file.as_bytes())?;
+
+        Ok(report)
+    }
+
+    pub fn exif(&self, args: ExifArgs) -> Result<String> {
+        println!("Reading EXIF tags from {:?}", args.file_path);
+
+        let mut png_file = self.open_as_png(&args.file_path)?;
+
+        let report = if args.remove {
+            if png_file.chunk_by_type("eXIf").is_none() {
+                return Ok("No eXIf chunk to remove".to_string());
+            }
+            png_file.remove_chunks("eXIf")?;
+            "Removed the eXIf chunk".to_string()
+        } else {
+            let mut exif = Exif::from_png(&png_file)?.ok_or("Image has no eXIf chunk")?;
+            if !args.remove_gps {
+                return Ok(exif.to_string());
+            }
+            if !exif.remove_gps()? {
+                return Ok("Image has no GPS tags".to_string());
+            }
+            png_file.set_chunk(exif.to_chunk(), ChunkPosition::BeforeFirstIdat)?;
+            "Removed the GPS tags".to_string()
+        };
+
+        let output_path = args.output_file.as_ref().unwrap_or(&args.file_path);
+        fs::write(output_path, png_file.as_bytes())?;
+
+        Ok(report)
+    }
+
+    /// Helper function to parse a list of chunk types given to `strip`, which never removes
+    /// or has to be told to keep critical chunks
+    fn parse_ancillary_types(&self, types: &[String]) -> Result<Vec<ChunkType>> {
+        let types = types
+            .iter()
+            .map(|t| ChunkType::from_str(t))
+            .collect::<Result<Vec<ChunkType>>>()?;
+        if let Some(critical) = types.iter().find(|t| t.is_critical()) {
+            return Err(format!(
+                "Critical chunk {} can't be listed, it is always kept",
+                critical
+            )
+            .into());
+        }
+        Ok(types)
     }
 
     /// Helper function to make sure that the file is opened as a png file
     fn open_as_png(&self, file_path: &PathBuf) -> Result<Png> {
-        if fs::exists(&file_path).is_err() {
+        if fs::exists(file_path).is_err() {
             return Err("File does not exist".into());
         }
 
         let raw_file = fs::read(file_path)?;
         let u8_raw_file: &[u8] = &raw_file;
 
-        Ok(Png::try_from(u8_raw_file)?)
+        Png::try_from(u8_raw_file)
     }
 }
@@ -0,0 +1,596 @@
+use crate::chunk_types::ChunkType;
+use crate::pixels::{ColorType, PixelData};
+use crate::png::Png;
+use crate::stego::{FEC_MAGIC, MAGIC};
+
+use std::fmt;
+
+/// Chi-square p-value above which a run of samples is considered embedded
+const CHI_SQUARE_THRESHOLD: f64 = 0.95;
+
+/// Pairs of values expected to occur less often than this are left out of the chi-square test
+const CHI_SQUARE_MIN_EXPECTED: f64 = 5.0;
+
+/// Number of prefixes the chi-square test is repeated on to estimate the embedded length
+const CHI_SQUARE_STEPS: usize = 20;
+
+/// Embedding rates estimated by RS and SPA on clean images rarely go above this
+const RATE_NOISE_FLOOR: f64 = 0.05;
+
+/// Estimated embedding rate from which the LSB planes are considered certainly modified
+const RATE_CERTAIN: f64 = 0.3;
+
+/// Mask applied to groups of 4 samples in RS analysis
+const RS_MASK: [i32; 4] = [0, 1, 1, 0];
+
+/// Result of the chi-square attack of Westfeld and Pfitzmann
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChiSquare {
+    /// Probability that the LSBs of the whole image are embedded
+    pub p_value: f64,
+    /// Fraction of the samples, counted from the start, that look embedded
+    pub embedded_fraction: f64,
+}
+
+/// Statistical analysis of the least significant bits of the color samples
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LsbAnalysis {
+    pub samples: usize,
+    pub chi_square: Option<ChiSquare>,
+    /// Embedding rate estimated by RS (regular/singular groups) analysis
+    pub rs_rate: Option<f64>,
+    /// Embedding rate estimated by sample pair analysis
+    pub spa_rate: Option<f64>,
+}
+
+impl LsbAnalysis {
+    /// Runs every LSB test on `pixels`. Returns `None` for indexed images, whose samples
+    /// are palette indices rather than colors.
+    pub fn new(pixels: &PixelData) -> Option<Self> {
+        let planes = color_planes(pixels)?;
+        let width = pixels.width() as usize;
+        let channels = pixels.channels();
+        let color_channels = planes.len();
+
+        let samples: Vec<i32> = pixels
+            .samples()
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| index % channels < color_channels)
+            .map(|(_, &sample)| sample as i32)
+            .collect();
+        let max = pixels.header().max_sample() as usize;
+
+        Some(LsbAnalysis {
+            samples: samples.len(),
+            chi_square: chi_square(&samples, max),
+            rs_rate: rs_rate(&planes),
+            spa_rate: spa_rate(&planes, width),
+        })
+    }
+
+    /// Suspicion between 0 and 1 based on the tests that could be run
+    fn suspicion(&self) -> f64 {
+        let chi = self.chi_square.map_or(0.0, |chi| {
+            let sequential = if chi.embedded_fraction > 0.0 {
+                0.6 + 0.4 * chi.embedded_fraction
+            } else {
+                0.0
+            };
+            chi.p_value.max(sequential)
+        });
+        let rate = self
+            .rs_rate
+            .into_iter()
+            .chain(self.spa_rate)
+            .fold(0.0, f64::max);
+        let rate = ((rate - RATE_NOISE_FLOOR) / (RATE_CERTAIN - RATE_NOISE_FLOOR)).clamp(0.0, 1.0);
+
+        chi.max(rate)
+    }
+}
+
+/// Everything `pngme detect` found out about one file
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectionReport {
+    /// `None` if the image data couldn't be decoded or is indexed
+    pub lsb: Option<LsbAnalysis>,
+    pub unknown_chunks: Vec<ChunkType>,
+    pub private_chunks: Vec<ChunkType>,
+    /// Chunks whose data starts with a pngme payload header
+    pub payload_chunks: Vec<ChunkType>,
+    pub trailing_bytes: usize,
+    /// Overall suspicion from 0 (clean) to 100 (certainly carries hidden data)
+    pub score: u8,
+}
+
+impl DetectionReport {
+    /// Analyzes `png` and the `trailing` bytes found after its `IEND` chunk
+    pub fn new(png: &Png, trailing: &[u8]) -> Self {
+        let lsb = PixelData::decode(png)
+            .ok()
+            .and_then(|pixels| LsbAnalysis::new(&pixels));
+
+        let mut unknown_chunks = Vec::new();
+        let mut private_chunks = Vec::new();
+        let mut payload_chunks = Vec::new();
+        for chunk in png.chunks() {
+            let chunk_type = chunk.chunk_type().clone();
+            let payload = chunk.data().starts_with(&MAGIC) || chunk.data().starts_with(&FEC_MAGIC);
+            if payload && !payload_chunks.contains(&chunk_type) {
+                payload_chunks.push(chunk_type.clone());
+            }
+            let private = !chunk_type.is_public() && !chunk_type.is_known();
+            if private && !private_chunks.contains(&chunk_type) {
+                private_chunks.push(chunk_type.clone());
+            }
+            if !chunk_type.is_known() && !unknown_chunks.contains(&chunk_type) {
+                unknown_chunks.push(chunk_type);
+            }
+        }
+
+        let mut suspicion = lsb.as_ref().map_or(0.0, LsbAnalysis::suspicion);
+        if !unknown_chunks.is_empty() || !private_chunks.is_empty() {
+            suspicion = suspicion.max(0.5);
+        }
+        if !trailing.is_empty() {
+            suspicion = suspicion.max(0.9);
+        }
+        if !payload_chunks.is_empty() {
+            suspicion = 1.0;
+        }
+
+        DetectionReport {
+            lsb,
+            unknown_chunks,
+            private_chunks,
+            payload_chunks,
+            trailing_bytes: trailing.len(),
+            score: (suspicion * 100.0).round() as u8,
+        }
+    }
+
+    /// A one word summary of the score
+    pub fn verdict(&self) -> &'static str {
+        match self.score {
+            0..30 => "clean",
+            30..70 => "suspicious",
+            _ => "likely hiding data",
+        }
+    }
+}
+
+impl fmt::Display for DetectionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |types: &[ChunkType]| {
+            types
+                .iter()
+                .map(|t| t.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        let rate = |rate: Option<f64>| rate.map_or("n/a".to_string(), |r| format!("{:.3}", r));
+
+        writeln!(
+            f,
+            "Suspicion score: {}/100 ({})",
+            self.score,
+            self.verdict()
+        )?;
+
+        match &self.lsb {
+            Some(lsb) => {
+                writeln!(f, "LSB analysis of {} samples:", lsb.samples)?;
+                match lsb.chi_square {
+                    Some(chi) => writeln!(
+                        f,
+                        "  Chi-square p-value: {:.3} (first {:.0}% look embedded)",
+                        chi.p_value,
+                        chi.embedded_fraction * 100.0
+                    )?,
+                    None => writeln!(f, "  Chi-square p-value: n/a")?,
+                }
+                writeln!(f, "  RS estimated rate: {}", rate(lsb.rs_rate))?;
+                writeln!(f, "  SPA estimated rate: {}", rate(lsb.spa_rate))?;
+            }
+            None => writeln!(f, "LSB analysis: not available for this image")?,
+        }
+
+        if !self.unknown_chunks.is_empty() {
+            writeln!(f, "Unknown chunks: {}", list(&self.unknown_chunks))?;
+        }
+        if !self.private_chunks.is_empty() {
+            writeln!(f, "Private chunks: {}", list(&self.private_chunks))?;
+        }
+        if !self.payload_chunks.is_empty() {
+            writeln!(
+                f,
+                "Chunks holding pngme payloads: {}",
+                list(&self.payload_chunks)
+            )?;
+        }
+        if self.trailing_bytes > 0 {
+            writeln!(f, "Trailing data after IEND: {} bytes", self.trailing_bytes)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Splits the color samples into one plane per channel, skipping alpha
+fn color_planes(pixels: &PixelData) -> Option<Vec<Vec<i32>>> {
+    let channels = pixels.channels();
+    let color_channels = match pixels.header().color_type {
+        ColorType::Indexed => return None,
+        ColorType::GrayscaleAlpha | ColorType::Rgba => channels - 1,
+        _ => channels,
+    };
+
+    Some(
+        (0..color_channels)
+            .map(|channel| {
+                pixels
+                    .samples()
+                    .iter()
+                    .skip(channel)
+                    .step_by(channels)
+                    .map(|&sample| sample as i32)
+                    .collect()
+            })
+            .collect(),
+    )
+}
+
+/// Runs the chi-square test on the whole image and on growing prefixes of `samples`
+fn chi_square(samples: &[i32], max: usize) -> Option<ChiSquare> {
+    let p_value = chi_square_p_value(samples, max)?;
+
+    let mut embedded_fraction = 0.0;
+    for step in 1..=CHI_SQUARE_STEPS {
+        let prefix = &samples[..samples.len() * step / CHI_SQUARE_STEPS];
+        match chi_square_p_value(prefix, max) {
+            Some(p) if p > CHI_SQUARE_THRESHOLD => {
+                embedded_fraction = step as f64 / CHI_SQUARE_STEPS as f64
+            }
+            _ => break,
+        }
+    }
+
+    Some(ChiSquare {
+        p_value,
+        embedded_fraction,
+    })
+}
+
+/// Probability that the values 2k and 2k+1 occur equally often, which is what embedding
+/// random bits in the LSBs does to a histogram
+fn chi_square_p_value(samples: &[i32], max: usize) -> Option<f64> {
+    let mut counts = vec![0u64; max + 1];
+    for &sample in samples {
+        counts[sample as usize] += 1;
+    }
+
+    let mut chi = 0.0;
+    let mut categories = 0;
+    for pair in counts.chunks_exact(2) {
+        let expected = (pair[0] + pair[1]) as f64 / 2.0;
+        if expected < CHI_SQUARE_MIN_EXPECTED {
+            continue;
+        }
+        chi += (pair[0] as f64 - expected).powi(2) / expected;
+        categories += 1;
+    }
+
+    if categories < 2 {
+        return None;
+    }
+    Some(1.0 - regularized_gamma((categories - 1) as f64 / 2.0, chi / 2.0))
+}
+
+/// Flips the LSB of `value` for `direction` 1, shifts it the other way for -1
+fn flip(value: i32, direction: i32) -> i32 {
+    match direction {
+        1 => value ^ 1,
+        -1 => ((value + 1) ^ 1) - 1,
+        _ => value,
+    }
+}
+
+/// Sum of the differences between neighbouring samples in a group
+fn smoothness(group: &[i32]) -> i32 {
+    group.windows(2).map(|pair| (pair[1] - pair[0]).abs()).sum()
+}
+
+/// Fractions of regular and singular groups under the mask (or the negative mask), after
+/// optionally flipping every LSB first
+fn rs_groups(planes: &[Vec<i32>], negative: bool, flipped: bool) -> (f64, f64) {
+    let (mut regular, mut singular, mut total) = (0usize, 0usize, 0usize);
+    let sign = if negative { -1 } else { 1 };
+
+    for plane in planes {
+        for chunk in plane.chunks_exact(RS_MASK.len()) {
+            let group: Vec<i32> = chunk
+                .iter()
+                .map(|&value| if flipped { value ^ 1 } else { value })
+                .collect();
+            let masked: Vec<i32> = group
+                .iter()
+                .zip(RS_MASK)
+                .map(|(&value, mask)| flip(value, mask * sign))
+                .collect();
+
+            match smoothness(&masked).cmp(&smoothness(&group)) {
+                std::cmp::Ordering::Greater => regular += 1,
+                std::cmp::Ordering::Less => singular += 1,
+                std::cmp::Ordering::Equal => {}
+            }
+            total += 1;
+        }
+    }
+
+    if total == 0 {
+        return (0.0, 0.0);
+    }
+    (
+        regular as f64 / total as f64,
+        singular as f64 / total as f64,
+    )
+}
+
+/// Embedding rate estimated by RS analysis (Fridrich, Goljan and Du)
+fn rs_rate(planes: &[Vec<i32>]) -> Option<f64> {
+    let difference = |(regular, singular): (f64, f64)| regular - singular;
+    let d0 = difference(rs_groups(planes, false, false));
+    let d1 = difference(rs_groups(planes, false, true));
+    let dn0 = difference(rs_groups(planes, true, false));
+    let dn1 = difference(rs_groups(planes, true, true));
+
+    let x = smaller_root(2.0 * (d1 + d0), dn0 - dn1 - d1 - 3.0 * d0, d0 - dn0)?;
+    if x == 0.5 {
+        return None;
+    }
+    Some((x / (x - 0.5)).clamp(0.0, 1.0))
+}
+
+/// Embedding rate estimated by sample pair analysis (Dumitrescu, Wu and Wang) over
+/// horizontally adjacent samples
+fn spa_rate(planes: &[Vec<i32>], width: usize) -> Option<f64> {
+    let (mut x, mut y, mut z, mut w, mut total) = (0.0, 0.0, 0.0, 0.0, 0.0);
+
+    for plane in planes {
+        for (index, pair) in plane.windows(2).enumerate() {
+            if (index + 1) % width == 0 {
+                continue;
+            }
+            let (u, v) = (pair[0], pair[1]);
+            if u == v {
+                z += 1.0;
+            } else if (v % 2 == 0) == (u < v) {
+                x += 1.0;
+            } else {
+                y += 1.0;
+                if u / 2 == v / 2 {
+                    w += 1.0;
+                }
+            }
+            total += 1.0;
+        }
+    }
+
+    if total == 0.0 {
+        return None;
+    }
+    let rate = smaller_root(0.5 * (w + z), 2.0 * x - total, y - x)?;
+    Some(rate.clamp(0.0, 1.0))
+}
+
+/// Root of `a x^2 + b x + c` with the smallest magnitude. Measurement noise can push the
+/// parabola off the axis, in which case its vertex is the closest estimate.
+fn smaller_root(a: f64, b: f64, c: f64) -> Option<f64> {
+    if a.abs() < f64::EPSILON {
+        return (b.abs() >= f64::EPSILON).then(|| -c / b);
+    }
+
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return Some(-b / (2.0 * a));
+    }
+    let first = (-b + discriminant.sqrt()) / (2.0 * a);
+    let second = (-b - discriminant.sqrt()) / (2.0 * a);
+    Some(if first.abs() <= second.abs() {
+        first
+    } else {
+        second
+    })
+}
+
+/// Natural logarithm of the gamma function, Lanczos approximation
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let series = COEFFICIENTS
+        .iter()
+        .enumerate()
+        .fold(1.000000000190015, |acc, (i, c)| {
+            acc + c / (x + 1.0 + i as f64)
+        });
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+/// The regularized lower incomplete gamma function P(a, x), used for the chi-square CDF
+fn regularized_gamma(a: f64, x: f64) -> f64 {
+    const ITERATIONS: usize = 500;
+    const EPSILON: f64 = 1e-12;
+
+    if x <= 0.0 {
+        return 0.0;
+    }
+    let prefix = (-x + a * x.ln() - ln_gamma(a)).exp();
+
+    if x < a + 1.0 {
+        // series expansion
+        let (mut term, mut sum, mut n) = (1.0 / a, 1.0 / a, a);
+        for _ in 0..ITERATIONS {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+            if term.abs() < sum.abs() * EPSILON {
+                break;
+            }
+        }
+        (sum * prefix).min(1.0)
+    } else {
+        // continued fraction for the upper function, evaluated with Lentz's method
+        let tiny = f64::MIN_POSITIVE / EPSILON;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..ITERATIONS {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < tiny {
+                d = tiny;
+            }
+            c = b + an / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < EPSILON {
+                break;
+            }
+        }
+        (1.0 - prefix * h).max(0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::fec::FecOptions;
+    use crate::pixels::ImageHeader;
+    use crate::png::tests::PNG_FILE;
+    use crate::stego;
+    use crate::stego::EmbedOptions;
+    use std::str::FromStr;
+
+    fn testing_png() -> Png {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.remove_first_chunk("RuSt").unwrap();
+        png
+    }
+
+    /// Overwrites every LSB with pseudo random bits
+    fn embedded(pixels: &PixelData) -> PixelData {
+        let mut pixels = pixels.clone();
+        let mut state = 0x2545f491u32;
+        for sample in pixels.samples_mut() {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            *sample = (*sample & !1) | (state & 1) as u16;
+        }
+        pixels
+    }
+
+    #[test]
+    fn test_regularized_gamma() {
+        // chi-square CDF with 2 degrees of freedom is 1 - e^(-x/2)
+        let cdf = regularized_gamma(1.0, 1.5);
+        assert!((cdf - (1.0 - (-1.5f64).exp())).abs() < 1e-9);
+        assert!((regularized_gamma(3.0, 20.0) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_smaller_root() {
+        assert_eq!(smaller_root(1.0, -3.0, 2.0), Some(1.0));
+        assert_eq!(smaller_root(0.0, 2.0, -1.0), Some(0.5));
+        assert_eq!(smaller_root(1.0, -2.0, 2.0), Some(1.0));
+        assert_eq!(smaller_root(0.0, 0.0, 1.0), None);
+    }
+
+    #[test]
+    fn test_clean_image() {
+        let lsb = LsbAnalysis::new(&PixelData::decode(&testing_png()).unwrap()).unwrap();
+        assert!(lsb.chi_square.unwrap().p_value < 0.5);
+        assert!(lsb.suspicion() < 0.3);
+    }
+
+    #[test]
+    fn test_embedded_image() {
+        let pixels = embedded(&PixelData::decode(&testing_png()).unwrap());
+        let lsb = LsbAnalysis::new(&pixels).unwrap();
+        assert!(lsb.chi_square.unwrap().p_value > CHI_SQUARE_THRESHOLD);
+        assert!(lsb.rs_rate.unwrap() > 0.5);
+        assert!(lsb.spa_rate.unwrap() > 0.5);
+        assert!(lsb.suspicion() > 0.7);
+    }
+
+    #[test]
+    fn test_indexed_image_skipped() {
+        let header = ImageHeader {
+            width: 4,
+            height: 4,
+            bit_depth: 8,
+            color_type: ColorType::Indexed,
+            interlaced: false,
+        };
+        let pixels = PixelData::new(header, vec![0; 16]).unwrap();
+        assert!(LsbAnalysis::new(&pixels).is_none());
+    }
+
+    #[test]
+    fn test_report_chunks_and_trailing_data() {
+        let mut png = testing_png();
+        let label = ChunkType::from_str("ruSt").unwrap();
+        png.append_chunk(Chunk::new(label.clone(), b"hidden".to_vec()));
+
+        let report = DetectionReport::new(&png, b"appended");
+        assert_eq!(report.unknown_chunks, vec![label.clone()]);
+        assert_eq!(report.private_chunks, vec![label]);
+        assert!(report.payload_chunks.is_empty());
+        assert_eq!(report.trailing_bytes, 8);
+        assert!(report.score >= 90);
+    }
+
+    #[test]
+    fn test_report_protected_chunk_payload() {
+        let mut png = testing_png();
+        let label = ChunkType::from_str("ruSt").unwrap();
+        let fec = FecOptions::new(8, 1).unwrap();
+        let frame = stego::split_message(&label, b"hidden", 1).unwrap()[0].to_frame(Some(&fec));
+        png.append_chunk(Chunk::new(label.clone(), frame));
+
+        let report = DetectionReport::new(&png, &[]);
+        assert_eq!(report.payload_chunks, vec![label]);
+        assert_eq!(report.score, 100);
+    }
+
+    #[test]
+    fn test_report_lsb_payload() {
+        let mut png = testing_png();
+        let clean = DetectionReport::new(&png, &[]);
+        assert_eq!(clean.verdict(), "clean");
+
+        let label = ChunkType::from_str("ruSt").unwrap();
+        let message: Vec<u8> = (0..800u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 13) as u8)
+            .collect();
+        stego::embed_in_pixels(&mut png, &label, &message, &EmbedOptions::default()).unwrap();
+        let report = DetectionReport::new(&png, &[]);
+        assert_eq!(report.verdict(), "likely hiding data");
+    }
+}
@@ -0,0 +1,593 @@
+use crate::chunk::Chunk;
+use crate::chunk_types::ChunkType;
+use crate::pixels::{ImageHeader, PixelData};
+use crate::png::Png;
+
+use std::fmt;
+
+/// Chunks up to this many bytes get a byte-level diff when modified
+pub const BYTE_DIFF_LIMIT: usize = 256;
+
+/// A run of differing bytes inside a modified chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ByteChange {
+    pub offset: usize,
+    pub old: Vec<u8>,
+    pub new: Vec<u8>,
+}
+
+/// What happened to a single chunk between two files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChunkChange {
+    Unchanged {
+        chunk_type: ChunkType,
+        old_index: usize,
+        new_index: usize,
+    },
+    /// The same chunk exists in both files but at a different relative position
+    Moved {
+        chunk_type: ChunkType,
+        old_index: usize,
+        new_index: usize,
+    },
+    /// A chunk of the same type whose data changed. `bytes` is only filled in for chunks no
+    /// larger than `BYTE_DIFF_LIMIT`.
+    Modified {
+        chunk_type: ChunkType,
+        old_index: usize,
+        new_index: usize,
+        bytes: Option<Vec<ByteChange>>,
+    },
+    Removed {
+        chunk_type: ChunkType,
+        old_index: usize,
+    },
+    Inserted {
+        chunk_type: ChunkType,
+        new_index: usize,
+    },
+}
+
+impl ChunkChange {
+    /// Position used to order changes, preferring the index in the new file
+    fn sort_key(&self) -> (usize, usize) {
+        match self {
+            ChunkChange::Unchanged {
+                old_index,
+                new_index,
+                ..
+            }
+            | ChunkChange::Moved {
+                old_index,
+                new_index,
+                ..
+            }
+            | ChunkChange::Modified {
+                old_index,
+                new_index,
+                ..
+            } => (*new_index, *old_index),
+            ChunkChange::Removed { old_index, .. } => (*old_index, *old_index),
+            ChunkChange::Inserted { new_index, .. } => (*new_index, usize::MAX),
+        }
+    }
+}
+
+/// Summary of how the decoded pixels differ when the `IDAT` data is not identical
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PixelDiff {
+    /// The compressed data differs but decodes to the same pixels
+    Identical,
+    /// The images can't be compared sample by sample
+    HeaderChanged { old: ImageHeader, new: ImageHeader },
+    Changed {
+        total_pixels: usize,
+        changed_pixels: usize,
+        changed_samples: usize,
+        /// Changed samples that only differ in their least significant bit
+        lsb_only_samples: usize,
+        max_delta: u16,
+        /// Bounding box of the changed pixels as (min x, min y, max x, max y)
+        bounds: (u32, u32, u32, u32),
+    },
+    /// One of the images couldn't be decoded
+    Undecodable(String),
+}
+
+impl PixelDiff {
+    pub fn new(old: &Png, new: &Png) -> Self {
+        let (old_pixels, new_pixels) = match (PixelData::decode(old), PixelData::decode(new)) {
+            (Ok(old), Ok(new)) => (old, new),
+            (Err(e), _) | (_, Err(e)) => return PixelDiff::Undecodable(e.to_string()),
+        };
+
+        if old_pixels.header() != new_pixels.header() {
+            return PixelDiff::HeaderChanged {
+                old: *old_pixels.header(),
+                new: *new_pixels.header(),
+            };
+        }
+
+        let channels = old_pixels.channels();
+        let width = old_pixels.width() as usize;
+        let mut changed_pixels = 0;
+        let mut changed_samples = 0;
+        let mut lsb_only_samples = 0;
+        let mut max_delta = 0;
+        let mut bounds = (u32::MAX, u32::MAX, 0, 0);
+
+        for (i, (old_px, new_px)) in old_pixels
+            .samples()
+            .chunks(channels)
+            .zip(new_pixels.samples().chunks(channels))
+            .enumerate()
+        {
+            if old_px == new_px {
+                continue;
+            }
+
+            changed_pixels += 1;
+            let x = (i % width) as u32;
+            let y = (i / width) as u32;
+            bounds = (
+                bounds.0.min(x),
+                bounds.1.min(y),
+                bounds.2.max(x),
+                bounds.3.max(y),
+            );
+
+            for (&a, &b) in old_px.iter().zip(new_px) {
+                if a != b {
+                    changed_samples += 1;
+                    if a ^ b == 1 {
+                        lsb_only_samples += 1;
+                    }
+                    max_delta = max_delta.max(a.abs_diff(b));
+                }
+            }
+        }
+
+        if changed_pixels == 0 {
+            return PixelDiff::Identical;
+        }
+
+        PixelDiff::Changed {
+            total_pixels: old_pixels.samples().len() / channels,
+            changed_pixels,
+            changed_samples,
+            lsb_only_samples,
+            max_delta,
+            bounds,
+        }
+    }
+}
+
+/// A chunk level comparison of two PNG files
+#[derive(Debug, Clone)]
+pub struct PngDiff {
+    changes: Vec<ChunkChange>,
+    pixels: Option<PixelDiff>,
+}
+
+impl PngDiff {
+    /// Aligns the chunks of `old` and `new` and classifies every chunk.
+    /// Chunks that appear in the same relative order in both files are matched first, the
+    /// remaining identical chunks are reported as moved and the remaining chunks of the same
+    /// type are paired up in order as modified.
+    pub fn new(old: &Png, new: &Png) -> Self {
+        let a = old.chunks();
+        let b = new.chunks();
+
+        let mut changes = Vec::new();
+        let mut removed: Vec<usize> = Vec::new();
+        let mut inserted: Vec<usize> = Vec::new();
+
+        for step in align(a, b) {
+            match step {
+                (Some(i), Some(j)) => changes.push(ChunkChange::Unchanged {
+                    chunk_type: a[i].chunk_type().clone(),
+                    old_index: i,
+                    new_index: j,
+                }),
+                (Some(i), None) => removed.push(i),
+                (None, Some(j)) => inserted.push(j),
+                (None, None) => unreachable!(),
+            }
+        }
+
+        // identical chunks that the alignment couldn't keep in order were reordered
+        inserted.retain(
+            |&j| match removed.iter().position(|&i| same_chunk(&a[i], &b[j])) {
+                Some(pos) => {
+                    changes.push(ChunkChange::Moved {
+                        chunk_type: b[j].chunk_type().clone(),
+                        old_index: removed.remove(pos),
+                        new_index: j,
+                    });
+                    false
+                }
+                None => true,
+            },
+        );
+
+        inserted.retain(|&j| {
+            match removed
+                .iter()
+                .position(|&i| a[i].chunk_type() == b[j].chunk_type())
+            {
+                Some(pos) => {
+                    let i = removed.remove(pos);
+                    changes.push(ChunkChange::Modified {
+                        chunk_type: b[j].chunk_type().clone(),
+                        old_index: i,
+                        new_index: j,
+                        bytes: byte_changes(a[i].data(), b[j].data()),
+                    });
+                    false
+                }
+                None => true,
+            }
+        });
+
+        changes.extend(removed.into_iter().map(|i| ChunkChange::Removed {
+            chunk_type: a[i].chunk_type().clone(),
+            old_index: i,
+        }));
+        changes.extend(inserted.into_iter().map(|j| ChunkChange::Inserted {
+            chunk_type: b[j].chunk_type().clone(),
+            new_index: j,
+        }));
+        changes.sort_by_key(ChunkChange::sort_key);
+
+        let pixels = if old.image_data() != new.image_data() {
+            Some(PixelDiff::new(old, new))
+        } else {
+            None
+        };
+
+        PngDiff { changes, pixels }
+    }
+
+    /// Every chunk of both files, in new file order
+    pub fn changes(&self) -> &[ChunkChange] {
+        &self.changes
+    }
+
+    /// The pixel level summary, only present when the `IDAT` data differs
+    pub fn pixels(&self) -> Option<&PixelDiff> {
+        self.pixels.as_ref()
+    }
+
+    /// Returns true if both files contain exactly the same chunks in the same order
+    pub fn is_identical(&self) -> bool {
+        self.changes
+            .iter()
+            .all(|change| matches!(change, ChunkChange::Unchanged { .. }))
+    }
+}
+
+fn same_chunk(a: &Chunk, b: &Chunk) -> bool {
+    a.chunk_type() == b.chunk_type() && a.data() == b.data()
+}
+
+/// Longest common subsequence alignment of two chunk lists. Each step is a pair of indices
+/// where `None` on one side means the chunk only exists on the other side.
+fn align(a: &[Chunk], b: &[Chunk]) -> Vec<(Option<usize>, Option<usize>)> {
+    let mut lengths = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lengths[i][j] = if same_chunk(&a[i], &b[j]) {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut steps = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if same_chunk(&a[i], &b[j]) {
+            steps.push((Some(i), Some(j)));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            steps.push((Some(i), None));
+            i += 1;
+        } else {
+            steps.push((None, Some(j)));
+            j += 1;
+        }
+    }
+    steps.extend((i..a.len()).map(|i| (Some(i), None)));
+    steps.extend((j..b.len()).map(|j| (None, Some(j))));
+
+    steps
+}
+
+/// Compares `old` and `new` byte by byte and groups differing bytes into runs. Bytes past the
+/// end of the shorter side are reported as a final run.
+fn byte_changes(old: &[u8], new: &[u8]) -> Option<Vec<ByteChange>> {
+    if old.len() > BYTE_DIFF_LIMIT || new.len() > BYTE_DIFF_LIMIT {
+        return None;
+    }
+
+    let mut changes: Vec<ByteChange> = Vec::new();
+    let common = old.len().min(new.len());
+
+    for offset in 0..common {
+        if old[offset] == new[offset] {
+            continue;
+        }
+        match changes.last_mut() {
+            Some(last) if last.offset + last.old.len() == offset => {
+                last.old.push(old[offset]);
+                last.new.push(new[offset]);
+            }
+            _ => changes.push(ByteChange {
+                offset,
+                old: vec![old[offset]],
+                new: vec![new[offset]],
+            }),
+        }
+    }
+
+    if old.len() != new.len() {
+        changes.push(ByteChange {
+            offset: common,
+            old: old[common..].to_vec(),
+            new: new[common..].to_vec(),
+        });
+    }
+
+    Some(changes)
+}
+
+impl fmt::Display for ByteChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "@{}: {:02x?} -> {:02x?}",
+            self.offset, self.old, self.new
+        )
+    }
+}
+
+impl fmt::Display for ChunkChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChunkChange::Unchanged {
+                chunk_type,
+                old_index,
+                new_index,
+            } => write!(f, "  {} #{} -> #{}", chunk_type, old_index, new_index),
+            ChunkChange::Moved {
+                chunk_type,
+                old_index,
+                new_index,
+            } => write!(f, "~ {} moved #{} -> #{}", chunk_type, old_index, new_index),
+            ChunkChange::Modified {
+                chunk_type,
+                old_index,
+                new_index,
+                bytes,
+            } => {
+                write!(
+                    f,
+                    "* {} modified #{} -> #{}",
+                    chunk_type, old_index, new_index
+                )?;
+                if let Some(bytes) = bytes {
+                    for change in bytes {
+                        write!(f, "\n    {}", change)?;
+                    }
+                }
+                Ok(())
+            }
+            ChunkChange::Removed {
+                chunk_type,
+                old_index,
+            } => write!(f, "- {} removed #{}", chunk_type, old_index),
+            ChunkChange::Inserted {
+                chunk_type,
+                new_index,
+            } => write!(f, "+ {} inserted #{}", chunk_type, new_index),
+        }
+    }
+}
+
+impl fmt::Display for PixelDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PixelDiff::Identical => write!(f, "Pixels: identical (image data re-encoded)"),
+            PixelDiff::HeaderChanged { old, new } => {
+                write!(f, "Pixels: image format changed from {} to {}", old, new)
+            }
+            PixelDiff::Changed {
+                total_pixels,
+                changed_pixels,
+                changed_samples,
+                lsb_only_samples,
+                max_delta,
+                bounds,
+            } => {
+                writeln!(
+                    f,
+                    "Pixels: {} of {} changed ({:.2}%)",
+                    changed_pixels,
+                    total_pixels,
+                    *changed_pixels as f64 * 100.0 / *total_pixels as f64
+                )?;
+                writeln!(
+                    f,
+                    "  Samples changed: {} ({} in the LSB only)",
+                    changed_samples, lsb_only_samples
+                )?;
+                writeln!(f, "  Max sample delta: {}", max_delta)?;
+                write!(
+                    f,
+                    "  Bounds: ({}, {}) - ({}, {})",
+                    bounds.0, bounds.1, bounds.2, bounds.3
+                )
+            }
+            PixelDiff::Undecodable(reason) => write!(f, "Pixels: could not decode ({})", reason),
+        }
+    }
+}
+
+impl fmt::Display for PngDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_identical() {
+            return write!(f, "Files contain identical chunks");
+        }
+
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        if let Some(pixels) = &self.pixels {
+            write!(f, "{}", pixels)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixels::ColorType;
+    use std::str::FromStr;
+
+    fn chunk_from_strings(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(
+            ChunkType::from_str(chunk_type).unwrap(),
+            data.bytes().collect(),
+        )
+    }
+
+    fn testing_png(chunks: &[(&str, &str)]) -> Png {
+        Png::from_chunks(
+            chunks
+                .iter()
+                .map(|(chunk_type, data)| chunk_from_strings(chunk_type, data))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_identical() {
+        let a = testing_png(&[("FrSt", "one"), ("miDl", "two")]);
+        let b = testing_png(&[("FrSt", "one"), ("miDl", "two")]);
+        let diff = PngDiff::new(&a, &b);
+        assert!(diff.is_identical());
+        assert!(diff.pixels().is_none());
+    }
+
+    #[test]
+    fn test_inserted_and_removed() {
+        let a = testing_png(&[("FrSt", "one"), ("miDl", "two"), ("LASt", "end")]);
+        let b = testing_png(&[("FrSt", "one"), ("neWw", "new"), ("LASt", "end")]);
+        let diff = PngDiff::new(&a, &b);
+
+        assert!(diff.changes().contains(&ChunkChange::Removed {
+            chunk_type: ChunkType::from_str("miDl").unwrap(),
+            old_index: 1,
+        }));
+        assert!(diff.changes().contains(&ChunkChange::Inserted {
+            chunk_type: ChunkType::from_str("neWw").unwrap(),
+            new_index: 1,
+        }));
+    }
+
+    #[test]
+    fn test_moved() {
+        let a = testing_png(&[("FrSt", "one"), ("miDl", "two"), ("LASt", "end")]);
+        let b = testing_png(&[("miDl", "two"), ("FrSt", "one"), ("LASt", "end")]);
+        let diff = PngDiff::new(&a, &b);
+
+        let moved = diff
+            .changes()
+            .iter()
+            .filter(|change| matches!(change, ChunkChange::Moved { .. }))
+            .count();
+        assert_eq!(moved, 1);
+    }
+
+    #[test]
+    fn test_modified_byte_changes() {
+        let a = testing_png(&[("miDl", "hello world")]);
+        let b = testing_png(&[("miDl", "hellO world!")]);
+        let diff = PngDiff::new(&a, &b);
+
+        assert_eq!(
+            diff.changes(),
+            &[ChunkChange::Modified {
+                chunk_type: ChunkType::from_str("miDl").unwrap(),
+                old_index: 0,
+                new_index: 0,
+                bytes: Some(vec![
+                    ByteChange {
+                        offset: 4,
+                        old: vec![b'o'],
+                        new: vec![b'O'],
+                    },
+                    ByteChange {
+                        offset: 11,
+                        old: vec![],
+                        new: vec![b'!'],
+                    },
+                ]),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_large_chunk_has_no_byte_changes() {
+        let big_a = "a".repeat(BYTE_DIFF_LIMIT + 1);
+        let big_b = "b".repeat(BYTE_DIFF_LIMIT + 1);
+        let diff = PngDiff::new(
+            &testing_png(&[("miDl", &big_a)]),
+            &testing_png(&[("miDl", &big_b)]),
+        );
+        assert!(matches!(
+            diff.changes()[0],
+            ChunkChange::Modified { bytes: None, .. }
+        ));
+    }
+
+    #[test]
+    fn test_pixel_diff() {
+        let header = ImageHeader {
+            width: 4,
+            height: 2,
+            bit_depth: 8,
+            color_type: ColorType::Grayscale,
+            interlaced: false,
+        };
+        let old_pixels = PixelData::new(header, vec![10; 8]).unwrap();
+        let mut new_pixels = old_pixels.clone();
+        new_pixels.samples_mut()[5] = 11;
+        new_pixels.samples_mut()[6] = 20;
+
+        let to_png = |pixels: &PixelData| {
+            Png::from_chunks(vec![
+                header.to_chunk(),
+                Chunk::new(ChunkType::new(b"IDAT"), pixels.encode().unwrap()),
+                Chunk::new(ChunkType::new(b"IEND"), Vec::new()),
+            ])
+        };
+
+        let diff = PngDiff::new(&to_png(&old_pixels), &to_png(&new_pixels));
+        assert_eq!(
+            diff.pixels(),
+            Some(&PixelDiff::Changed {
+                total_pixels: 8,
+                changed_pixels: 2,
+                changed_samples: 2,
+                lsb_only_samples: 1,
+                max_delta: 10,
+                bounds: (1, 1, 2, 1),
+            })
+        );
+    }
+}
@@ -0,0 +1,637 @@
+use crate::chunk::Chunk;
+use crate::chunk_types::ChunkType;
+use crate::png::Png;
+use crate::{Error, Result};
+
+use std::collections::HashSet;
+use std::fmt;
+use std::ops::Range;
+
+/// Header some writers put in front of the TIFF structure although PNG doesn't allow it
+const EXIF_PREFIX: &[u8] = b"Exif\0\0";
+
+/// Tag in the primary IFD pointing at the EXIF sub-IFD
+const EXIF_IFD_POINTER: u16 = 0x8769;
+
+/// Tag in the primary IFD pointing at the GPS sub-IFD
+const GPS_IFD_POINTER: u16 = 0x8825;
+
+/// Bytes one IFD entry takes up: tag, type, count and value or offset
+const ENTRY_LEN: usize = 12;
+
+/// The directory a tag was read from. Tag numbers only have a meaning within their directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Ifd {
+    /// IFD0, describing the main image
+    Primary,
+    /// IFD1, describing the thumbnail
+    Thumbnail,
+    Exif,
+    Gps,
+}
+
+/// A decoded tag value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Byte(Vec<u8>),
+    Ascii(String),
+    Short(Vec<u16>),
+    Long(Vec<u32>),
+    Rational(Vec<(u32, u32)>),
+    SignedLong(Vec<i32>),
+    SignedRational(Vec<(i32, i32)>),
+    Undefined(Vec<u8>),
+}
+
+impl Value {
+    /// The number at `index` of a numeric value, with rationals divided out
+    pub fn as_f64(&self, index: usize) -> Option<f64> {
+        match self {
+            Value::Byte(v) => v.get(index).map(|&n| n as f64),
+            Value::Short(v) => v.get(index).map(|&n| n as f64),
+            Value::Long(v) => v.get(index).map(|&n| n as f64),
+            Value::SignedLong(v) => v.get(index).map(|&n| n as f64),
+            Value::Rational(v) => v
+                .get(index)
+                .filter(|(_, d)| *d != 0)
+                .map(|&(n, d)| n as f64 / d as f64),
+            Value::SignedRational(v) => v
+                .get(index)
+                .filter(|(_, d)| *d != 0)
+                .map(|&(n, d)| n as f64 / d as f64),
+            Value::Ascii(_) | Value::Undefined(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn list<T: fmt::Display>(values: &[T]) -> String {
+            values
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+
+        match self {
+            Value::Ascii(text) => write!(f, "{}", text),
+            Value::Byte(v) => write!(f, "{}", list(v)),
+            Value::Short(v) => write!(f, "{}", list(v)),
+            Value::Long(v) => write!(f, "{}", list(v)),
+            Value::SignedLong(v) => write!(f, "{}", list(v)),
+            Value::Rational(v) => write!(
+                f,
+                "{}",
+                list(
+                    &v.iter()
+                        .map(|(n, d)| format!("{}/{}", n, d))
+                        .collect::<Vec<_>>()
+                )
+            ),
+            Value::SignedRational(v) => write!(
+                f,
+                "{}",
+                list(
+                    &v.iter()
+                        .map(|(n, d)| format!("{}/{}", n, d))
+                        .collect::<Vec<_>>()
+                )
+            ),
+            Value::Undefined(v) => write!(f, "{} bytes", v.len()),
+        }
+    }
+}
+
+/// One tag of a directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub ifd: Ifd,
+    pub tag: u16,
+    pub value: Value,
+}
+
+impl Entry {
+    /// Name of the tag if it's one of the common ones
+    pub fn name(&self) -> Option<&'static str> {
+        let name = match (self.ifd, self.tag) {
+            (Ifd::Gps, 0x00) => "GPSVersionID",
+            (Ifd::Gps, 0x01) => "GPSLatitudeRef",
+            (Ifd::Gps, 0x02) => "GPSLatitude",
+            (Ifd::Gps, 0x03) => "GPSLongitudeRef",
+            (Ifd::Gps, 0x04) => "GPSLongitude",
+            (Ifd::Gps, 0x05) => "GPSAltitudeRef",
+            (Ifd::Gps, 0x06) => "GPSAltitude",
+            (Ifd::Gps, 0x07) => "GPSTimeStamp",
+            (Ifd::Gps, 0x12) => "GPSMapDatum",
+            (Ifd::Gps, 0x1d) => "GPSDateStamp",
+            (Ifd::Gps, _) => return None,
+            (_, 0x010e) => "ImageDescription",
+            (_, 0x010f) => "Make",
+            (_, 0x0110) => "Model",
+            (_, 0x0112) => "Orientation",
+            (_, 0x011a) => "XResolution",
+            (_, 0x011b) => "YResolution",
+            (_, 0x0128) => "ResolutionUnit",
+            (_, 0x0131) => "Software",
+            (_, 0x0132) => "DateTime",
+            (_, 0x013b) => "Artist",
+            (_, 0x8298) => "Copyright",
+            (_, 0x829a) => "ExposureTime",
+            (_, 0x829d) => "FNumber",
+            (_, 0x8827) => "ISOSpeedRatings",
+            (_, 0x9003) => "DateTimeOriginal",
+            (_, 0x9004) => "DateTimeDigitized",
+            (_, 0x920a) => "FocalLength",
+            (_, 0xa430) => "CameraOwnerName",
+            (_, 0xa431) => "BodySerialNumber",
+            (_, 0xa434) => "LensModel",
+            _ => return None,
+        };
+        Some(name)
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{}: ", name)?,
+            None => write!(f, "{:?} tag 0x{:04x}: ", self.ifd, self.tag)?,
+        }
+
+        let orientation = match self.value.as_f64(0) {
+            Some(1.0) => "normal",
+            Some(2.0) => "mirrored horizontally",
+            Some(3.0) => "rotated 180°",
+            Some(4.0) => "mirrored vertically",
+            Some(5.0) => "mirrored horizontally, rotated 270° clockwise",
+            Some(6.0) => "rotated 90° clockwise",
+            Some(7.0) => "mirrored horizontally, rotated 90° clockwise",
+            Some(8.0) => "rotated 270° clockwise",
+            _ => "",
+        };
+        match self.name() {
+            Some("Orientation") if !orientation.is_empty() => write!(f, "{}", orientation),
+            _ => write!(f, "{}", self.value),
+        }
+    }
+}
+
+/// Where one directory was found, so it can be edited in place
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Directory {
+    ifd: Ifd,
+    offset: usize,
+    count: usize,
+    /// Values stored outside the directory because they don't fit 4 bytes
+    value_ranges: Vec<Range<usize>>,
+}
+
+/// The contents of an `eXIf` chunk: a TIFF structure holding EXIF tags
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exif {
+    data: Vec<u8>,
+    /// Where the TIFF header starts, offsets in the structure are relative to it
+    base: usize,
+    little_endian: bool,
+    entries: Vec<Entry>,
+    directories: Vec<Directory>,
+}
+
+impl Exif {
+    /// Reads the `eXIf` chunk of `png`, if it has one
+    pub fn from_png(png: &Png) -> Result<Option<Self>> {
+        png.chunk_by_type("eXIf")
+            .map(|chunk| Exif::try_from(chunk.data()))
+            .transpose()
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk::new(ChunkType::new(b"eXIf"), self.data.clone())
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn is_little_endian(&self) -> bool {
+        self.little_endian
+    }
+
+    /// The first entry of `tag` in `ifd`
+    pub fn get(&self, ifd: Ifd, tag: u16) -> Option<&Entry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.ifd == ifd && entry.tag == tag)
+    }
+
+    /// Latitude and longitude in decimal degrees, negative to the south and west
+    pub fn gps_position(&self) -> Option<(f64, f64)> {
+        let coordinate = |value_tag: u16, ref_tag: u16, negative: &str| -> Option<f64> {
+            let value = &self.get(Ifd::Gps, value_tag)?.value;
+            let degrees = value.as_f64(0)?
+                + value.as_f64(1).unwrap_or(0.0) / 60.0
+                + value.as_f64(2).unwrap_or(0.0) / 3600.0;
+            match self.get(Ifd::Gps, ref_tag).map(|entry| &entry.value) {
+                Some(Value::Ascii(reference)) if reference == negative => Some(-degrees),
+                _ => Some(degrees),
+            }
+        };
+
+        Some((coordinate(0x02, 0x01, "S")?, coordinate(0x04, 0x03, "W")?))
+    }
+
+    pub fn has_gps(&self) -> bool {
+        self.directories.iter().any(|dir| dir.ifd == Ifd::Gps)
+            || self.get(Ifd::Primary, GPS_IFD_POINTER).is_some()
+    }
+
+    /// Removes the GPS directory and the pointer to it. Everything else stays at the same
+    /// offset so tags this parser doesn't understand, like maker notes, keep working. The
+    /// freed bytes are zeroed so the coordinates don't linger in the file.
+    pub fn remove_gps(&mut self) -> Result<bool> {
+        let Some(primary) = self
+            .directories
+            .iter()
+            .find(|dir| dir.ifd == Ifd::Primary)
+            .cloned()
+        else {
+            return Ok(false);
+        };
+        let entries_start = primary.offset + 2;
+        let Some(pointer_index) = (0..primary.count)
+            .find(|i| self.read_u16(entries_start + i * ENTRY_LEN) == Some(GPS_IFD_POINTER))
+        else {
+            return Ok(false);
+        };
+
+        // shift the following entries and the next IFD offset over the pointer entry
+        let entry = entries_start + pointer_index * ENTRY_LEN;
+        let end = entries_start + primary.count * ENTRY_LEN + 4;
+        self.data.copy_within(entry + ENTRY_LEN..end, entry);
+        self.data[end - ENTRY_LEN..end].fill(0);
+        let count = (primary.count - 1) as u16;
+        let count_bytes = if self.little_endian {
+            count.to_le_bytes()
+        } else {
+            count.to_be_bytes()
+        };
+        self.data[primary.offset..primary.offset + 2].copy_from_slice(&count_bytes);
+
+        for gps in self.directories.iter().filter(|dir| dir.ifd == Ifd::Gps) {
+            self.data[gps.offset..gps.offset + 2 + gps.count * ENTRY_LEN + 4].fill(0);
+            for range in &gps.value_ranges {
+                self.data[range.clone()].fill(0);
+            }
+        }
+
+        *self = Exif::try_from(&self.data[..])?;
+        Ok(true)
+    }
+
+    fn read_u16(&self, offset: usize) -> Option<u16> {
+        let bytes = self.data.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn read_u32(&self, offset: usize) -> Option<u32> {
+        let bytes = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    /// Reads the directory at `offset` (relative to the TIFF header), its entries and the
+    /// sub-directories it points to. Returns the offset of the next directory.
+    fn read_directory(
+        &mut self,
+        ifd: Ifd,
+        offset: usize,
+        visited: &mut HashSet<usize>,
+    ) -> Result<usize> {
+        let start = self.base + offset;
+        if !visited.insert(start) {
+            return Err("EXIF directories form a loop".into());
+        }
+        let truncated = || -> Error { format!("{:?} IFD is truncated", ifd).into() };
+
+        let count = self.read_u16(start).ok_or_else(truncated)? as usize;
+        let mut directory = Directory {
+            ifd,
+            offset: start,
+            count,
+            value_ranges: Vec::new(),
+        };
+        let mut sub_directories = Vec::new();
+
+        for index in 0..count {
+            let entry = start + 2 + index * ENTRY_LEN;
+            let tag = self.read_u16(entry).ok_or_else(truncated)?;
+            let field_type = self.read_u16(entry + 2).ok_or_else(truncated)?;
+            let value_count = self.read_u32(entry + 4).ok_or_else(truncated)? as usize;
+
+            // unknown types are skipped as the TIFF spec asks
+            let Some(size) = type_size(field_type) else {
+                continue;
+            };
+            let len = size
+                .checked_mul(value_count)
+                .ok_or("EXIF value is too large")?;
+            let value_start = if len <= 4 {
+                entry + 8
+            } else {
+                let value_start =
+                    self.base + self.read_u32(entry + 8).ok_or_else(truncated)? as usize;
+                directory.value_ranges.push(value_start..value_start + len);
+                value_start
+            };
+            let bytes = self
+                .data
+                .get(value_start..value_start + len)
+                .ok_or_else(|| format!("EXIF tag 0x{:04x} points outside the data", tag))?;
+            let value = self.decode_value(field_type, bytes);
+
+            if ifd == Ifd::Primary
+                && let Some(pointer) = value.as_f64(0)
+            {
+                match tag {
+                    EXIF_IFD_POINTER => sub_directories.push((Ifd::Exif, pointer as usize)),
+                    GPS_IFD_POINTER => sub_directories.push((Ifd::Gps, pointer as usize)),
+                    _ => {}
+                }
+            }
+            self.entries.push(Entry { ifd, tag, value });
+        }
+
+        let next = self
+            .read_u32(start + 2 + count * ENTRY_LEN)
+            .ok_or_else(truncated)? as usize;
+        self.directories.push(directory);
+        for (sub_ifd, sub_offset) in sub_directories {
+            self.read_directory(sub_ifd, sub_offset, visited)?;
+        }
+
+        Ok(next)
+    }
+
+    fn decode_value(&self, field_type: u16, bytes: &[u8]) -> Value {
+        let u16s = || {
+            bytes
+                .chunks(2)
+                .map(|b| {
+                    let b = [b[0], b[1]];
+                    if self.little_endian {
+                        u16::from_le_bytes(b)
+                    } else {
+                        u16::from_be_bytes(b)
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+        let u32s = || {
+            bytes
+                .chunks(4)
+                .map(|b| {
+                    let b = [b[0], b[1], b[2], b[3]];
+                    if self.little_endian {
+                        u32::from_le_bytes(b)
+                    } else {
+                        u32::from_be_bytes(b)
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+
+        match field_type {
+            1 | 6 => Value::Byte(bytes.to_vec()),
+            2 => Value::Ascii(
+                String::from_utf8_lossy(bytes)
+                    .trim_end_matches('\0')
+                    .to_string(),
+            ),
+            3 | 8 => Value::Short(u16s()),
+            4 => Value::Long(u32s()),
+            5 => Value::Rational(u32s().chunks(2).map(|r| (r[0], r[1])).collect()),
+            9 => Value::SignedLong(u32s().into_iter().map(|n| n as i32).collect()),
+            10 => Value::SignedRational(
+                u32s()
+                    .chunks(2)
+                    .map(|r| (r[0] as i32, r[1] as i32))
+                    .collect(),
+            ),
+            _ => Value::Undefined(bytes.to_vec()),
+        }
+    }
+}
+
+/// Bytes one value of a TIFF field type takes up, `None` for unknown types
+fn type_size(field_type: u16) -> Option<usize> {
+    match field_type {
+        1 | 2 | 6 | 7 => Some(1),
+        3 | 8 => Some(2),
+        4 | 9 | 11 => Some(4),
+        5 | 10 | 12 => Some(8),
+        _ => None,
+    }
+}
+
+impl TryFrom<&[u8]> for Exif {
+    type Error = Error;
+
+    fn try_from(data: &[u8]) -> Result<Self> {
+        let base = if data.starts_with(EXIF_PREFIX) {
+            EXIF_PREFIX.len()
+        } else {
+            0
+        };
+        let little_endian = match data.get(base..base + 2) {
+            Some(b"II") => true,
+            Some(b"MM") => false,
+            _ => return Err("eXIf doesn't start with a TIFF byte order mark".into()),
+        };
+
+        let mut exif = Exif {
+            data: data.to_vec(),
+            base,
+            little_endian,
+            entries: Vec::new(),
+            directories: Vec::new(),
+        };
+        if exif.read_u16(base + 2) != Some(42) {
+            return Err("eXIf has an invalid TIFF header".into());
+        }
+
+        let mut visited = HashSet::new();
+        let primary = exif
+            .read_u32(base + 4)
+            .ok_or("eXIf has an invalid TIFF header")? as usize;
+        let thumbnail = exif.read_directory(Ifd::Primary, primary, &mut visited)?;
+        if thumbnail != 0 {
+            exif.read_directory(Ifd::Thumbnail, thumbnail, &mut visited)?;
+        }
+
+        Ok(exif)
+    }
+}
+
+impl fmt::Display for Exif {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            // pointers are structure, not information
+            if entry.ifd == Ifd::Primary && [EXIF_IFD_POINTER, GPS_IFD_POINTER].contains(&entry.tag)
+            {
+                continue;
+            }
+            writeln!(f, "{}", entry)?;
+        }
+        if let Some((latitude, longitude)) = self.gps_position() {
+            writeln!(f, "GPS position: {:.6}, {:.6}", latitude, longitude)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a TIFF structure with Orientation, DateTime and Make in IFD0, and a GPS IFD
+    /// placing the image at 52°22'12"N 4°53'42"W
+    fn testing_exif(little_endian: bool) -> Vec<u8> {
+        let u16b = |n: u16| {
+            if little_endian {
+                n.to_le_bytes()
+            } else {
+                n.to_be_bytes()
+            }
+        };
+        let u32b = |n: u32| {
+            if little_endian {
+                n.to_le_bytes()
+            } else {
+                n.to_be_bytes()
+            }
+        };
+        let entry = |tag: u16, field_type: u16, count: u32, value: [u8; 4]| {
+            let mut bytes = u16b(tag).to_vec();
+            bytes.extend(u16b(field_type));
+            bytes.extend(u32b(count));
+            bytes.extend(value);
+            bytes
+        };
+        let short = |n: u16| {
+            let mut value = [0; 4];
+            value[..2].copy_from_slice(&u16b(n));
+            value
+        };
+
+        // IFD0 at 8 with 4 entries ends at 8 + 2 + 48 + 4 = 62, values follow
+        let date_time = b"2024:05:01 12:30:00\0";
+        let make = b"Pngme Camera\0";
+        let gps_offset = 62 + date_time.len() + make.len();
+        let mut data = if little_endian {
+            b"II".to_vec()
+        } else {
+            b"MM".to_vec()
+        };
+        data.extend(u16b(42));
+        data.extend(u32b(8));
+        data.extend(u16b(4));
+        data.extend(entry(
+            0x010f,
+            2,
+            make.len() as u32,
+            u32b(62 + date_time.len() as u32),
+        ));
+        data.extend(entry(0x0112, 3, 1, short(6)));
+        data.extend(entry(0x0132, 2, date_time.len() as u32, u32b(62)));
+        data.extend(entry(GPS_IFD_POINTER, 4, 1, u32b(gps_offset as u32)));
+        data.extend(u32b(0));
+        data.extend(date_time);
+        data.extend(make);
+
+        // GPS IFD with 4 entries, its rationals follow at gps_offset + 54
+        let rationals = gps_offset as u32 + 2 + 48 + 4;
+        data.extend(u16b(4));
+        data.extend(entry(0x01, 2, 2, *b"N\0\0\0"));
+        data.extend(entry(0x02, 5, 3, u32b(rationals)));
+        data.extend(entry(0x03, 2, 2, *b"W\0\0\0"));
+        data.extend(entry(0x04, 5, 3, u32b(rationals + 24)));
+        data.extend(u32b(0));
+        for (n, d) in [(52, 1), (22, 1), (12, 1), (4, 1), (53, 1), (4200, 100)] {
+            data.extend(u32b(n));
+            data.extend(u32b(d));
+        }
+        data
+    }
+
+    #[test]
+    fn test_parse_both_byte_orders() {
+        for little_endian in [true, false] {
+            let exif = Exif::try_from(&testing_exif(little_endian)[..]).unwrap();
+            assert_eq!(exif.is_little_endian(), little_endian);
+            assert_eq!(
+                exif.get(Ifd::Primary, 0x0132).unwrap().value,
+                Value::Ascii("2024:05:01 12:30:00".to_string())
+            );
+            assert_eq!(
+                exif.get(Ifd::Primary, 0x0112).unwrap().value,
+                Value::Short(vec![6])
+            );
+            assert_eq!(
+                exif.get(Ifd::Primary, 0x0112).unwrap().to_string(),
+                "Orientation: rotated 90° clockwise"
+            );
+
+            let (latitude, longitude) = exif.gps_position().unwrap();
+            assert!((latitude - 52.37).abs() < 1e-9);
+            assert!((longitude + 4.895).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_exif_prefix_is_tolerated() {
+        let mut data = EXIF_PREFIX.to_vec();
+        data.extend(testing_exif(false));
+        let exif = Exif::try_from(&data[..]).unwrap();
+        assert!(exif.gps_position().is_some());
+    }
+
+    #[test]
+    fn test_invalid_data() {
+        assert!(Exif::try_from(&b"XX\0\x2a\0\0\0\x08"[..]).is_err());
+        let data = testing_exif(true);
+        assert!(Exif::try_from(&data[..40]).is_err());
+
+        // IFD0 pointing at itself as the next directory
+        let mut looped = data.clone();
+        looped[58..62].copy_from_slice(&8u32.to_le_bytes());
+        assert!(Exif::try_from(&looped[..]).is_err());
+    }
+
+    #[test]
+    fn test_remove_gps() {
+        for little_endian in [true, false] {
+            let mut exif = Exif::try_from(&testing_exif(little_endian)[..]).unwrap();
+            assert!(exif.has_gps());
+            assert!(exif.remove_gps().unwrap());
+
+            assert!(!exif.has_gps());
+            assert_eq!(exif.gps_position(), None);
+            assert!(exif.entries().iter().all(|entry| entry.ifd != Ifd::Gps));
+            assert_eq!(exif.entries().len(), 3);
+            assert!(exif.get(Ifd::Primary, 0x0132).is_some());
+            assert!(!exif.remove_gps().unwrap());
+
+            // the coordinates are gone from the bytes, not just unreferenced
+            let data = exif.to_chunk().data().to_vec();
+            assert!(!data.windows(2).any(|w| w == b"W\0"));
+        }
+    }
+}
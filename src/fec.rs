@@ -0,0 +1,445 @@
+use crate::Result;
+
+/// Largest number of symbols in one Reed-Solomon codeword over GF(256)
+pub const MAX_CODEWORD_LEN: usize = 255;
+
+/// Exponent and logarithm tables of GF(256) with the primitive polynomial 0x11d. The
+/// exponent table is doubled so products of two logarithms don't need a modulo.
+const TABLES: ([u8; 512], [u8; 256]) = build_tables();
+
+const fn build_tables() -> ([u8; 512], [u8; 256]) {
+    let mut exp = [0u8; 512];
+    let mut log = [0u8; 256];
+    let mut x: u16 = 1;
+    let mut i = 0;
+    while i < 255 {
+        exp[i] = x as u8;
+        log[x as usize] = i as u8;
+        x <<= 1;
+        if x & 0x100 != 0 {
+            x ^= 0x11d;
+        }
+        i += 1;
+    }
+    while i < 512 {
+        exp[i] = exp[i - 255];
+        i += 1;
+    }
+    (exp, log)
+}
+
+fn gf_mul(x: u8, y: u8) -> u8 {
+    if x == 0 || y == 0 {
+        return 0;
+    }
+    let (exp, log) = &TABLES;
+    exp[log[x as usize] as usize + log[y as usize] as usize]
+}
+
+fn gf_div(x: u8, y: u8) -> u8 {
+    if x == 0 {
+        return 0;
+    }
+    let (exp, log) = &TABLES;
+    exp[(log[x as usize] as usize + 255 - log[y as usize] as usize) % 255]
+}
+
+fn gf_pow(x: u8, power: i32) -> u8 {
+    let (exp, log) = &TABLES;
+    exp[(log[x as usize] as i32 * power).rem_euclid(255) as usize]
+}
+
+fn gf_inverse(x: u8) -> u8 {
+    gf_div(1, x)
+}
+
+// Polynomials are stored with the highest degree coefficient first.
+
+fn poly_scale(p: &[u8], x: u8) -> Vec<u8> {
+    p.iter().map(|&c| gf_mul(c, x)).collect()
+}
+
+fn poly_add(p: &[u8], q: &[u8]) -> Vec<u8> {
+    let len = p.len().max(q.len());
+    let mut sum = vec![0u8; len];
+    for (i, &c) in p.iter().enumerate() {
+        sum[i + len - p.len()] = c;
+    }
+    for (i, &c) in q.iter().enumerate() {
+        sum[i + len - q.len()] ^= c;
+    }
+    sum
+}
+
+fn poly_mul(p: &[u8], q: &[u8]) -> Vec<u8> {
+    let mut product = vec![0u8; p.len() + q.len() - 1];
+    for (j, &qc) in q.iter().enumerate() {
+        for (i, &pc) in p.iter().enumerate() {
+            product[i + j] ^= gf_mul(pc, qc);
+        }
+    }
+    product
+}
+
+fn poly_eval(p: &[u8], x: u8) -> u8 {
+    p.iter().skip(1).fold(p[0], |y, &c| gf_mul(y, x) ^ c)
+}
+
+/// Remainder of dividing `dividend` by the monic polynomial `divisor`
+fn poly_remainder(dividend: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut out = dividend.to_vec();
+    for i in 0..dividend.len().saturating_sub(divisor.len() - 1) {
+        let coef = out[i];
+        if coef != 0 {
+            for (j, &d) in divisor.iter().enumerate().skip(1) {
+                out[i + j] ^= gf_mul(d, coef);
+            }
+        }
+    }
+    out.split_off(out.len() - (divisor.len() - 1))
+}
+
+fn generator_poly(parity: usize) -> Vec<u8> {
+    (0..parity).fold(vec![1], |g, i| poly_mul(&g, &[1, gf_pow(2, i as i32)]))
+}
+
+/// Appends `parity` Reed-Solomon check symbols to `data`. The codeword can't be longer
+/// than 255 bytes.
+pub fn encode_block(data: &[u8], parity: usize) -> Vec<u8> {
+    let mut padded = data.to_vec();
+    padded.resize(data.len() + parity, 0);
+
+    let mut codeword = data.to_vec();
+    codeword.extend(poly_remainder(&padded, &generator_poly(parity)));
+    codeword
+}
+
+/// Syndromes of a codeword, with a leading zero to keep the indices of the textbook
+/// algorithms
+fn syndromes(codeword: &[u8], parity: usize) -> Vec<u8> {
+    std::iter::once(0)
+        .chain((0..parity).map(|i| poly_eval(codeword, gf_pow(2, i as i32))))
+        .collect()
+}
+
+/// Berlekamp-Massey: the error locator polynomial for the syndromes
+fn error_locator(syndromes: &[u8], parity: usize) -> Result<Vec<u8>> {
+    let mut locator = vec![1u8];
+    let mut old_locator = vec![1u8];
+    let shift = syndromes.len() - parity;
+
+    for i in 0..parity {
+        let k = i + shift;
+        let mut delta = syndromes[k];
+        for j in 1..locator.len() {
+            delta ^= gf_mul(locator[locator.len() - 1 - j], syndromes[k - j]);
+        }
+        old_locator.push(0);
+        if delta != 0 {
+            if old_locator.len() > locator.len() {
+                let new_locator = poly_scale(&old_locator, delta);
+                old_locator = poly_scale(&locator, gf_inverse(delta));
+                locator = new_locator;
+            }
+            locator = poly_add(&locator, &poly_scale(&old_locator, delta));
+        }
+    }
+
+    let leading_zeros = locator.iter().take_while(|&&c| c == 0).count();
+    locator.drain(..leading_zeros);
+    if (locator.len() - 1) * 2 > parity {
+        return Err("Too many errors to correct".into());
+    }
+    Ok(locator)
+}
+
+/// Chien search: positions in the codeword where the errors are
+fn error_positions(reversed_locator: &[u8], len: usize) -> Result<Vec<usize>> {
+    let positions: Vec<usize> = (0..len)
+        .filter(|&i| poly_eval(reversed_locator, gf_pow(2, i as i32)) == 0)
+        .map(|i| len - 1 - i)
+        .collect();
+
+    if positions.len() != reversed_locator.len() - 1 {
+        return Err("Too many errors to correct".into());
+    }
+    Ok(positions)
+}
+
+/// Forney: corrects the errors at `positions` in place
+fn correct_errors(codeword: &mut [u8], syndromes: &[u8], positions: &[usize]) {
+    let coefficients: Vec<i32> = positions
+        .iter()
+        .map(|&p| (codeword.len() - 1 - p) as i32)
+        .collect();
+    let locator = coefficients.iter().fold(vec![1u8], |locator, &c| {
+        poly_mul(&locator, &poly_add(&[1], &[gf_pow(2, c), 0]))
+    });
+
+    // the error evaluator is (syndromes * locator) mod x^(errors + 1)
+    let reversed: Vec<u8> = syndromes.iter().rev().copied().collect();
+    let mut modulus = vec![0u8; locator.len() + 1];
+    modulus[0] = 1;
+    let evaluator = poly_remainder(&poly_mul(&reversed, &locator), &modulus);
+
+    let roots: Vec<u8> = coefficients.iter().map(|&c| gf_pow(2, c)).collect();
+    for (i, &root) in roots.iter().enumerate() {
+        let root_inverse = gf_inverse(root);
+        let derivative = roots
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .fold(1, |acc, (_, &other)| {
+                gf_mul(acc, 1 ^ gf_mul(root_inverse, other))
+            });
+        let y = gf_mul(root, poly_eval(&evaluator, root_inverse));
+        codeword[positions[i]] ^= gf_div(y, derivative);
+    }
+}
+
+/// Corrects up to `parity / 2` wrong bytes of `codeword` in place and returns how many
+/// were corrected
+pub fn decode_block(codeword: &mut [u8], parity: usize) -> Result<usize> {
+    let syndromes = syndromes(codeword, parity);
+    if syndromes.iter().all(|&s| s == 0) {
+        return Ok(0);
+    }
+
+    let mut locator = error_locator(&syndromes, parity)?;
+    locator.reverse();
+    let positions = error_positions(&locator, codeword.len())?;
+    correct_errors(codeword, &syndromes, &positions);
+
+    if self::syndromes(codeword, parity).iter().any(|&s| s != 0) {
+        return Err("Too many errors to correct".into());
+    }
+    Ok(positions.len())
+}
+
+/// Forward error correction settings for a payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FecOptions {
+    /// Check bytes per codeword, half as many wrong bytes can be corrected
+    pub redundancy: u8,
+    /// Minimum number of codewords the data is spread over. Bytes of the codewords are
+    /// interleaved so a burst of errors is shared between them.
+    pub interleave: u8,
+}
+
+impl FecOptions {
+    pub fn new(redundancy: u8, interleave: u8) -> Result<Self> {
+        if redundancy < 2 || redundancy as usize >= MAX_CODEWORD_LEN / 2 {
+            return Err("Redundancy must be between 2 and 126 bytes per codeword".into());
+        }
+        if interleave == 0 {
+            return Err("Interleave depth must be at least 1".into());
+        }
+
+        Ok(FecOptions {
+            redundancy,
+            interleave,
+        })
+    }
+
+    /// Number of codewords `len` bytes of data are split into
+    fn codewords(&self, len: usize) -> usize {
+        let data_per_codeword = MAX_CODEWORD_LEN - self.redundancy as usize;
+        len.div_ceil(data_per_codeword)
+            .max(self.interleave as usize)
+    }
+
+    /// Data bytes in each codeword, the data is dealt out round robin
+    fn data_lengths(&self, len: usize) -> Vec<usize> {
+        let codewords = self.codewords(len);
+        (0..codewords)
+            .map(|i| len / codewords + usize::from(i < len % codewords))
+            .collect()
+    }
+
+    /// Size of `len` bytes of data once protected
+    pub fn protected_len(&self, len: usize) -> usize {
+        len + self.codewords(len) * self.redundancy as usize
+    }
+
+    /// Largest amount of data that still fits into `space` bytes once protected
+    pub fn max_data_len(&self, space: usize) -> usize {
+        // protected_len only grows with the data length, so binary search for the limit
+        let (mut low, mut high) = (0, space + 1);
+        while low + 1 < high {
+            let middle = (low + high) / 2;
+            if self.protected_len(middle) <= space {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        if self.protected_len(low) <= space {
+            low
+        } else {
+            0
+        }
+    }
+}
+
+/// Splits `data` into interleaved Reed-Solomon codewords
+pub fn protect(data: &[u8], options: &FecOptions) -> Vec<u8> {
+    let lengths = options.data_lengths(data.len());
+    let codewords: Vec<Vec<u8>> = (0..lengths.len())
+        .map(|i| {
+            let block: Vec<u8> = data
+                .iter()
+                .skip(i)
+                .step_by(lengths.len())
+                .copied()
+                .collect();
+            encode_block(&block, options.redundancy as usize)
+        })
+        .collect();
+
+    interleave(&codewords)
+}
+
+/// Reverses `protect`, correcting what it can. Returns the `len` data bytes and the
+/// number of corrected bytes.
+pub fn recover(protected: &[u8], len: usize, options: &FecOptions) -> Result<(Vec<u8>, usize)> {
+    if protected.len() < options.protected_len(len) {
+        return Err("Protected data is cut off".into());
+    }
+
+    let lengths: Vec<usize> = options
+        .data_lengths(len)
+        .iter()
+        .map(|l| l + options.redundancy as usize)
+        .collect();
+    let mut codewords = deinterleave(protected, &lengths);
+
+    let mut corrected = 0;
+    for codeword in codewords.iter_mut() {
+        corrected += decode_block(codeword, options.redundancy as usize)?;
+    }
+
+    let mut data = vec![0u8; len];
+    for (i, codeword) in codewords.iter().enumerate() {
+        let data_len = codeword.len() - options.redundancy as usize;
+        for (j, &byte) in codeword[..data_len].iter().enumerate() {
+            data[i + j * codewords.len()] = byte;
+        }
+    }
+
+    Ok((data, corrected))
+}
+
+/// Writes the first byte of every codeword, then the second byte of every codeword, ...
+fn interleave(codewords: &[Vec<u8>]) -> Vec<u8> {
+    let longest = codewords.iter().map(Vec::len).max().unwrap_or(0);
+    (0..longest)
+        .flat_map(|i| codewords.iter().filter_map(move |c| c.get(i).copied()))
+        .collect()
+}
+
+fn deinterleave(bytes: &[u8], lengths: &[usize]) -> Vec<Vec<u8>> {
+    let mut codewords: Vec<Vec<u8>> = lengths.iter().map(|&l| Vec::with_capacity(l)).collect();
+    let longest = lengths.iter().copied().max().unwrap_or(0);
+    let mut source = bytes.iter();
+
+    for i in 0..longest {
+        for (codeword, &len) in codewords.iter_mut().zip(lengths) {
+            if i < len {
+                codeword.push(*source.next().unwrap_or(&0));
+            }
+        }
+    }
+    codewords
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gf_arithmetic() {
+        assert_eq!(gf_mul(2, 0x80), 0x1d);
+        for x in 1..=255u8 {
+            assert_eq!(gf_mul(x, gf_inverse(x)), 1);
+            assert_eq!(gf_div(gf_mul(x, 7), 7), x);
+        }
+    }
+
+    #[test]
+    fn test_block_round_trip() {
+        let codeword = encode_block(b"hello world", 10);
+        assert_eq!(codeword.len(), 21);
+        assert_eq!(&codeword[..11], b"hello world");
+
+        let mut clean = codeword.clone();
+        assert_eq!(decode_block(&mut clean, 10).unwrap(), 0);
+        assert_eq!(clean, codeword);
+    }
+
+    #[test]
+    fn test_block_corrects_errors() {
+        let codeword = encode_block(b"reed solomon at work", 10);
+        let mut damaged = codeword.clone();
+        for position in [0, 3, 7, 12, 25] {
+            damaged[position] ^= 0x5a;
+        }
+
+        assert_eq!(decode_block(&mut damaged, 10).unwrap(), 5);
+        assert_eq!(damaged, codeword);
+    }
+
+    #[test]
+    fn test_block_too_many_errors() {
+        let mut damaged = encode_block(b"reed solomon at work", 4);
+        for position in [0, 3, 7] {
+            damaged[position] ^= 0xff;
+        }
+        assert!(decode_block(&mut damaged, 4).is_err());
+    }
+
+    #[test]
+    fn test_options_validation() {
+        assert!(FecOptions::new(0, 1).is_err());
+        assert!(FecOptions::new(8, 0).is_err());
+        assert!(FecOptions::new(200, 1).is_err());
+        assert!(FecOptions::new(8, 4).is_ok());
+    }
+
+    #[test]
+    fn test_protect_and_recover() {
+        let options = FecOptions::new(8, 3).unwrap();
+        let data: Vec<u8> = (0..600).map(|i| (i * 31 % 256) as u8).collect();
+        let protected = protect(&data, &options);
+        assert_eq!(protected.len(), options.protected_len(data.len()));
+
+        let (recovered, corrected) = recover(&protected, data.len(), &options).unwrap();
+        assert_eq!(recovered, data);
+        assert_eq!(corrected, 0);
+    }
+
+    #[test]
+    fn test_max_data_len() {
+        let options = FecOptions::new(8, 2).unwrap();
+        for space in [0, 15, 16, 17, 300, 600, 1000] {
+            let len = options.max_data_len(space);
+            assert!(len == 0 || options.protected_len(len) <= space);
+            assert!(options.protected_len(len + 1) > space);
+        }
+    }
+
+    #[test]
+    fn test_interleaving_spreads_bursts() {
+        let options = FecOptions::new(4, 4).unwrap();
+        let data = b"a burst of errors is shared between codewords".to_vec();
+        let mut protected = protect(&data, &options);
+
+        // 8 consecutive bytes hit each of the 4 codewords twice, which 4 check bytes fix
+        for byte in &mut protected[10..18] {
+            *byte ^= 0xff;
+        }
+
+        let (recovered, corrected) = recover(&protected, data.len(), &options).unwrap();
+        assert_eq!(recovered, data);
+        assert_eq!(corrected, 8);
+    }
+}
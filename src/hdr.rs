@@ -0,0 +1,348 @@
+use crate::chunk::Chunk;
+use crate::chunk_types::ChunkType;
+use crate::png::{ChunkPosition, Png};
+use crate::{Error, Result};
+
+use std::fmt;
+
+/// Chromaticity steps per unit in `mDCV`, so one step is 0.00002
+pub const CHROMATICITY_STEPS: u32 = 50_000;
+
+/// Luminance steps per cd/m² in `mDCV` and `cLLI`, so one step is 0.0001 cd/m²
+pub const LUMINANCE_STEPS: u32 = 10_000;
+
+/// Converts a chromaticity coordinate to `mDCV` steps, which have to stay within 0..=1
+fn chromaticity_steps(value: f64) -> Result<u16> {
+    if !(0.0..=1.0).contains(&value) {
+        return Err(format!("Chromaticity {} is outside 0..=1", value).into());
+    }
+    Ok((value * CHROMATICITY_STEPS as f64).round() as u16)
+}
+
+/// Converts a luminance in cd/m² to steps of 0.0001 cd/m²
+fn luminance_steps(nits: f64) -> Result<u32> {
+    let steps = (nits * LUMINANCE_STEPS as f64).round();
+    if !(0.0..=u32::MAX as f64).contains(&steps) {
+        return Err(format!("Luminance {} cd/m² doesn't fit", nits).into());
+    }
+    Ok(steps as u32)
+}
+
+/// The contents of an `mDCV` chunk: the color volume of the display the image was
+/// mastered on, with chromaticities in steps of 0.00002 and luminance in 0.0001 cd/m²
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MasteringDisplay {
+    /// Red, green and blue primaries as x and y
+    pub primaries: [(u16, u16); 3],
+    pub white: (u16, u16),
+    pub max_luminance: u32,
+    pub min_luminance: u32,
+}
+
+impl MasteringDisplay {
+    /// Creates the chunk from chromaticity coordinates and luminances in cd/m²
+    pub fn new(
+        primaries: [(f64, f64); 3],
+        white: (f64, f64),
+        max_nits: f64,
+        min_nits: f64,
+    ) -> Result<Self> {
+        let point = |(x, y): (f64, f64)| -> Result<(u16, u16)> {
+            Ok((chromaticity_steps(x)?, chromaticity_steps(y)?))
+        };
+        let display = MasteringDisplay {
+            primaries: [
+                point(primaries[0])?,
+                point(primaries[1])?,
+                point(primaries[2])?,
+            ],
+            white: point(white)?,
+            max_luminance: luminance_steps(max_nits)?,
+            min_luminance: luminance_steps(min_nits)?,
+        };
+
+        match display.problems().into_iter().next() {
+            Some(problem) => Err(problem.into()),
+            None => Ok(display),
+        }
+    }
+
+    /// A chromaticity coordinate pair as x and y
+    pub fn chromaticity((x, y): (u16, u16)) -> (f64, f64) {
+        (
+            x as f64 / CHROMATICITY_STEPS as f64,
+            y as f64 / CHROMATICITY_STEPS as f64,
+        )
+    }
+
+    pub fn max_nits(&self) -> f64 {
+        self.max_luminance as f64 / LUMINANCE_STEPS as f64
+    }
+
+    pub fn min_nits(&self) -> f64 {
+        self.min_luminance as f64 / LUMINANCE_STEPS as f64
+    }
+
+    /// Values that parse but can't describe a real display
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let steps_per_unit = CHROMATICITY_STEPS as u16;
+        let points = self.primaries.iter().chain([&self.white]);
+        if points
+            .into_iter()
+            .any(|&(x, y)| x > steps_per_unit || y > steps_per_unit)
+        {
+            problems.push("mDCV chromaticities must be at most 1".to_string());
+        }
+        if self.min_luminance >= self.max_luminance {
+            problems.push(format!(
+                "mDCV minimum luminance {} cd/m² isn't below the maximum {} cd/m²",
+                self.min_nits(),
+                self.max_nits()
+            ));
+        }
+        problems
+    }
+
+    /// Reads the `mDCV` chunk of `png`, if it has one
+    pub fn from_png(png: &Png) -> Result<Option<Self>> {
+        png.chunk_by_type("mDCV")
+            .map(|chunk| MasteringDisplay::try_from(chunk.data()))
+            .transpose()
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data: Vec<u8> = self
+            .primaries
+            .iter()
+            .chain([&self.white])
+            .flat_map(|(x, y)| [x.to_be_bytes(), y.to_be_bytes()])
+            .flatten()
+            .collect();
+        data.extend(self.max_luminance.to_be_bytes());
+        data.extend(self.min_luminance.to_be_bytes());
+        Chunk::new(ChunkType::new(b"mDCV"), data)
+    }
+
+    /// Stores this in `png`, replacing its `mDCV` chunk or adding one after `IHDR`.
+    /// Returns the value it replaced.
+    pub fn apply(&self, png: &mut Png) -> Result<Option<MasteringDisplay>> {
+        let old = MasteringDisplay::from_png(png).ok().flatten();
+        png.set_chunk(self.to_chunk(), ChunkPosition::AfterIhdr)?;
+        Ok(old)
+    }
+}
+
+impl TryFrom<&[u8]> for MasteringDisplay {
+    type Error = Error;
+
+    fn try_from(data: &[u8]) -> Result<Self> {
+        if data.len() != 24 {
+            return Err(format!("mDCV must be 24 bytes long, not {}", data.len()).into());
+        }
+        let short = |index: usize| u16::from_be_bytes([data[index * 2], data[index * 2 + 1]]);
+        let long = |offset: usize| u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap());
+
+        Ok(MasteringDisplay {
+            primaries: [
+                (short(0), short(1)),
+                (short(2), short(3)),
+                (short(4), short(5)),
+            ],
+            white: (short(6), short(7)),
+            max_luminance: long(16),
+            min_luminance: long(20),
+        })
+    }
+}
+
+impl fmt::Display for MasteringDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, point) in ["red", "green", "blue", "white"]
+            .iter()
+            .zip(self.primaries.iter().chain([&self.white]))
+        {
+            let (x, y) = MasteringDisplay::chromaticity(*point);
+            write!(f, "{} {:.5},{:.5}, ", name, x, y)?;
+        }
+        write!(
+            f,
+            "luminance {:.4}-{:.4} cd/m²",
+            self.min_nits(),
+            self.max_nits()
+        )
+    }
+}
+
+/// The contents of a `cLLI` chunk: the brightest pixel and the brightest frame average
+/// of the content, in steps of 0.0001 cd/m²
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentLightLevel {
+    pub max_cll: u32,
+    pub max_fall: u32,
+}
+
+impl ContentLightLevel {
+    /// Creates the chunk from luminances in cd/m²
+    pub fn new(max_cll_nits: f64, max_fall_nits: f64) -> Result<Self> {
+        let level = ContentLightLevel {
+            max_cll: luminance_steps(max_cll_nits)?,
+            max_fall: luminance_steps(max_fall_nits)?,
+        };
+
+        match level.problems().into_iter().next() {
+            Some(problem) => Err(problem.into()),
+            None => Ok(level),
+        }
+    }
+
+    pub fn max_cll_nits(&self) -> f64 {
+        self.max_cll as f64 / LUMINANCE_STEPS as f64
+    }
+
+    pub fn max_fall_nits(&self) -> f64 {
+        self.max_fall as f64 / LUMINANCE_STEPS as f64
+    }
+
+    /// Values that parse but contradict each other
+    pub fn problems(&self) -> Vec<String> {
+        if self.max_fall > self.max_cll {
+            vec![format!(
+                "cLLI MaxFALL {} cd/m² is above MaxCLL {} cd/m²",
+                self.max_fall_nits(),
+                self.max_cll_nits()
+            )]
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Reads the `cLLI` chunk of `png`, if it has one
+    pub fn from_png(png: &Png) -> Result<Option<Self>> {
+        png.chunk_by_type("cLLI")
+            .map(|chunk| ContentLightLevel::try_from(chunk.data()))
+            .transpose()
+    }
+
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = self.max_cll.to_be_bytes().to_vec();
+        data.extend(self.max_fall.to_be_bytes());
+        Chunk::new(ChunkType::new(b"cLLI"), data)
+    }
+
+    /// Stores this in `png`, replacing its `cLLI` chunk or adding one after `IHDR`.
+    /// Returns the value it replaced.
+    pub fn apply(&self, png: &mut Png) -> Result<Option<ContentLightLevel>> {
+        let old = ContentLightLevel::from_png(png).ok().flatten();
+        png.set_chunk(self.to_chunk(), ChunkPosition::AfterIhdr)?;
+        Ok(old)
+    }
+}
+
+impl TryFrom<&[u8]> for ContentLightLevel {
+    type Error = Error;
+
+    fn try_from(data: &[u8]) -> Result<Self> {
+        if data.len() != 8 {
+            return Err(format!("cLLI must be 8 bytes long, not {}", data.len()).into());
+        }
+
+        Ok(ContentLightLevel {
+            max_cll: u32::from_be_bytes(data[0..4].try_into().unwrap()),
+            max_fall: u32::from_be_bytes(data[4..8].try_into().unwrap()),
+        })
+    }
+}
+
+impl fmt::Display for ContentLightLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "MaxCLL {:.4} cd/m², MaxFALL {:.4} cd/m²",
+            self.max_cll_nits(),
+            self.max_fall_nits()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::tests::PNG_FILE;
+
+    /// BT.2020 primaries with a D65 white point, mastered at 0.0050 to 1000 cd/m²
+    fn bt2020_display() -> MasteringDisplay {
+        MasteringDisplay::new(
+            [(0.708, 0.292), (0.170, 0.797), (0.131, 0.046)],
+            (0.3127, 0.3290),
+            1000.0,
+            0.005,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_mastering_display_units() {
+        let display = bt2020_display();
+        assert_eq!(display.primaries[0], (35400, 14600));
+        assert_eq!(display.white, (15635, 16450));
+        assert_eq!(display.max_luminance, 10_000_000);
+        assert_eq!(display.min_luminance, 50);
+
+        let (x, y) = MasteringDisplay::chromaticity(display.white);
+        assert!((x - 0.3127).abs() < 1e-9 && (y - 0.3290).abs() < 1e-9);
+        assert!((display.max_nits() - 1000.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_mastering_display_round_trip() {
+        let display = bt2020_display();
+        let chunk = display.to_chunk();
+        assert_eq!(chunk.data().len(), 24);
+        assert_eq!(&chunk.data()[..4], &[0x8a, 0x48, 0x39, 0x08]);
+        assert_eq!(MasteringDisplay::try_from(chunk.data()).unwrap(), display);
+        assert!(MasteringDisplay::try_from(&chunk.data()[..20]).is_err());
+    }
+
+    #[test]
+    fn test_mastering_display_validation() {
+        let primaries = [(0.708, 0.292), (0.170, 0.797), (0.131, 0.046)];
+        assert!(MasteringDisplay::new(primaries, (0.3127, 0.3290), 0.005, 1000.0).is_err());
+        assert!(MasteringDisplay::new(primaries, (1.2, 0.3290), 1000.0, 0.005).is_err());
+
+        let mut display = bt2020_display();
+        display.white.0 = 60000;
+        assert_eq!(display.problems().len(), 1);
+    }
+
+    #[test]
+    fn test_content_light_level() {
+        let level = ContentLightLevel::new(1000.0, 400.0).unwrap();
+        assert_eq!((level.max_cll, level.max_fall), (10_000_000, 4_000_000));
+        assert_eq!(
+            ContentLightLevel::try_from(level.to_chunk().data()).unwrap(),
+            level
+        );
+        assert_eq!(
+            level.to_string(),
+            "MaxCLL 1000.0000 cd/m², MaxFALL 400.0000 cd/m²"
+        );
+        assert!(ContentLightLevel::new(400.0, 1000.0).is_err());
+        assert!(ContentLightLevel::new(-1.0, 0.0).is_err());
+    }
+
+    #[test]
+    fn test_apply_before_image_data() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert_eq!(bt2020_display().apply(&mut png).unwrap(), None);
+        let level = ContentLightLevel::new(1000.0, 400.0).unwrap();
+        assert_eq!(level.apply(&mut png).unwrap(), None);
+        assert_eq!(level.apply(&mut png).unwrap(), Some(level));
+
+        assert_eq!(
+            MasteringDisplay::from_png(&png).unwrap(),
+            Some(bt2020_display())
+        );
+        assert!(png.check_ordering().is_ok());
+    }
+}
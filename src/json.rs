@@ -0,0 +1,307 @@
+use crate::color::ColorInfo;
+use crate::exif::{Exif, Ifd};
+use crate::hdr::{ContentLightLevel, MasteringDisplay};
+use crate::phys::{PhysicalDimensions, Unit};
+use crate::png::Png;
+use crate::time::Time;
+
+use std::fmt;
+
+/// A JSON value, enough to describe a PNG without pulling in a serialization library
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// Keys keep their insertion order
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Builds an object from key/value pairs
+    pub fn object<const N: usize>(fields: [(&str, Json); N]) -> Json {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    fn from_option<T>(value: Option<T>, convert: impl FnOnce(T) -> Json) -> Json {
+        value.map_or(Json::Null, convert)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Json::String(value.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Json::String(value)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(value: f64) -> Self {
+        Json::Number(value)
+    }
+}
+
+impl From<u32> for Json {
+    fn from(value: u32) -> Self {
+        Json::Number(value as f64)
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Number(value) if value.is_finite() => write!(f, "{}", value),
+            Json::Number(_) => write!(f, "null"),
+            Json::String(value) => write_string(f, value),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (index, (key, value)) in fields.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// Describes the chunks of `png` and the metadata of every typed chunk it has. Chunks that
+/// are missing are `null`, chunks that don't parse hold their error as a string.
+pub fn describe_png(png: &Png) -> Json {
+    let chunks = png
+        .chunks()
+        .iter()
+        .map(|chunk| {
+            Json::object([
+                ("type", chunk.chunk_type().to_string().into()),
+                ("length", chunk.length().into()),
+                ("crc", chunk.crc().into()),
+            ])
+        })
+        .collect();
+
+    let color = match ColorInfo::from_png(png) {
+        Ok(color) => Json::object([
+            ("effective", color.to_string().into()),
+            (
+                "conflicts",
+                Json::Array(color.conflicts().into_iter().map(Json::from).collect()),
+            ),
+        ]),
+        Err(e) => e.to_string().into(),
+    };
+
+    Json::object([
+        ("chunks", Json::Array(chunks)),
+        (
+            "modified",
+            typed(Time::from_png(png), |time| time.to_string().into()),
+        ),
+        (
+            "physical",
+            typed(PhysicalDimensions::from_png(png), physical),
+        ),
+        ("color", color),
+        (
+            "mastering_display",
+            typed(MasteringDisplay::from_png(png), mastering_display),
+        ),
+        (
+            "content_light_level",
+            typed(ContentLightLevel::from_png(png), content_light_level),
+        ),
+        ("exif", typed(Exif::from_png(png), exif)),
+    ])
+}
+
+/// Converts an optional typed chunk, keeping parse errors visible
+fn typed<T>(parsed: crate::Result<Option<T>>, convert: impl FnOnce(T) -> Json) -> Json {
+    match parsed {
+        Ok(value) => Json::from_option(value, convert),
+        Err(e) => e.to_string().into(),
+    }
+}
+
+fn physical(dimensions: PhysicalDimensions) -> Json {
+    Json::object([
+        ("x", dimensions.x.into()),
+        ("y", dimensions.y.into()),
+        (
+            "unit",
+            match dimensions.unit {
+                Unit::Unknown => Json::Null,
+                Unit::Meter => "meter".into(),
+            },
+        ),
+        (
+            "dpi",
+            Json::from_option(dimensions.dpi(), |(x, y)| {
+                Json::Array(vec![x.into(), y.into()])
+            }),
+        ),
+    ])
+}
+
+fn mastering_display(display: MasteringDisplay) -> Json {
+    let point = |coordinates| {
+        let (x, y) = MasteringDisplay::chromaticity(coordinates);
+        Json::object([("x", x.into()), ("y", y.into())])
+    };
+
+    Json::object([
+        ("red", point(display.primaries[0])),
+        ("green", point(display.primaries[1])),
+        ("blue", point(display.primaries[2])),
+        ("white", point(display.white)),
+        ("max_luminance", display.max_nits().into()),
+        ("min_luminance", display.min_nits().into()),
+    ])
+}
+
+fn content_light_level(level: ContentLightLevel) -> Json {
+    Json::object([
+        ("max_cll", level.max_cll_nits().into()),
+        ("max_fall", level.max_fall_nits().into()),
+    ])
+}
+
+/// Tags are grouped by the directory they come from, since the thumbnail repeats tags of
+/// the main image
+fn exif(exif: Exif) -> Json {
+    let directories = [
+        (Ifd::Primary, "primary"),
+        (Ifd::Thumbnail, "thumbnail"),
+        (Ifd::Exif, "exif"),
+        (Ifd::Gps, "gps"),
+    ];
+    let tags = directories
+        .into_iter()
+        .filter_map(|(ifd, key)| {
+            let tags: Vec<(String, Json)> = exif
+                .entries()
+                .iter()
+                .filter(|entry| entry.ifd == ifd)
+                .filter_map(|entry| {
+                    Some((entry.name()?.to_string(), entry.value.to_string().into()))
+                })
+                .collect();
+            (!tags.is_empty()).then(|| (key.to_string(), Json::Object(tags)))
+        })
+        .collect();
+
+    Json::object([
+        ("tags", Json::Object(tags)),
+        (
+            "gps",
+            Json::from_option(exif.gps_position(), |(latitude, longitude)| {
+                Json::object([
+                    ("latitude", latitude.into()),
+                    ("longitude", longitude.into()),
+                ])
+            }),
+        ),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::tests::PNG_FILE;
+
+    #[test]
+    fn test_display() {
+        let json = Json::object([
+            ("name", "a \"quoted\"\nline".into()),
+            (
+                "values",
+                Json::Array(vec![1.5.into(), Json::Null, Json::Bool(true)]),
+            ),
+            ("count", 3u32.into()),
+            ("nan", f64::NAN.into()),
+        ]);
+        assert_eq!(
+            json.to_string(),
+            r#"{"name":"a \"quoted\"\nline","values":[1.5,null,true],"count":3,"nan":null}"#
+        );
+    }
+
+    #[test]
+    fn test_describe_png() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        ContentLightLevel::new(1000.0, 400.0)
+            .unwrap()
+            .apply(&mut png)
+            .unwrap();
+
+        let Json::Object(fields) = describe_png(&png) else {
+            panic!("not an object");
+        };
+        let field = |name: &str| &fields.iter().find(|(key, _)| key == name).unwrap().1;
+
+        assert!(
+            matches!(field("chunks"), Json::Array(chunks) if chunks.len() == png.chunks().len())
+        );
+        assert_eq!(field("mastering_display"), &Json::Null);
+        assert_eq!(
+            field("content_light_level").to_string(),
+            r#"{"max_cll":1000,"max_fall":400}"#
+        );
+    }
+
+    #[test]
+    fn test_exif_tags_per_directory() {
+        // big endian, IFD0 at 8 and IFD1 at 26, each holding only an Orientation tag
+        let mut data = b"MM\0\x2a\0\0\0\x08".to_vec();
+        data.extend([0, 1, 0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, 1, 0, 0, 0, 0, 0, 26]);
+        data.extend([0, 1, 0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, 6, 0, 0, 0, 0, 0, 0]);
+        let exif = Exif::try_from(&data[..]).unwrap();
+
+        assert_eq!(
+            super::exif(exif).to_string(),
+            r#"{"tags":{"primary":{"Orientation":"1"},"thumbnail":{"Orientation":"6"}},"gps":null}"#
+        );
+    }
+}
@@ -1,9 +1,30 @@
-
+pub mod adaptive;
+pub mod apng;
 pub mod args;
+pub mod capacity;
+pub mod carve;
 pub mod chunk;
 pub mod chunk_types;
+pub mod color;
 pub mod commands;
+pub mod detect;
+pub mod diff;
+pub mod exif;
+pub mod fec;
+pub mod hdr;
+pub mod json;
+pub mod manifest;
+pub mod ordering;
+pub mod palette;
+pub mod phys;
+pub mod pixels;
+pub mod planes;
 pub mod png;
+pub mod polyglot;
+pub mod stego;
+pub mod strip;
+pub mod time;
+pub mod validate;
 
 pub use commands::Commands;
 
@@ -28,3 +49,83 @@ pub fn print_with_args(args: crate::args::PrintArgs) -> Result<String> {
     let runner = Commands::new();
     runner.print(args)
 }
+
+pub fn explode_with_args(args: crate::args::ExplodeArgs) -> Result<String> {
+    let runner = Commands::new();
+    runner.explode(args)
+}
+
+pub fn implode_with_args(args: crate::args::ImplodeArgs) -> Result<String> {
+    let runner = Commands::new();
+    runner.implode(args)
+}
+
+pub fn diff_with_args(args: crate::args::DiffArgs) -> Result<String> {
+    let runner = Commands::new();
+    runner.diff(args)
+}
+
+pub fn strip_with_args(args: crate::args::StripArgs) -> Result<String> {
+    let runner = Commands::new();
+    runner.strip(args)
+}
+
+pub fn frames_with_args(args: crate::args::FramesArgs) -> Result<String> {
+    let runner = Commands::new();
+    runner.frames(args)
+}
+
+pub fn animate_with_args(args: crate::args::AnimateArgs) -> Result<String> {
+    let runner = Commands::new();
+    runner.animate(args)
+}
+
+pub fn detect_with_args(args: crate::args::DetectArgs) -> Result<String> {
+    let runner = Commands::new();
+    runner.detect(args)
+}
+
+pub fn planes_with_args(args: crate::args::PlanesArgs) -> Result<String> {
+    let runner = Commands::new();
+    runner.planes(args)
+}
+
+pub fn carve_with_args(args: crate::args::CarveArgs) -> Result<String> {
+    let runner = Commands::new();
+    runner.carve(args)
+}
+
+pub fn polyglot_with_args(args: crate::args::PolyglotArgs) -> Result<String> {
+    let runner = Commands::new();
+    runner.polyglot(args)
+}
+
+pub fn capacity_with_args(args: crate::args::CapacityArgs) -> Result<String> {
+    let runner = Commands::new();
+    runner.capacity(args)
+}
+
+pub fn validate_with_args(args: crate::args::ValidateArgs) -> Result<String> {
+    let runner = Commands::new();
+    runner.validate(args)
+}
+
+pub fn touch_with_args(args: crate::args::TouchArgs) -> Result<String> {
+    let runner = Commands::new();
+    runner.touch(args)
+}
+
+pub fn dpi_with_args(args: crate::args::DpiArgs) -> Result<String> {
+    let runner = Commands::new();
+    runner.dpi(args)
+}
+
+pub fn icc_with_args(args: crate::args::IccArgs) -> Result<String> {
+    let runner = Commands::new();
+    runner.icc(args)
+}
+
+pub fn exif_with_args(args: crate::args::ExifArgs) -> Result<String> {
+    let runner = Commands::new();
+    runner.exif(args)
+}
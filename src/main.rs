@@ -8,11 +8,28 @@ fn main() -> Result<()> {
     let args = Args::parse();
     let command_runner = Commands::new();
 
-    match args.commands {
+    let output = match args.commands {
         PngMeArgs::Encode(args) => command_runner.encode(args)?,
         PngMeArgs::Decode(args) => command_runner.decode(args)?,
         PngMeArgs::Remove(args) => command_runner.remove(args)?,
         PngMeArgs::Print(args) => command_runner.print(args)?,
+        PngMeArgs::Explode(args) => command_runner.explode(args)?,
+        PngMeArgs::Implode(args) => command_runner.implode(args)?,
+        PngMeArgs::Diff(args) => command_runner.diff(args)?,
+        PngMeArgs::Strip(args) => command_runner.strip(args)?,
+        PngMeArgs::Frames(args) => command_runner.frames(args)?,
+        PngMeArgs::Animate(args) => command_runner.animate(args)?,
+        PngMeArgs::Detect(args) => command_runner.detect(args)?,
+        PngMeArgs::Planes(args) => command_runner.planes(args)?,
+        PngMeArgs::Carve(args) => command_runner.carve(args)?,
+        PngMeArgs::Polyglot(args) => command_runner.polyglot(args)?,
+        PngMeArgs::Capacity(args) => command_runner.capacity(args)?,
+        PngMeArgs::Validate(args) => command_runner.validate(args)?,
+        PngMeArgs::Touch(args) => command_runner.touch(args)?,
+        PngMeArgs::Dpi(args) => command_runner.dpi(args)?,
+        PngMeArgs::Icc(args) => command_runner.icc(args)?,
+        PngMeArgs::Exif(args) => command_runner.exif(args)?,
     };
+    println!("{}", output);
     Ok(())
 }
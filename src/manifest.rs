@@ -0,0 +1,189 @@
+use crate::{Error, Result, chunk_types::ChunkType};
+use std::fmt;
+use std::str::FromStr;
+
+/// The first line of every manifest written by `explode`.
+const MANIFEST_HEADER: &str = "# pngme manifest v1";
+
+/// A single exploded chunk: its position in the original file, its type,
+/// the CRC it had when it was written out and the file holding its data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManifestEntry {
+    pub index: usize,
+    pub chunk_type: ChunkType,
+    pub crc: u32,
+    pub file_name: String,
+}
+
+impl ManifestEntry {
+    /// The file name `explode` uses for the chunk at `index`, e.g. `0003_tEXt.bin`
+    pub fn default_file_name(index: usize, chunk_type: &ChunkType) -> String {
+        format!("{:04}_{}.bin", index, chunk_type)
+    }
+}
+
+/// Describes how to rebuild a PNG from a directory of exploded chunks.
+/// The manifest is a plain text file with one chunk per line so it can be
+/// edited by hand along with the chunk data:
+///
+/// ```text
+/// # pngme manifest v1
+/// 0000 IHDR 0x1a2b3c4d 0000_IHDR.bin
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// Name of the manifest file inside an exploded directory
+    pub const FILE_NAME: &'static str = "manifest.txt";
+
+    pub fn new(entries: Vec<ManifestEntry>) -> Self {
+        Manifest { entries }
+    }
+
+    /// The entries of this manifest in the order the chunks should be written
+    pub fn entries(&self) -> &[ManifestEntry] {
+        &self.entries
+    }
+}
+
+impl FromStr for Manifest {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut lines = s.lines();
+
+        if lines.next().map(str::trim) != Some(MANIFEST_HEADER) {
+            return Err("Missing manifest header".into());
+        }
+
+        let mut entries = Vec::new();
+
+        for (line_no, line) in lines.enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 4 {
+                return Err(format!("Malformed manifest line {}: {:?}", line_no + 2, line).into());
+            }
+
+            let index = fields[0].parse::<usize>()?;
+            let chunk_type = ChunkType::from_str(fields[1])?;
+            let crc = u32::from_str_radix(fields[2].trim_start_matches("0x"), 16)?;
+
+            // chunk files must stay inside the exploded directory
+            let file_name = fields[3];
+            if file_name == ".." || file_name.contains(['/', '\\']) || file_name.contains(':') {
+                return Err(format!(
+                    "Manifest line {} names a file outside the directory: {:?}",
+                    line_no + 2,
+                    file_name
+                )
+                .into());
+            }
+
+            entries.push(ManifestEntry {
+                index,
+                chunk_type,
+                crc,
+                file_name: file_name.to_string(),
+            });
+        }
+
+        // the index is what decides the order, so the lines themselves may be shuffled
+        entries.sort_by_key(|entry| entry.index);
+
+        Ok(Manifest { entries })
+    }
+}
+
+impl fmt::Display for Manifest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", MANIFEST_HEADER)?;
+        for entry in &self.entries {
+            writeln!(
+                f,
+                "{:04} {} {:#010x} {}",
+                entry.index, entry.chunk_type, entry.crc, entry.file_name
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_manifest() -> Manifest {
+        Manifest::new(vec![
+            ManifestEntry {
+                index: 0,
+                chunk_type: ChunkType::from_str("IHDR").unwrap(),
+                crc: 0x1e3f88b1,
+                file_name: String::from("0000_IHDR.bin"),
+            },
+            ManifestEntry {
+                index: 1,
+                chunk_type: ChunkType::from_str("RuSt").unwrap(),
+                crc: 2882656334,
+                file_name: String::from("0001_RuSt.bin"),
+            },
+        ])
+    }
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let manifest = testing_manifest();
+        let parsed = Manifest::from_str(&manifest.to_string()).unwrap();
+        assert_eq!(parsed, manifest);
+    }
+
+    #[test]
+    fn test_manifest_orders_by_index() {
+        let text = "# pngme manifest v1\n0001 RuSt 0xabcdef01 b.bin\n0000 IHDR 0x00000001 a.bin\n";
+        let manifest = Manifest::from_str(text).unwrap();
+        assert_eq!(manifest.entries()[0].file_name, "a.bin");
+        assert_eq!(manifest.entries()[1].crc, 0xabcdef01);
+    }
+
+    #[test]
+    fn test_manifest_missing_header() {
+        assert!(Manifest::from_str("0000 IHDR 0x00000001 a.bin\n").is_err());
+    }
+
+    #[test]
+    fn test_manifest_malformed_line() {
+        assert!(Manifest::from_str("# pngme manifest v1\n0000 IHDR\n").is_err());
+    }
+
+    #[test]
+    fn test_manifest_rejects_paths() {
+        for file_name in [
+            "../../secret",
+            "/etc/shadow",
+            "..",
+            "sub/a.bin",
+            "..\\a.bin",
+            "C:a.bin",
+        ] {
+            let text = format!("# pngme manifest v1\n0000 IHDR 0x00000001 {}\n", file_name);
+            assert!(Manifest::from_str(&text).is_err(), "{}", file_name);
+        }
+    }
+
+    #[test]
+    fn test_default_file_name() {
+        let chunk_type = ChunkType::from_str("tEXt").unwrap();
+        assert_eq!(
+            ManifestEntry::default_file_name(3, &chunk_type),
+            "0003_tEXt.bin"
+        );
+    }
+}
@@ -0,0 +1,144 @@
+use crate::chunk::Chunk;
+
+/// Chunks that must appear before both `PLTE` and the first `IDAT`
+const BEFORE_PLTE: [&[u8; 4]; 8] = [
+    b"cHRM", b"gAMA", b"iCCP", b"sBIT", b"sRGB", b"cICP", b"mDCV", b"cLLI",
+];
+
+/// Chunks that must appear after `PLTE` but before the first `IDAT`
+const AFTER_PLTE: [&[u8; 4]; 3] = [b"bKGD", b"hIST", b"tRNS"];
+
+/// Chunks that must appear before the first `IDAT`
+const BEFORE_IDAT: [&[u8; 4]; 4] = [b"pHYs", b"sPLT", b"eXIf", b"acTL"];
+
+/// Chunks that may appear at most once
+const SINGLE: [&[u8; 4]; 18] = [
+    b"IHDR", b"PLTE", b"IEND", b"cHRM", b"gAMA", b"iCCP", b"sBIT", b"sRGB", b"cICP", b"mDCV",
+    b"cLLI", b"bKGD", b"hIST", b"tRNS", b"pHYs", b"tIME", b"eXIf", b"acTL",
+];
+
+/// Checks `chunks` against the chunk ordering rules of the PNG spec
+/// https://www.w3.org/TR/png-3/#5ChunkOrdering
+/// and returns a description of every rule that is broken.
+pub fn ordering_violations(chunks: &[Chunk]) -> Vec<String> {
+    let types: Vec<[u8; 4]> = chunks.iter().map(|c| c.chunk_type().bytes()).collect();
+    let first = |t: &[u8; 4]| types.iter().position(|c| c == t);
+    let last = |t: &[u8; 4]| types.iter().rposition(|c| c == t);
+    let name = |t: &[u8; 4]| String::from_utf8_lossy(t).into_owned();
+
+    let mut violations = Vec::new();
+
+    if !types.is_empty() && first(b"IHDR") != Some(0) {
+        violations.push("IHDR must be the first chunk".to_string());
+    }
+    if let Some(iend) = first(b"IEND")
+        && iend != types.len() - 1
+    {
+        violations.push("IEND must be the last chunk".to_string());
+    }
+
+    for t in SINGLE {
+        if types.iter().filter(|c| *c == t).count() > 1 {
+            violations.push(format!("Only one {} chunk is allowed", name(t)));
+        }
+    }
+
+    let plte = first(b"PLTE");
+    let first_idat = first(b"IDAT");
+
+    if let (Some(first_idat), Some(last_idat)) = (first_idat, last(b"IDAT"))
+        && types[first_idat..=last_idat].iter().any(|c| c != b"IDAT")
+    {
+        violations.push("IDAT chunks must be consecutive".to_string());
+    }
+    if let (Some(plte), Some(first_idat)) = (plte, first_idat)
+        && plte > first_idat
+    {
+        violations.push("PLTE must come before the first IDAT".to_string());
+    }
+
+    for (index, t) in types.iter().enumerate() {
+        let before_idat = first_idat.is_none_or(|idat| index < idat);
+
+        if BEFORE_PLTE.contains(&t) {
+            if plte.is_some_and(|plte| index > plte) {
+                violations.push(format!("{} must come before PLTE", name(t)));
+            }
+            if !before_idat {
+                violations.push(format!("{} must come before the first IDAT", name(t)));
+            }
+        } else if AFTER_PLTE.contains(&t) {
+            if plte.is_some_and(|plte| index < plte) {
+                violations.push(format!("{} must come after PLTE", name(t)));
+            }
+            if !before_idat {
+                violations.push(format!("{} must come before the first IDAT", name(t)));
+            }
+        } else if BEFORE_IDAT.contains(&t) && !before_idat {
+            violations.push(format!("{} must come before the first IDAT", name(t)));
+        }
+    }
+
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_types::ChunkType;
+    use std::str::FromStr;
+
+    fn chunks(types: &[&str]) -> Vec<Chunk> {
+        types
+            .iter()
+            .map(|t| Chunk::new(ChunkType::from_str(t).unwrap(), Vec::new()))
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_ordering() {
+        let chunks = chunks(&[
+            "IHDR", "gAMA", "PLTE", "tRNS", "pHYs", "IDAT", "IDAT", "tEXt", "IEND",
+        ]);
+        assert!(ordering_violations(&chunks).is_empty());
+    }
+
+    #[test]
+    fn test_ihdr_first_iend_last() {
+        let violations = ordering_violations(&chunks(&["tEXt", "IHDR", "IEND", "tEXt"]));
+        assert_eq!(
+            violations,
+            vec![
+                "IHDR must be the first chunk",
+                "IEND must be the last chunk"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_idat_consecutive() {
+        let violations = ordering_violations(&chunks(&["IHDR", "IDAT", "tEXt", "IDAT", "IEND"]));
+        assert_eq!(violations, vec!["IDAT chunks must be consecutive"]);
+    }
+
+    #[test]
+    fn test_palette_rules() {
+        let violations = ordering_violations(&chunks(&["IHDR", "tRNS", "PLTE", "gAMA", "IDAT"]));
+        assert_eq!(
+            violations,
+            vec!["tRNS must come after PLTE", "gAMA must come before PLTE"]
+        );
+    }
+
+    #[test]
+    fn test_before_idat() {
+        let violations = ordering_violations(&chunks(&["IHDR", "IDAT", "pHYs", "IEND"]));
+        assert_eq!(violations, vec!["pHYs must come before the first IDAT"]);
+    }
+
+    #[test]
+    fn test_single_instance() {
+        let violations = ordering_violations(&chunks(&["IHDR", "pHYs", "pHYs", "IDAT"]));
+        assert_eq!(violations, vec!["Only one pHYs chunk is allowed"]);
+    }
+}
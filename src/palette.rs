@@ -0,0 +1,231 @@
+use crate::chunk::Chunk;
+use crate::chunk_types::ChunkType;
+use crate::pixels::ColorType;
+use crate::png::Png;
+use crate::{Error, Result};
+
+use std::fmt;
+
+/// Largest number of entries a `PLTE` chunk can hold
+pub const MAX_PALETTE_ENTRIES: usize = 256;
+
+/// One color of a palette
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PaletteEntry {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+impl PaletteEntry {
+    /// Largest difference between the channels of two colors
+    pub fn distance(&self, other: &PaletteEntry) -> u8 {
+        self.red
+            .abs_diff(other.red)
+            .max(self.green.abs_diff(other.green))
+            .max(self.blue.abs_diff(other.blue))
+    }
+}
+
+/// The contents of a `PLTE` chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    entries: Vec<PaletteEntry>,
+}
+
+impl Palette {
+    /// Creates a palette, failing if it has no entries or more than 256
+    pub fn new(entries: Vec<PaletteEntry>) -> Result<Self> {
+        if entries.is_empty() || entries.len() > MAX_PALETTE_ENTRIES {
+            return Err(format!("A palette can't have {} entries", entries.len()).into());
+        }
+
+        Ok(Palette { entries })
+    }
+
+    /// Reads the `PLTE` chunk of `png`
+    pub fn from_png(png: &Png) -> Result<Self> {
+        let chunk = png.chunk_by_type("PLTE").ok_or("PLTE chunk not found")?;
+        Palette::try_from(chunk.data())
+    }
+
+    /// Returns this palette as a `PLTE` chunk
+    pub fn to_chunk(&self) -> Chunk {
+        let data = self
+            .entries
+            .iter()
+            .flat_map(|entry| [entry.red, entry.green, entry.blue])
+            .collect();
+
+        Chunk::new(ChunkType::new(b"PLTE"), data)
+    }
+
+    pub fn entries(&self) -> &[PaletteEntry] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl TryFrom<&[u8]> for Palette {
+    type Error = Error;
+
+    fn try_from(data: &[u8]) -> Result<Self> {
+        if !data.len().is_multiple_of(3) {
+            return Err("PLTE length must be a multiple of 3".into());
+        }
+
+        Palette::new(
+            data.chunks(3)
+                .map(|rgb| PaletteEntry {
+                    red: rgb[0],
+                    green: rgb[1],
+                    blue: rgb[2],
+                })
+                .collect(),
+        )
+    }
+}
+
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, entry) in self.entries.iter().enumerate() {
+            writeln!(
+                f,
+                "{:3}: #{:02x}{:02x}{:02x}",
+                index, entry.red, entry.green, entry.blue
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The contents of a `tRNS` chunk, whose layout depends on the color type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transparency {
+    /// The gray level that is fully transparent
+    Gray(u16),
+    /// The color that is fully transparent
+    Rgb(u16, u16, u16),
+    /// Alpha of the first palette entries, the remaining entries are opaque
+    Alpha(Vec<u8>),
+}
+
+impl Transparency {
+    /// Parses a `tRNS` chunk of an image with `color_type`
+    pub fn from_bytes(data: &[u8], color_type: ColorType) -> Result<Self> {
+        let sample = |index: usize| u16::from_be_bytes([data[index], data[index + 1]]);
+
+        match color_type {
+            ColorType::Grayscale if data.len() == 2 => Ok(Transparency::Gray(sample(0))),
+            ColorType::Rgb if data.len() == 6 => {
+                Ok(Transparency::Rgb(sample(0), sample(2), sample(4)))
+            }
+            ColorType::Indexed if data.len() <= MAX_PALETTE_ENTRIES => {
+                Ok(Transparency::Alpha(data.to_vec()))
+            }
+            ColorType::GrayscaleAlpha | ColorType::Rgba => {
+                Err("tRNS is not allowed in images with an alpha channel".into())
+            }
+            _ => Err(format!("Invalid tRNS length {} for {:?}", data.len(), color_type).into()),
+        }
+    }
+
+    /// Reads the `tRNS` chunk of `png`, if it has one
+    pub fn from_png(png: &Png, color_type: ColorType) -> Result<Option<Self>> {
+        png.chunk_by_type("tRNS")
+            .map(|chunk| Transparency::from_bytes(chunk.data(), color_type))
+            .transpose()
+    }
+
+    /// Builds the transparency of a palette from one alpha value per entry, leaving out
+    /// trailing opaque entries. Returns `None` if every entry is opaque.
+    pub fn from_alpha(alpha: &[u8]) -> Option<Self> {
+        let len = alpha.iter().rposition(|&a| a != 255)? + 1;
+        Some(Transparency::Alpha(alpha[..len].to_vec()))
+    }
+
+    /// Alpha of palette entry `index`
+    pub fn alpha(&self, index: usize) -> u8 {
+        match self {
+            Transparency::Alpha(alpha) => *alpha.get(index).unwrap_or(&255),
+            _ => 255,
+        }
+    }
+
+    /// Returns this transparency as a `tRNS` chunk
+    pub fn to_chunk(&self) -> Chunk {
+        let data = match self {
+            Transparency::Gray(gray) => gray.to_be_bytes().to_vec(),
+            Transparency::Rgb(red, green, blue) => [red, green, blue]
+                .iter()
+                .flat_map(|sample| sample.to_be_bytes())
+                .collect(),
+            Transparency::Alpha(alpha) => alpha.clone(),
+        };
+
+        Chunk::new(ChunkType::new(b"tRNS"), data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_palette_round_trip() {
+        let data = [255, 0, 0, 0, 255, 0, 0, 0, 255];
+        let palette = Palette::try_from(data.as_ref()).unwrap();
+
+        assert_eq!(palette.len(), 3);
+        assert_eq!(
+            palette.entries()[1],
+            PaletteEntry {
+                red: 0,
+                green: 255,
+                blue: 0
+            }
+        );
+        assert_eq!(palette.to_chunk().data(), data);
+    }
+
+    #[test]
+    fn test_invalid_palette() {
+        assert!(Palette::try_from([1, 2].as_ref()).is_err());
+        assert!(Palette::try_from([].as_ref()).is_err());
+        assert!(Palette::try_from(vec![0; 257 * 3].as_ref()).is_err());
+    }
+
+    #[test]
+    fn test_transparency_round_trip() {
+        for (data, color_type) in [
+            (vec![0, 7], ColorType::Grayscale),
+            (vec![0, 1, 0, 2, 0, 3], ColorType::Rgb),
+            (vec![0, 128], ColorType::Indexed),
+        ] {
+            let transparency = Transparency::from_bytes(&data, color_type).unwrap();
+            assert_eq!(transparency.to_chunk().data(), data);
+        }
+    }
+
+    #[test]
+    fn test_transparency_alpha() {
+        let transparency = Transparency::from_alpha(&[0, 128, 255, 255]).unwrap();
+        assert_eq!(transparency, Transparency::Alpha(vec![0, 128]));
+        assert_eq!(transparency.alpha(1), 128);
+        assert_eq!(transparency.alpha(3), 255);
+        assert!(Transparency::from_alpha(&[255, 255]).is_none());
+    }
+
+    #[test]
+    fn test_invalid_transparency() {
+        assert!(Transparency::from_bytes(&[0, 1], ColorType::Rgba).is_err());
+        assert!(Transparency::from_bytes(&[0, 1, 2], ColorType::Rgb).is_err());
+    }
+}
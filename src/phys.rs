@@ -0,0 +1,195 @@
+use crate::chunk::Chunk;
+use crate::chunk_types::ChunkType;
+use crate::png::{ChunkPosition, Png};
+use crate::{Error, Result};
+
+use std::fmt;
+
+/// Length of an inch in meters
+const METERS_PER_INCH: f64 = 0.0254;
+
+/// Unit the pixel density of a `pHYs` chunk is given in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    /// Only the aspect ratio of the pixels is known
+    Unknown,
+    Meter,
+}
+
+/// The contents of a `pHYs` chunk: pixels per unit along both axes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhysicalDimensions {
+    pub x: u32,
+    pub y: u32,
+    pub unit: Unit,
+}
+
+impl PhysicalDimensions {
+    /// Pixel density from dots per inch along both axes
+    pub fn from_dpi(x: f64, y: f64) -> Result<Self> {
+        Ok(PhysicalDimensions {
+            x: pixels_per_meter(x / METERS_PER_INCH)?,
+            y: pixels_per_meter(y / METERS_PER_INCH)?,
+            unit: Unit::Meter,
+        })
+    }
+
+    /// Pixel density from dots per centimeter along both axes
+    pub fn from_dpcm(x: f64, y: f64) -> Result<Self> {
+        Ok(PhysicalDimensions {
+            x: pixels_per_meter(x * 100.0)?,
+            y: pixels_per_meter(y * 100.0)?,
+            unit: Unit::Meter,
+        })
+    }
+
+    /// Dots per inch along both axes, if the unit is known
+    pub fn dpi(&self) -> Option<(f64, f64)> {
+        (self.unit == Unit::Meter).then_some((
+            self.x as f64 * METERS_PER_INCH,
+            self.y as f64 * METERS_PER_INCH,
+        ))
+    }
+
+    /// Dots per centimeter along both axes, if the unit is known
+    pub fn dpcm(&self) -> Option<(f64, f64)> {
+        (self.unit == Unit::Meter).then_some((self.x as f64 / 100.0, self.y as f64 / 100.0))
+    }
+
+    /// Reads the `pHYs` chunk of `png`, if it has one
+    pub fn from_png(png: &Png) -> Result<Option<Self>> {
+        png.chunk_by_type("pHYs")
+            .map(|chunk| PhysicalDimensions::try_from(chunk.data()))
+            .transpose()
+    }
+
+    /// Returns these dimensions as a `pHYs` chunk
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = self.x.to_be_bytes().to_vec();
+        data.extend(self.y.to_be_bytes());
+        data.push(match self.unit {
+            Unit::Unknown => 0,
+            Unit::Meter => 1,
+        });
+        Chunk::new(ChunkType::new(b"pHYs"), data)
+    }
+
+    /// Stores these dimensions in `png`, replacing its `pHYs` chunk or adding one before
+    /// the image data. Returns the dimensions it replaced.
+    pub fn apply(&self, png: &mut Png) -> Result<Option<PhysicalDimensions>> {
+        let old = PhysicalDimensions::from_png(png).ok().flatten();
+        png.set_chunk(self.to_chunk(), ChunkPosition::BeforeFirstIdat)?;
+        Ok(old)
+    }
+}
+
+/// Rounds a density to whole pixels per meter, which has to fit the 31 bits PNG allows
+fn pixels_per_meter(density: f64) -> Result<u32> {
+    let rounded = density.round();
+    if !(1.0..=i32::MAX as f64).contains(&rounded) {
+        return Err(format!("Invalid pixel density {}", density).into());
+    }
+    Ok(rounded as u32)
+}
+
+impl TryFrom<&[u8]> for PhysicalDimensions {
+    type Error = Error;
+
+    fn try_from(data: &[u8]) -> Result<Self> {
+        if data.len() != 9 {
+            return Err(format!("pHYs must be 9 bytes long, not {}", data.len()).into());
+        }
+        let unit = match data[8] {
+            0 => Unit::Unknown,
+            1 => Unit::Meter,
+            other => return Err(format!("Invalid pHYs unit {}", other).into()),
+        };
+
+        Ok(PhysicalDimensions {
+            x: u32::from_be_bytes(data[0..4].try_into().unwrap()),
+            y: u32::from_be_bytes(data[4..8].try_into().unwrap()),
+            unit,
+        })
+    }
+}
+
+impl fmt::Display for PhysicalDimensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.dpi() {
+            Some((x, _)) if self.x == self.y => write!(f, "{:.2} dpi", x)?,
+            Some((x, y)) => write!(f, "{:.2}x{:.2} dpi", x, y)?,
+            None => write!(f, "aspect ratio {}:{}", self.x, self.y)?,
+        }
+        if self.unit == Unit::Meter {
+            write!(f, " ({}x{} pixels per meter)", self.x, self.y)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::tests::PNG_FILE;
+
+    #[test]
+    fn test_chunk_round_trip() {
+        let dimensions = PhysicalDimensions {
+            x: 3780,
+            y: 2835,
+            unit: Unit::Meter,
+        };
+        let chunk = dimensions.to_chunk();
+        assert_eq!(chunk.data(), &[0, 0, 0x0e, 0xc4, 0, 0, 0x0b, 0x13, 1]);
+        assert_eq!(
+            PhysicalDimensions::try_from(chunk.data()).unwrap(),
+            dimensions
+        );
+        assert!(PhysicalDimensions::try_from(&chunk.data()[..8]).is_err());
+    }
+
+    #[test]
+    fn test_dpi_conversion() {
+        let dimensions = PhysicalDimensions::from_dpi(300.0, 72.0).unwrap();
+        assert_eq!((dimensions.x, dimensions.y), (11811, 2835));
+        let (x, y) = dimensions.dpi().unwrap();
+        assert!((x - 300.0).abs() < 0.01 && (y - 72.0).abs() < 0.01);
+
+        let dimensions = PhysicalDimensions::from_dpcm(118.11, 118.11).unwrap();
+        assert_eq!(dimensions.x, 11811);
+        assert_eq!(dimensions.dpcm(), Some((118.11, 118.11)));
+
+        assert!(PhysicalDimensions::from_dpi(0.0, 72.0).is_err());
+        assert!(PhysicalDimensions::from_dpi(f64::NAN, 72.0).is_err());
+    }
+
+    #[test]
+    fn test_unknown_unit_has_no_dpi() {
+        let dimensions = PhysicalDimensions {
+            x: 2,
+            y: 1,
+            unit: Unit::Unknown,
+        };
+        assert_eq!(dimensions.dpi(), None);
+        assert_eq!(dimensions.to_string(), "aspect ratio 2:1");
+    }
+
+    #[test]
+    fn test_apply_inserts_before_image_data() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.remove_chunks("pHYs").unwrap();
+        let dimensions = PhysicalDimensions::from_dpi(96.0, 96.0).unwrap();
+        assert_eq!(dimensions.apply(&mut png).unwrap(), None);
+        assert_eq!(dimensions.apply(&mut png).unwrap(), Some(dimensions));
+
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        let phys = types.iter().position(|t| t == "pHYs").unwrap();
+        assert_eq!(types.iter().filter(|t| *t == "pHYs").count(), 1);
+        assert_eq!(types[phys + 1], "IDAT");
+        assert!(png.check_ordering().is_ok());
+    }
+}
@@ -0,0 +1,768 @@
+use crate::chunk::Chunk;
+use crate::png::Png;
+use crate::{Error, Result, chunk_types::ChunkType};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Decompress, FlushDecompress, Status};
+use std::fmt;
+use std::io::{Read, Write};
+
+/// The color types allowed by the PNG spec
+/// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.IHDR
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorType {
+    Grayscale,
+    Rgb,
+    Indexed,
+    GrayscaleAlpha,
+    Rgba,
+}
+
+impl ColorType {
+    /// Number of samples that make up one pixel
+    pub fn channels(&self) -> usize {
+        match self {
+            ColorType::Grayscale | ColorType::Indexed => 1,
+            ColorType::GrayscaleAlpha => 2,
+            ColorType::Rgb => 3,
+            ColorType::Rgba => 4,
+        }
+    }
+
+    /// The value stored in the IHDR chunk for this color type
+    pub fn value(&self) -> u8 {
+        match self {
+            ColorType::Grayscale => 0,
+            ColorType::Rgb => 2,
+            ColorType::Indexed => 3,
+            ColorType::GrayscaleAlpha => 4,
+            ColorType::Rgba => 6,
+        }
+    }
+
+    /// Returns true if `bit_depth` is allowed for this color type
+    pub fn allows_bit_depth(&self, bit_depth: u8) -> bool {
+        match self {
+            ColorType::Grayscale => matches!(bit_depth, 1 | 2 | 4 | 8 | 16),
+            ColorType::Indexed => matches!(bit_depth, 1 | 2 | 4 | 8),
+            _ => matches!(bit_depth, 8 | 16),
+        }
+    }
+}
+
+impl TryFrom<u8> for ColorType {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            0 => Ok(ColorType::Grayscale),
+            2 => Ok(ColorType::Rgb),
+            3 => Ok(ColorType::Indexed),
+            4 => Ok(ColorType::GrayscaleAlpha),
+            6 => Ok(ColorType::Rgba),
+            _ => Err(format!("Invalid color type {}", value).into()),
+        }
+    }
+}
+
+/// The contents of an `IHDR` chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageHeader {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    pub interlaced: bool,
+}
+
+impl ImageHeader {
+    /// Reads the `IHDR` chunk of `png`
+    pub fn from_png(png: &Png) -> Result<Self> {
+        let chunk = png.chunk_by_type("IHDR").ok_or("IHDR chunk not found")?;
+        ImageHeader::try_from(chunk.data())
+    }
+
+    /// Returns this header as an `IHDR` chunk
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = Vec::with_capacity(13);
+        data.extend_from_slice(&self.width.to_be_bytes());
+        data.extend_from_slice(&self.height.to_be_bytes());
+        data.push(self.bit_depth);
+        data.push(self.color_type.value());
+        data.push(0); // compression method
+        data.push(0); // filter method
+        data.push(self.interlaced as u8);
+
+        Chunk::new(ChunkType::new(b"IHDR"), data)
+    }
+
+    /// Number of samples that make up one pixel
+    pub fn channels(&self) -> usize {
+        self.color_type.channels()
+    }
+
+    /// Number of bits used by one pixel
+    pub fn bits_per_pixel(&self) -> usize {
+        self.channels() * self.bit_depth as usize
+    }
+
+    /// The largest value a single sample can hold
+    pub fn max_sample(&self) -> u16 {
+        ((1u32 << self.bit_depth) - 1) as u16
+    }
+
+    /// Number of bytes the decompressed, filtered image data takes up, including the filter
+    /// type byte in front of every scanline and all interlace passes. Fails if that doesn't
+    /// fit in memory.
+    pub fn raw_len(&self) -> Result<usize> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        if !self.interlaced {
+            return self.pass_len(width, height);
+        }
+
+        ADAM7_PASSES
+            .iter()
+            .try_fold(0usize, |total, &(x0, y0, dx, dy)| {
+                let pass_width = (width + dx - 1).saturating_sub(x0) / dx;
+                let pass_height = (height + dy - 1).saturating_sub(y0) / dy;
+                let pass_len = if pass_width == 0 || pass_height == 0 {
+                    0
+                } else {
+                    self.pass_len(pass_width, pass_height)?
+                };
+                total.checked_add(pass_len).ok_or_else(too_large)
+            })
+    }
+
+    /// Number of bytes `height` filtered scanlines of `width` pixels take up
+    fn pass_len(&self, width: usize, height: usize) -> Result<usize> {
+        (self.row_bytes(width)? + 1)
+            .checked_mul(height)
+            .ok_or_else(too_large)
+    }
+
+    /// Number of bytes in one unfiltered scanline of `width` pixels
+    fn row_bytes(&self, width: usize) -> Result<usize> {
+        width
+            .checked_mul(self.bits_per_pixel())
+            .map(|bits| bits.div_ceil(8))
+            .ok_or_else(too_large)
+    }
+
+    /// Distance in bytes to the corresponding byte of the previous pixel, used by the filters
+    fn filter_stride(&self) -> usize {
+        self.bits_per_pixel().div_ceil(8)
+    }
+}
+
+impl TryFrom<&[u8]> for ImageHeader {
+    type Error = Error;
+
+    fn try_from(data: &[u8]) -> Result<Self> {
+        if data.len() != 13 {
+            return Err("IHDR must be 13 bytes long".into());
+        }
+
+        let width = u32::from_be_bytes(data[0..4].try_into()?);
+        let height = u32::from_be_bytes(data[4..8].try_into()?);
+        let bit_depth = data[8];
+        let color_type = ColorType::try_from(data[9])?;
+
+        if width == 0 || height == 0 {
+            return Err("Image dimensions must be non-zero".into());
+        }
+        if !color_type.allows_bit_depth(bit_depth) {
+            return Err(format!(
+                "Bit depth {} is not allowed for {:?} images",
+                bit_depth, color_type
+            )
+            .into());
+        }
+        if data[10] != 0 || data[11] != 0 {
+            return Err("Unknown compression or filter method".into());
+        }
+
+        let interlaced = match data[12] {
+            0 => false,
+            1 => true,
+            _ => return Err("Unknown interlace method".into()),
+        };
+
+        Ok(ImageHeader {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            interlaced,
+        })
+    }
+}
+
+impl fmt::Display for ImageHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}x{} {:?} {}-bit{}",
+            self.width,
+            self.height,
+            self.color_type,
+            self.bit_depth,
+            if self.interlaced { " interlaced" } else { "" }
+        )
+    }
+}
+
+/// Starting column, starting row, column step and row step of the 7 Adam7 passes
+const ADAM7_PASSES: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// Decoded image samples. Every sample is unpacked into its own `u16` regardless of the
+/// bit depth, stored row by row with `channels` samples per pixel. Indexed images keep
+/// their palette indices.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PixelData {
+    header: ImageHeader,
+    samples: Vec<u16>,
+}
+
+impl PixelData {
+    /// Creates pixel data from unpacked samples. The header's interlace flag is ignored as
+    /// pixel data is always encoded without interlacing.
+    pub fn new(header: ImageHeader, samples: Vec<u16>) -> Result<Self> {
+        let expected = header.width as usize * header.height as usize * header.channels();
+        if samples.len() != expected {
+            return Err(format!("Expected {} samples, got {}", expected, samples.len()).into());
+        }
+        if samples.iter().any(|&s| s > header.max_sample()) {
+            return Err("Sample value too large for the bit depth".into());
+        }
+
+        Ok(PixelData {
+            header: ImageHeader {
+                interlaced: false,
+                ..header
+            },
+            samples,
+        })
+    }
+
+    /// Decodes the pixels stored in the `IDAT` chunks of `png`
+    pub fn decode(png: &Png) -> Result<Self> {
+        let header = ImageHeader::from_png(png)?;
+        PixelData::decode_stream(header, &png.image_data())
+    }
+
+    /// Decodes a zlib compressed, filtered image described by `header`. This is also used for
+    /// APNG frames which have their own dimensions.
+    pub fn decode_stream(header: ImageHeader, compressed: &[u8]) -> Result<Self> {
+        let width = header.width as usize;
+        let height = header.height as usize;
+        let channels = header.channels();
+        let sample_count = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(channels))
+            .ok_or_else(too_large)?;
+        let raw_len = header.raw_len()?;
+
+        // never inflate more than the header allows, so a small stream can't exhaust memory
+        let mut raw = Vec::new();
+        ZlibDecoder::new(compressed)
+            .take((raw_len as u64).saturating_add(1))
+            .read_to_end(&mut raw)?;
+        if raw.len() < raw_len {
+            return Err("Image data is too short".into());
+        }
+        if raw.len() > raw_len {
+            return Err("Image data is longer than the header allows".into());
+        }
+
+        let mut samples = vec![0u16; sample_count];
+
+        if !header.interlaced {
+            let rows = unfilter(&header, &raw, width, height)?;
+            for (y, row) in rows.iter().enumerate() {
+                let start = y * width * channels;
+                unpack_row(&header, row, &mut samples[start..start + width * channels]);
+            }
+        } else {
+            let mut offset = 0;
+            for (x0, y0, dx, dy) in ADAM7_PASSES {
+                let pass_width = (width + dx - 1 - x0) / dx;
+                let pass_height = (height + dy - 1 - y0) / dy;
+                if width <= x0 || height <= y0 || pass_width == 0 || pass_height == 0 {
+                    continue;
+                }
+
+                let pass_len = header.pass_len(pass_width, pass_height)?;
+                if offset + pass_len > raw.len() {
+                    return Err("Image data is too short".into());
+                }
+                let rows = unfilter(
+                    &header,
+                    &raw[offset..offset + pass_len],
+                    pass_width,
+                    pass_height,
+                )?;
+                offset += pass_len;
+
+                let mut pass_samples = vec![0u16; pass_width * channels];
+                for (py, row) in rows.iter().enumerate() {
+                    unpack_row(&header, row, &mut pass_samples);
+                    let y = y0 + py * dy;
+                    for px in 0..pass_width {
+                        let x = x0 + px * dx;
+                        let dst = (y * width + x) * channels;
+                        samples[dst..dst + channels]
+                            .copy_from_slice(&pass_samples[px * channels..(px + 1) * channels]);
+                    }
+                }
+            }
+        }
+
+        PixelData::new(header, samples)
+    }
+
+    /// Number of bytes the zlib stream at the start of `compressed` takes up, including its
+    /// Adler-32 checksum. Anything after that is ignored by decoders.
+    pub fn stream_len(compressed: &[u8]) -> Result<usize> {
+        let mut decompress = Decompress::new(true);
+        let mut buffer = vec![0u8; 32 * 1024];
+        loop {
+            let consumed = decompress.total_in() as usize;
+            let produced = decompress.total_out();
+            let status = decompress.decompress(
+                &compressed[consumed..],
+                &mut buffer,
+                FlushDecompress::None,
+            )?;
+            if status == Status::StreamEnd {
+                return Ok(decompress.total_in() as usize);
+            }
+            if decompress.total_in() as usize == consumed && decompress.total_out() == produced {
+                return Err("Zlib stream ends early".into());
+            }
+        }
+    }
+
+    /// Filters and compresses these pixels into a zlib stream ready to be stored in `IDAT`
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let header = &self.header;
+        let width = header.width as usize;
+        let channels = header.channels();
+        let row_bytes = header.row_bytes(width)?;
+        let stride = header.filter_stride();
+
+        let mut filtered = Vec::with_capacity((row_bytes + 1) * header.height as usize);
+        let mut previous = vec![0u8; row_bytes];
+
+        for row in self.samples.chunks(width * channels) {
+            let packed = pack_row(header, row);
+
+            // pick the filter with the smallest sum of absolute values, as suggested by the spec
+            let best = (0..5u8)
+                .map(|filter_type| {
+                    (
+                        filter_type,
+                        filter_row(filter_type, &packed, &previous, stride),
+                    )
+                })
+                .min_by_key(|(_, bytes)| {
+                    bytes
+                        .iter()
+                        .map(|&b| (b as i8).unsigned_abs() as u64)
+                        .sum::<u64>()
+                })
+                .unwrap();
+
+            filtered.push(best.0);
+            filtered.extend_from_slice(&best.1);
+            previous = packed;
+        }
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&filtered)?;
+        Ok(encoder.finish()?)
+    }
+
+    /// The header describing these pixels
+    pub fn header(&self) -> &ImageHeader {
+        &self.header
+    }
+
+    pub fn width(&self) -> u32 {
+        self.header.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.header.height
+    }
+
+    /// Number of samples that make up one pixel
+    pub fn channels(&self) -> usize {
+        self.header.channels()
+    }
+
+    /// All samples, row by row
+    pub fn samples(&self) -> &[u16] {
+        &self.samples
+    }
+
+    /// Mutable access to the samples. Values must stay below `header().max_sample()`.
+    pub fn samples_mut(&mut self) -> &mut [u16] {
+        &mut self.samples
+    }
+
+    /// The samples of the pixel at column `x` and row `y`
+    pub fn pixel(&self, x: u32, y: u32) -> &[u16] {
+        let channels = self.channels();
+        let start = (y as usize * self.header.width as usize + x as usize) * channels;
+        &self.samples[start..start + channels]
+    }
+
+    /// Converts these pixels to 8-bit RGBA. `palette` and `transparency` are the raw
+    /// contents of the `PLTE` and `tRNS` chunks, if the image has them.
+    pub fn to_rgba8(&self, palette: Option<&[u8]>, transparency: Option<&[u8]>) -> Result<Self> {
+        let header = &self.header;
+        let max = header.max_sample() as u32;
+        let scale = |sample: u16| (sample as u32 * 255 / max) as u16;
+
+        // the tRNS color of grayscale and truecolor images, at the image bit depth
+        let transparent: Option<Vec<u16>> = match (header.color_type, transparency) {
+            (ColorType::Grayscale | ColorType::Rgb, Some(data)) => Some(
+                data.chunks(2)
+                    .map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]))
+                    .collect(),
+            ),
+            _ => None,
+        };
+
+        let mut rgba = Vec::with_capacity(self.samples.len() / self.channels() * 4);
+        for px in self.samples.chunks(self.channels()) {
+            let opaque = transparent.as_deref() != Some(px);
+            let alpha = if opaque { 255 } else { 0 };
+
+            match header.color_type {
+                ColorType::Grayscale => {
+                    rgba.extend([scale(px[0]), scale(px[0]), scale(px[0]), alpha])
+                }
+                ColorType::GrayscaleAlpha => {
+                    rgba.extend([scale(px[0]), scale(px[0]), scale(px[0]), scale(px[1])])
+                }
+                ColorType::Rgb => rgba.extend([scale(px[0]), scale(px[1]), scale(px[2]), alpha]),
+                ColorType::Rgba => rgba.extend(px.iter().map(|&s| scale(s))),
+                ColorType::Indexed => {
+                    let index = px[0] as usize;
+                    let palette = palette.ok_or("Indexed image without a PLTE chunk")?;
+                    let entry = palette
+                        .get(index * 3..index * 3 + 3)
+                        .ok_or("Palette index out of range")?;
+                    let alpha = transparency.and_then(|t| t.get(index)).unwrap_or(&255);
+                    rgba.extend([entry[0], entry[1], entry[2], *alpha].map(u16::from));
+                }
+            }
+        }
+
+        PixelData::new(
+            ImageHeader {
+                bit_depth: 8,
+                color_type: ColorType::Rgba,
+                ..*header
+            },
+            rgba,
+        )
+    }
+}
+
+fn too_large() -> Error {
+    "Image dimensions are too large".into()
+}
+
+/// Reverses the per-scanline filters of `height` rows of `width` pixels
+fn unfilter(header: &ImageHeader, raw: &[u8], width: usize, height: usize) -> Result<Vec<Vec<u8>>> {
+    let row_bytes = header.row_bytes(width)?;
+    let stride = header.filter_stride();
+
+    if raw.len() < header.pass_len(width, height)? {
+        return Err("Image data is too short".into());
+    }
+
+    let mut rows: Vec<Vec<u8>> = Vec::with_capacity(height);
+    let empty = vec![0u8; row_bytes];
+
+    for y in 0..height {
+        let start = y * (row_bytes + 1);
+        let filter_type = raw[start];
+        let mut row = raw[start + 1..start + 1 + row_bytes].to_vec();
+        let previous = rows.last().unwrap_or(&empty);
+
+        for i in 0..row_bytes {
+            let a = if i >= stride { row[i - stride] } else { 0 };
+            let b = previous[i];
+            let c = if i >= stride { previous[i - stride] } else { 0 };
+
+            row[i] = match filter_type {
+                0 => row[i],
+                1 => row[i].wrapping_add(a),
+                2 => row[i].wrapping_add(b),
+                3 => row[i].wrapping_add(((a as u16 + b as u16) / 2) as u8),
+                4 => row[i].wrapping_add(paeth(a, b, c)),
+                _ => return Err(format!("Invalid filter type {}", filter_type).into()),
+            };
+        }
+
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+/// Applies filter `filter_type` to `row` given the unfiltered `previous` row
+fn filter_row(filter_type: u8, row: &[u8], previous: &[u8], stride: usize) -> Vec<u8> {
+    (0..row.len())
+        .map(|i| {
+            let a = if i >= stride { row[i - stride] } else { 0 };
+            let b = previous[i];
+            let c = if i >= stride { previous[i - stride] } else { 0 };
+
+            match filter_type {
+                0 => row[i],
+                1 => row[i].wrapping_sub(a),
+                2 => row[i].wrapping_sub(b),
+                3 => row[i].wrapping_sub(((a as u16 + b as u16) / 2) as u8),
+                _ => row[i].wrapping_sub(paeth(a, b, c)),
+            }
+        })
+        .collect()
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Splits a packed scanline into one `u16` per sample
+fn unpack_row(header: &ImageHeader, row: &[u8], out: &mut [u16]) {
+    match header.bit_depth {
+        8 => {
+            for (sample, &byte) in out.iter_mut().zip(row) {
+                *sample = byte as u16;
+            }
+        }
+        16 => {
+            for (sample, pair) in out.iter_mut().zip(row.chunks(2)) {
+                *sample = u16::from_be_bytes([pair[0], pair[1]]);
+            }
+        }
+        depth => {
+            let per_byte = 8 / depth as usize;
+            let mask = (1u8 << depth) - 1;
+            for (i, sample) in out.iter_mut().enumerate() {
+                let shift = 8 - depth as usize * (i % per_byte + 1);
+                *sample = ((row[i / per_byte] >> shift) & mask) as u16;
+            }
+        }
+    }
+}
+
+/// Packs one row of samples into a scanline
+fn pack_row(header: &ImageHeader, samples: &[u16]) -> Vec<u8> {
+    match header.bit_depth {
+        8 => samples.iter().map(|&s| s as u8).collect(),
+        16 => samples.iter().flat_map(|s| s.to_be_bytes()).collect(),
+        depth => {
+            let per_byte = 8 / depth as usize;
+            let mut row = vec![0u8; samples.len().div_ceil(per_byte)];
+            for (i, &sample) in samples.iter().enumerate() {
+                let shift = 8 - depth as usize * (i % per_byte + 1);
+                row[i / per_byte] |= (sample as u8) << shift;
+            }
+            row
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_header(color_type: ColorType, bit_depth: u8) -> ImageHeader {
+        ImageHeader {
+            width: 5,
+            height: 3,
+            bit_depth,
+            color_type,
+            interlaced: false,
+        }
+    }
+
+    fn testing_pixels(color_type: ColorType, bit_depth: u8) -> PixelData {
+        let header = testing_header(color_type, bit_depth);
+        let count = 5 * 3 * color_type.channels();
+        let samples = (0..count)
+            .map(|i| ((i * 37 + 11) as u32 % (header.max_sample() as u32 + 1)) as u16)
+            .collect();
+        PixelData::new(header, samples).unwrap()
+    }
+
+    #[test]
+    fn test_header_round_trip() {
+        let header = testing_header(ColorType::Rgba, 16);
+        let chunk = header.to_chunk();
+        assert_eq!(ImageHeader::try_from(chunk.data()).unwrap(), header);
+    }
+
+    #[test]
+    fn test_invalid_bit_depth() {
+        let mut chunk_data = testing_header(ColorType::Rgb, 8).to_chunk().data().to_vec();
+        chunk_data[8] = 4;
+        assert!(ImageHeader::try_from(chunk_data.as_ref()).is_err());
+    }
+
+    #[test]
+    fn test_round_trip_all_formats() {
+        let formats = [
+            (ColorType::Grayscale, 1),
+            (ColorType::Grayscale, 2),
+            (ColorType::Grayscale, 4),
+            (ColorType::Grayscale, 16),
+            (ColorType::Indexed, 4),
+            (ColorType::GrayscaleAlpha, 8),
+            (ColorType::Rgb, 8),
+            (ColorType::Rgba, 16),
+        ];
+
+        for (color_type, bit_depth) in formats {
+            let pixels = testing_pixels(color_type, bit_depth);
+            let encoded = pixels.encode().unwrap();
+            let decoded = PixelData::decode_stream(*pixels.header(), &encoded).unwrap();
+            assert_eq!(decoded, pixels, "{:?} {}", color_type, bit_depth);
+        }
+    }
+
+    #[test]
+    fn test_decode_interlaced() {
+        // a 3x3 8-bit grayscale image with every pixel set to its index, written with Adam7
+        // passes 1, 4, 5, 6 and 7 (passes 2 and 3 are empty for this size)
+        let raw: Vec<u8> = vec![
+            0, 0, // pass 1: (0,0)
+            0, 2, // pass 4: (2,0)
+            0, 6, 8, // pass 5: (0,2), (2,2)
+            0, 1, // pass 6: (1,0)
+            0, 7, // pass 6: (1,2)
+            0, 3, 4, 5, // pass 7: row 1
+        ];
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&raw).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let header = ImageHeader {
+            width: 3,
+            height: 3,
+            bit_depth: 8,
+            color_type: ColorType::Grayscale,
+            interlaced: true,
+        };
+        assert_eq!(header.raw_len().unwrap(), raw.len());
+        let pixels = PixelData::decode_stream(header, &compressed).unwrap();
+        assert_eq!(pixels.samples(), &[0, 1, 2, 3, 4, 5, 6, 7, 8]);
+        assert!(!pixels.header().interlaced);
+    }
+
+    #[test]
+    fn test_stream_len() {
+        let mut compressed = testing_pixels(ColorType::Rgb, 8).encode().unwrap();
+        let len = compressed.len();
+        compressed.extend(b"trailing");
+        assert_eq!(PixelData::stream_len(&compressed).unwrap(), len);
+        assert!(PixelData::stream_len(&compressed[..len - 8]).is_err());
+    }
+
+    #[test]
+    fn test_decode_truncated_data() {
+        let pixels = testing_pixels(ColorType::Rgb, 8);
+        let mut raw = Vec::new();
+        ZlibDecoder::new(&pixels.encode().unwrap()[..])
+            .read_to_end(&mut raw)
+            .unwrap();
+        raw.truncate(raw.len() - 1);
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&raw).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        assert!(PixelData::decode_stream(*pixels.header(), &compressed).is_err());
+    }
+
+    #[test]
+    fn test_decode_huge_header() {
+        let pixels = testing_pixels(ColorType::Rgb, 8);
+        let header = ImageHeader {
+            width: 0x7fff_ffff,
+            height: 0x7fff_ffff,
+            bit_depth: 16,
+            color_type: ColorType::Rgba,
+            interlaced: false,
+        };
+        assert!(PixelData::decode_stream(header, &pixels.encode().unwrap()).is_err());
+
+        // fits in memory on paper, but the stream is far too short to back it
+        let header = ImageHeader {
+            width: 0x7fff_ffff,
+            height: 1,
+            ..header
+        };
+        assert!(PixelData::decode_stream(header, &pixels.encode().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_to_rgba8() {
+        let header = ImageHeader {
+            width: 2,
+            height: 1,
+            bit_depth: 2,
+            color_type: ColorType::Grayscale,
+            interlaced: false,
+        };
+        let pixels = PixelData::new(header, vec![1, 3]).unwrap();
+        let rgba = pixels.to_rgba8(None, Some(&[0, 3])).unwrap();
+        assert_eq!(rgba.samples(), &[85, 85, 85, 255, 255, 255, 255, 0]);
+
+        let indexed = PixelData::new(
+            ImageHeader {
+                color_type: ColorType::Indexed,
+                ..header
+            },
+            vec![1, 0],
+        )
+        .unwrap();
+        let rgba = indexed
+            .to_rgba8(Some(&[1, 2, 3, 4, 5, 6]), Some(&[128]))
+            .unwrap();
+        assert_eq!(rgba.samples(), &[4, 5, 6, 255, 1, 2, 3, 128]);
+    }
+
+    #[test]
+    fn test_pixel_accessor() {
+        let pixels = testing_pixels(ColorType::Rgb, 8);
+        let expected = &pixels.samples()[(5 + 2) * 3..(5 + 2) * 3 + 3];
+        assert_eq!(pixels.pixel(2, 1), expected);
+    }
+}
@@ -0,0 +1,184 @@
+use crate::Result;
+use crate::pixels::{ColorType, ImageHeader, PixelData};
+
+/// Order in which pixels are visited when reading a bit stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanOrder {
+    /// Left to right, top to bottom
+    Row,
+    /// Top to bottom, left to right
+    Column,
+}
+
+/// Names of the channels of `color_type`, in sample order
+pub fn channel_names(color_type: ColorType) -> &'static [&'static str] {
+    match color_type {
+        ColorType::Grayscale => &["gray"],
+        ColorType::GrayscaleAlpha => &["gray", "alpha"],
+        ColorType::Rgb => &["red", "green", "blue"],
+        ColorType::Rgba => &["red", "green", "blue", "alpha"],
+        ColorType::Indexed => &["index"],
+    }
+}
+
+/// Finds the channel called `name`, or by its first letter
+pub fn channel_index(color_type: ColorType, name: &str) -> Result<usize> {
+    let names = channel_names(color_type);
+    let name = name.to_lowercase();
+
+    names
+        .iter()
+        .position(|channel| *channel == name || channel[..1] == name)
+        .ok_or_else(|| {
+            format!(
+                "{:?} images have no {} channel, only {}",
+                color_type,
+                name,
+                names.join(", ")
+            )
+            .into()
+        })
+}
+
+/// Returns one bit of one channel as a black and white image, white where the bit is set
+pub fn bit_plane(pixels: &PixelData, channel: usize, bit: u8) -> Result<PixelData> {
+    check_channel_and_bit(pixels, channel, bit)?;
+
+    let samples = pixels
+        .samples()
+        .iter()
+        .skip(channel)
+        .step_by(pixels.channels())
+        .map(|sample| (sample >> bit) & 1)
+        .collect();
+
+    PixelData::new(
+        ImageHeader {
+            bit_depth: 1,
+            color_type: ColorType::Grayscale,
+            ..*pixels.header()
+        },
+        samples,
+    )
+}
+
+/// Reads `bits` of every sample of `channels`, visiting the pixels in `order`, and packs
+/// them into bytes most significant bit first. This is how most LSB tools lay out their data.
+pub fn bit_stream(
+    pixels: &PixelData,
+    channels: &[usize],
+    bits: &[u8],
+    order: ScanOrder,
+) -> Result<Vec<u8>> {
+    for &channel in channels {
+        for &bit in bits {
+            check_channel_and_bit(pixels, channel, bit)?;
+        }
+    }
+
+    let (width, height) = (pixels.width(), pixels.height());
+    let coordinates: Vec<(u32, u32)> = match order {
+        ScanOrder::Row => (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .collect(),
+        ScanOrder::Column => (0..width)
+            .flat_map(|x| (0..height).map(move |y| (x, y)))
+            .collect(),
+    };
+
+    let mut stream = Vec::new();
+    let mut byte = 0u8;
+    let mut filled = 0;
+    for (x, y) in coordinates {
+        let pixel = pixels.pixel(x, y);
+        for &channel in channels {
+            for &bit in bits {
+                byte = (byte << 1) | ((pixel[channel] >> bit) & 1) as u8;
+                filled += 1;
+                if filled == 8 {
+                    stream.push(byte);
+                    byte = 0;
+                    filled = 0;
+                }
+            }
+        }
+    }
+
+    Ok(stream)
+}
+
+fn check_channel_and_bit(pixels: &PixelData, channel: usize, bit: u8) -> Result<()> {
+    if channel >= pixels.channels() {
+        return Err(format!("Channel {} is out of range", channel).into());
+    }
+    if bit >= pixels.header().bit_depth {
+        return Err(format!(
+            "Bit {} is out of range for {}-bit samples",
+            bit,
+            pixels.header().bit_depth
+        )
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_pixels() -> PixelData {
+        let header = ImageHeader {
+            width: 4,
+            height: 2,
+            bit_depth: 8,
+            color_type: ColorType::Rgb,
+            interlaced: false,
+        };
+        let samples = (0..4 * 2 * 3).map(|i| i as u16).collect();
+        PixelData::new(header, samples).unwrap()
+    }
+
+    #[test]
+    fn test_channel_index() {
+        assert_eq!(channel_index(ColorType::Rgba, "alpha").unwrap(), 3);
+        assert_eq!(channel_index(ColorType::Rgb, "G").unwrap(), 1);
+        assert!(channel_index(ColorType::Rgb, "alpha").is_err());
+    }
+
+    #[test]
+    fn test_bit_plane() {
+        let plane = bit_plane(&testing_pixels(), 1, 0).unwrap();
+        assert_eq!(plane.header().bit_depth, 1);
+        assert_eq!(plane.header().color_type, ColorType::Grayscale);
+        // green samples are 1, 4, 7, 10, ...
+        assert_eq!(plane.samples(), [1, 0, 1, 0, 1, 0, 1, 0]);
+    }
+
+    #[test]
+    fn test_bit_plane_out_of_range() {
+        assert!(bit_plane(&testing_pixels(), 3, 0).is_err());
+        assert!(bit_plane(&testing_pixels(), 0, 8).is_err());
+    }
+
+    #[test]
+    fn test_bit_stream_row_order() {
+        let stream = bit_stream(&testing_pixels(), &[0], &[0], ScanOrder::Row).unwrap();
+        // red samples are 0, 3, 6, 9, 12, 15, 18, 21
+        assert_eq!(stream, vec![0b0101_0101]);
+    }
+
+    #[test]
+    fn test_bit_stream_column_order() {
+        let stream = bit_stream(&testing_pixels(), &[0], &[0], ScanOrder::Column).unwrap();
+        // red samples by column are 0, 12, 3, 15, 6, 18, 9, 21
+        assert_eq!(stream, vec![0b0011_0011]);
+    }
+
+    #[test]
+    fn test_bit_stream_several_bits() {
+        let stream = bit_stream(&testing_pixels(), &[0, 1, 2], &[1, 0], ScanOrder::Row).unwrap();
+        // 0, 1, 2 -> 00 01 10, 3, 4, 5 -> 11 00 01
+        assert_eq!(stream[0], 0b0001_1011);
+        assert_eq!(stream.len(), 6);
+    }
+}
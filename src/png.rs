@@ -1,9 +1,36 @@
 use crate::chunk::Chunk;
+use crate::ordering::ordering_violations;
+use crate::pixels::{ColorType, ImageHeader, PixelData};
+use crate::strip::StripPolicy;
 use crate::{Error, Result, chunk_types::ChunkType};
 
 use std::fmt;
 use std::str::FromStr;
 
+/// What happens to unknown ancillary chunks that are not safe to copy when critical chunks
+/// are rewritten. The PNG spec requires dropping them since they may depend on the old
+/// image data, `Preserve` overrides this.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnsafeChunkPolicy {
+    #[default]
+    Drop,
+    Preserve,
+}
+
+/// Where `Png::insert_chunk` and `Png::move_chunk` put a chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChunkPosition {
+    /// At this index in the chunk list
+    Index(usize),
+    /// Right before the first chunk of this type
+    Before(ChunkType),
+    /// Right after the last chunk of this type
+    After(ChunkType),
+    AfterIhdr,
+    BeforeFirstIdat,
+    BeforeIend,
+}
+
 /// A PNG container as described by the PNG spec
 /// http://www.libpng.org/pub/png/spec/1.2/PNG-Contents.html
 #[derive(Debug)]
@@ -19,17 +46,168 @@ impl Png {
         Png { chunks }
     }
 
-    /// Appends a chunk to the end of this `Png` file's `Chunk` list.
+    /// Creates a minimal `Png` holding `pixels`: `IHDR`, one `IDAT` and `IEND`
+    pub fn from_pixels(pixels: &PixelData) -> Result<Self> {
+        Ok(Png::from_chunks(vec![
+            pixels.header().to_chunk(),
+            Chunk::new(ChunkType::new(b"IDAT"), pixels.encode()?),
+            Chunk::new(ChunkType::new(b"IEND"), Vec::new()),
+        ]))
+    }
+
+    /// Appends a chunk to the end of this `Png` file's `Chunk` list, right before `IEND` if
+    /// there is one.
     pub fn append_chunk(&mut self, new_chunk: Chunk) {
         let iend_pos = self
             .chunks
             .iter()
             .position(|c| c.chunk_type().eq(&ChunkType::new(b"IEND")))
-            .expect("IEND must exist");
+            .unwrap_or(self.chunks.len());
 
         self.chunks.insert(iend_pos, new_chunk);
     }
 
+    /// Inserts `new_chunk` at `position`. Fails if the position doesn't exist or the insertion
+    /// breaks the chunk ordering rules of the PNG spec, in which case nothing is changed.
+    pub fn insert_chunk(&mut self, new_chunk: Chunk, position: ChunkPosition) -> Result<()> {
+        self.edit(|chunks| {
+            let index = Self::resolve_position(chunks, &position)?;
+            chunks.insert(index, new_chunk);
+            Ok(())
+        })
+    }
+
+    /// Replaces the chunk at `index` with `new_chunk` and returns the old chunk. Critical
+    /// chunks have to go through `replace_critical_chunks` instead.
+    pub fn replace_chunk(&mut self, index: usize, new_chunk: Chunk) -> Result<Chunk> {
+        self.edit(|chunks| {
+            let old = chunks.get_mut(index).ok_or("Chunk index out of range")?;
+            Ok(std::mem::replace(old, new_chunk))
+        })
+    }
+
+    /// Replaces the first chunk with the type of `new_chunk`, or inserts it at `position`
+    /// if there is none. Returns the replaced chunk.
+    pub fn set_chunk(
+        &mut self,
+        new_chunk: Chunk,
+        position: ChunkPosition,
+    ) -> Result<Option<Chunk>> {
+        match self
+            .chunks
+            .iter()
+            .position(|chunk| chunk.chunk_type() == new_chunk.chunk_type())
+        {
+            Some(index) => self.replace_chunk(index, new_chunk).map(Some),
+            None => self.insert_chunk(new_chunk, position).map(|_| None),
+        }
+    }
+
+    /// Moves the chunk at `index` to `position`. The position is resolved after the chunk
+    /// has been taken out of the list.
+    pub fn move_chunk(&mut self, index: usize, position: ChunkPosition) -> Result<()> {
+        self.edit(|chunks| {
+            if index >= chunks.len() {
+                return Err("Chunk index out of range".into());
+            }
+            let chunk = chunks.remove(index);
+            let new_index = Self::resolve_position(chunks, &position)?;
+            chunks.insert(new_index, chunk);
+            Ok(())
+        })
+    }
+
+    /// Removes every chunk of `chunk_type` and returns them. Fails if there are none.
+    pub fn remove_chunks(&mut self, chunk_type: &str) -> Result<Vec<Chunk>> {
+        let chunk_type_bin = ChunkType::from_str(chunk_type)?;
+
+        self.edit(|chunks| {
+            let (removed, kept): (Vec<Chunk>, Vec<Chunk>) = chunks
+                .drain(..)
+                .partition(|chunk| chunk.chunk_type() == &chunk_type_bin);
+            *chunks = kept;
+
+            if removed.is_empty() {
+                return Err("Could not find the specified chunk".into());
+            }
+            Ok(removed)
+        })
+    }
+
+    /// Removes the `n`th (zero based) chunk of `chunk_type` and returns it.
+    pub fn remove_nth_chunk(&mut self, chunk_type: &str, n: usize) -> Result<Chunk> {
+        let chunk_type_bin = ChunkType::from_str(chunk_type)?;
+
+        self.edit(|chunks| {
+            let index = chunks
+                .iter()
+                .enumerate()
+                .filter(|(_, chunk)| chunk.chunk_type() == &chunk_type_bin)
+                .nth(n)
+                .map(|(index, _)| index)
+                .ok_or("Could not find the specified chunk")?;
+            Ok(chunks.remove(index))
+        })
+    }
+
+    /// Checks the chunks against the ordering rules of the PNG spec
+    pub fn check_ordering(&self) -> Result<()> {
+        match ordering_violations(&self.chunks).into_iter().next() {
+            Some(violation) => Err(violation.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Applies `edit` to the chunk list and rolls it back if the edit fails or introduces a
+    /// chunk ordering violation that wasn't already there. Edits that touch the standard
+    /// critical chunks are rejected as well, since changing them means dropping unsafe chunks.
+    fn edit<T>(&mut self, edit: impl FnOnce(&mut Vec<Chunk>) -> Result<T>) -> Result<T> {
+        let before = ordering_violations(&self.chunks);
+        let mut chunks = self.chunks.clone();
+        let result = edit(&mut chunks)?;
+
+        if critical_data(&chunks) != critical_data(&self.chunks) {
+            return Err(
+                "Critical chunks can only be changed with replace_critical_chunks or set_pixels"
+                    .into(),
+            );
+        }
+        if let Some(violation) = ordering_violations(&chunks)
+            .into_iter()
+            .find(|violation| !before.contains(violation))
+        {
+            return Err(violation.into());
+        }
+
+        self.chunks = chunks;
+        Ok(result)
+    }
+
+    /// Turns `position` into an index into `chunks`
+    fn resolve_position(chunks: &[Chunk], position: &ChunkPosition) -> Result<usize> {
+        let first_of = |t: &[u8; 4]| chunks.iter().position(|c| &c.chunk_type().bytes() == t);
+        let missing = |t: &str| -> Error { format!("No {} chunk found", t).into() };
+
+        match position {
+            ChunkPosition::Index(index) if *index <= chunks.len() => Ok(*index),
+            ChunkPosition::Index(_) => Err("Chunk index out of range".into()),
+            ChunkPosition::Before(chunk_type) => chunks
+                .iter()
+                .position(|c| c.chunk_type() == chunk_type)
+                .ok_or_else(|| missing(&chunk_type.to_string())),
+            ChunkPosition::After(chunk_type) => chunks
+                .iter()
+                .rposition(|c| c.chunk_type() == chunk_type)
+                .map(|index| index + 1)
+                .ok_or_else(|| missing(&chunk_type.to_string())),
+            ChunkPosition::AfterIhdr => first_of(b"IHDR")
+                .map(|index| index + 1)
+                .ok_or_else(|| missing("IHDR")),
+            ChunkPosition::BeforeFirstIdat => first_of(b"IDAT").ok_or_else(|| missing("IDAT")),
+            ChunkPosition::BeforeIend => first_of(b"IEND").ok_or_else(|| missing("IEND")),
+        }
+    }
+
     /// Searches for a `Chunk` with the specified `chunk_type` and removes the first
     /// matching `Chunk` from this `Png` list of chunks.
     pub fn remove_first_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
@@ -46,6 +224,126 @@ impl Png {
         Err("Could not find the specified chunk".into())
     }
 
+    /// Removes every chunk that `policy` selects and returns the removed chunks in order.
+    pub fn strip(&mut self, policy: &StripPolicy) -> Vec<Chunk> {
+        let (removed, kept) = self
+            .chunks
+            .drain(..)
+            .partition(|chunk| policy.should_remove(chunk.chunk_type()));
+        self.chunks = kept;
+
+        removed
+    }
+
+    /// Replaces critical chunks with `new_chunks`. For every critical type in `new_chunks` all
+    /// existing chunks of that type are removed and the new ones are inserted where the first
+    /// old one was, or at the position the spec requires if there was none.
+    /// If this changed any critical data, unknown ancillary chunks that are not safe to copy
+    /// are handled according to `policy`, and `tRNS`, `bKGD`, `hIST` and `sBIT` are dropped
+    /// unless they still fit the new header and palette. Returns the dropped chunks.
+    pub fn replace_critical_chunks(
+        &mut self,
+        new_chunks: Vec<Chunk>,
+        policy: UnsafeChunkPolicy,
+    ) -> Result<Vec<Chunk>> {
+        if let Some(chunk) = new_chunks.iter().find(|c| !c.chunk_type().is_critical()) {
+            return Err(format!("{} is not a critical chunk", chunk.chunk_type()).into());
+        }
+
+        let mut types: Vec<ChunkType> = Vec::new();
+        for chunk in &new_chunks {
+            if !types.contains(chunk.chunk_type()) {
+                types.push(chunk.chunk_type().clone());
+            }
+        }
+
+        let mut changed = false;
+        for chunk_type in types {
+            let replacements: Vec<Chunk> = new_chunks
+                .iter()
+                .filter(|c| c.chunk_type() == &chunk_type)
+                .cloned()
+                .collect();
+            let existing: Vec<&Chunk> = self
+                .chunks
+                .iter()
+                .filter(|c| c.chunk_type() == &chunk_type)
+                .collect();
+
+            let unchanged = existing.len() == replacements.len()
+                && existing
+                    .iter()
+                    .zip(&replacements)
+                    .all(|(old, new)| old.data() == new.data());
+            if unchanged {
+                continue;
+            }
+            changed = true;
+
+            let position = match self
+                .chunks
+                .iter()
+                .position(|c| c.chunk_type() == &chunk_type)
+            {
+                Some(position) => position,
+                None => self.default_position(&chunk_type),
+            };
+            let before = self.chunks[..position]
+                .iter()
+                .filter(|c| c.chunk_type() != &chunk_type)
+                .count();
+            self.chunks.retain(|c| c.chunk_type() != &chunk_type);
+            self.chunks.splice(before..before, replacements);
+        }
+
+        if !changed {
+            return Ok(Vec::new());
+        }
+
+        let header = ImageHeader::from_png(self).ok();
+        let palette_len = self.chunk_by_type("PLTE").map(|c| c.data().len() / 3);
+        let (dropped, kept) = self.chunks.drain(..).partition(|chunk| {
+            let chunk_type = chunk.chunk_type();
+            if DEPENDENT_CHUNK_TYPES.contains(&&chunk_type.bytes()) {
+                return !header.is_some_and(|header| fits_image(chunk, &header, palette_len));
+            }
+            policy == UnsafeChunkPolicy::Drop
+                && !chunk_type.is_critical()
+                && !chunk_type.is_known()
+                && !chunk_type.is_safe_to_copy()
+        });
+        self.chunks = kept;
+
+        Ok(dropped)
+    }
+
+    /// Replaces the `IHDR` and `IDAT` chunks with `pixels`, see `replace_critical_chunks`.
+    pub fn set_pixels(
+        &mut self,
+        pixels: &PixelData,
+        policy: UnsafeChunkPolicy,
+    ) -> Result<Vec<Chunk>> {
+        let image_data = Chunk::new(ChunkType::new(b"IDAT"), pixels.encode()?);
+        self.replace_critical_chunks(vec![pixels.header().to_chunk(), image_data], policy)
+    }
+
+    /// Where a critical chunk of `chunk_type` goes when the file has none yet
+    fn default_position(&self, chunk_type: &ChunkType) -> usize {
+        let first_of = |t: &[u8; 4]| {
+            self.chunks
+                .iter()
+                .position(|c| &c.chunk_type().bytes() == t)
+        };
+
+        match &chunk_type.bytes() {
+            b"IHDR" => Some(0),
+            b"PLTE" => first_of(b"IDAT").or(first_of(b"IEND")),
+            b"IEND" => None,
+            _ => first_of(b"IEND"),
+        }
+        .unwrap_or(self.chunks.len())
+    }
+
     /// The header of this PNG.
     pub fn header(&self) -> &'static [u8; 8] {
         &Self::STANDARD_HEADER
@@ -71,6 +369,15 @@ impl Png {
         None
     }
 
+    /// Returns the concatenated data of all `IDAT` chunks, i.e. the compressed image stream.
+    pub fn image_data(&self) -> Vec<u8> {
+        self.chunks
+            .iter()
+            .filter(|chunk| chunk.chunk_type().bytes() == *b"IDAT")
+            .flat_map(|chunk| chunk.data().iter().copied())
+            .collect()
+    }
+
     /// Returns this `Png` as a byte sequence.
     /// These bytes will contain the header followed by the bytes of all of the chunks.
     pub fn as_bytes(&self) -> Vec<u8> {
@@ -82,12 +389,18 @@ impl Png {
 
         byte_vec
     }
-}
 
-impl TryFrom<&[u8]> for Png {
-    type Error = Error;
-    fn try_from(bytes: &[u8]) -> Result<Self> {
-        if bytes.len() < 8 || &bytes[..8] != Self::STANDARD_HEADER {
+    /// Parses `bytes` up to and including the `IEND` chunk and returns the bytes that
+    /// follow it. Strict parsing with `try_from` fails on such trailing data.
+    pub fn split_trailing_data(bytes: &[u8]) -> Result<(Png, &[u8])> {
+        let (png, end) = Png::parse(bytes, true)?;
+        Ok((png, &bytes[end..]))
+    }
+
+    /// Reads chunks from `bytes`, stopping after `IEND` if `stop_at_iend` is set.
+    /// Returns the png and the position after the last chunk read.
+    fn parse(bytes: &[u8], stop_at_iend: bool) -> Result<(Png, usize)> {
+        if bytes.len() < 8 || bytes[..8] != Self::STANDARD_HEADER {
             return Err("Invalid PNG header".into());
         }
 
@@ -112,19 +425,82 @@ impl TryFrom<&[u8]> for Png {
             }
 
             let chunk = Chunk::try_from(&bytes[position..position + chunk_length])?;
+            let is_end = &chunk.chunk_type().bytes() == b"IEND";
 
             chunks.push(chunk);
 
             position += chunk_length;
+
+            if stop_at_iend && is_end {
+                break;
+            }
+        }
+
+        Ok((Png::from_chunks(chunks), position))
+    }
+}
+
+/// Known ancillary chunks whose contents are given in terms of the image header or palette
+const DEPENDENT_CHUNK_TYPES: [&[u8; 4]; 4] = [b"tRNS", b"bKGD", b"hIST", b"sBIT"];
+
+/// Whether one of the `DEPENDENT_CHUNK_TYPES` is still valid for `header` and a palette of
+/// `palette_len` entries
+fn fits_image(chunk: &Chunk, header: &ImageHeader, palette_len: Option<usize>) -> bool {
+    let data = chunk.data();
+    let samples = |count: usize| {
+        data.len() == count * 2
+            && data
+                .chunks(2)
+                .all(|sample| u16::from_be_bytes([sample[0], sample[1]]) <= header.max_sample())
+    };
+    let indexed = header.color_type == ColorType::Indexed;
+
+    match &chunk.chunk_type().bytes() {
+        b"tRNS" => match header.color_type {
+            ColorType::Grayscale => samples(1),
+            ColorType::Rgb => samples(3),
+            ColorType::Indexed => palette_len.is_some_and(|len| data.len() <= len),
+            ColorType::GrayscaleAlpha | ColorType::Rgba => false,
+        },
+        b"bKGD" => match header.color_type {
+            ColorType::Grayscale | ColorType::GrayscaleAlpha => samples(1),
+            ColorType::Rgb | ColorType::Rgba => samples(3),
+            ColorType::Indexed => {
+                matches!(data, [index] if palette_len.is_some_and(|len| (*index as usize) < len))
+            }
+        },
+        b"hIST" => palette_len.is_some_and(|len| data.len() == len * 2),
+        b"sBIT" => {
+            let (channels, depth) = if indexed {
+                (3, 8)
+            } else {
+                (header.channels(), header.bit_depth)
+            };
+            data.len() == channels && data.iter().all(|&bits| (1..=depth).contains(&bits))
         }
+        _ => true,
+    }
+}
 
-        Ok(Png::from_chunks(chunks))
+/// The standard critical chunks of `chunks` in order, as raw bytes
+fn critical_data(chunks: &[Chunk]) -> Vec<Vec<u8>> {
+    chunks
+        .iter()
+        .filter(|chunk| chunk.chunk_type().is_critical() && chunk.chunk_type().is_known())
+        .map(Chunk::as_bytes)
+        .collect()
+}
+
+impl TryFrom<&[u8]> for Png {
+    type Error = Error;
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        Ok(Png::parse(bytes, false)?.0)
     }
 }
 
 impl fmt::Display for Png {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Header: {:?}\n", Self::STANDARD_HEADER)?;
+        writeln!(f, "Header: {:?}", Self::STANDARD_HEADER)?;
         for (i, chunk) in self.chunks.iter().enumerate() {
             write!(f, "Chunk: {}\n{}", i + 1, chunk)?;
         }
@@ -134,12 +510,11 @@ impl fmt::Display for Png {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use crate::chunk::Chunk;
     use crate::chunk_types::ChunkType;
     use std::convert::TryFrom;
-    use std::str::FromStr;
 
     fn testing_chunks() -> Vec<Chunk> {
         vec![
@@ -262,12 +637,298 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_append_chunk_before_iend() {
+        let mut png = testing_image_png();
+        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+        assert_eq!(&chunk_types(&png)[6..], ["TeSt", "IEND"]);
+    }
+
+    #[test]
+    fn test_insert_chunk_positions() {
+        let mut png = testing_image_png();
+        png.insert_chunk(
+            chunk_from_strings("pHYs", "phys").unwrap(),
+            ChunkPosition::BeforeFirstIdat,
+        )
+        .unwrap();
+        png.insert_chunk(
+            chunk_from_strings("tEXt", "text").unwrap(),
+            ChunkPosition::AfterIhdr,
+        )
+        .unwrap();
+        png.insert_chunk(
+            chunk_from_strings("zTXt", "text").unwrap(),
+            ChunkPosition::After(ChunkType::from_str("IDAT").unwrap()),
+        )
+        .unwrap();
+
+        assert_eq!(
+            chunk_types(&png),
+            vec![
+                "IHDR", "tEXt", "ruST", "gAMA", "pHYs", "IDAT", "IDAT", "zTXt", "ruSt", "IEND"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_insert_chunk_ordering_violation() {
+        let mut png = testing_image_png();
+        let result = png.insert_chunk(
+            chunk_from_strings("pHYs", "phys").unwrap(),
+            ChunkPosition::BeforeIend,
+        );
+
+        assert!(result.is_err());
+        assert_eq!(png.chunks().len(), 7);
+    }
+
+    #[test]
+    fn test_insert_chunk_missing_anchor() {
+        let mut png = testing_png();
+        let result = png.insert_chunk(
+            chunk_from_strings("TeSt", "Message").unwrap(),
+            ChunkPosition::BeforeIend,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_replace_chunk() {
+        let mut png = testing_image_png();
+        let old = png
+            .replace_chunk(5, chunk_from_strings("tEXt", "text").unwrap())
+            .unwrap();
+        assert_eq!(&old.chunk_type().to_string(), "ruSt");
+        assert_eq!(&chunk_types(&png)[5], "tEXt");
+
+        assert!(
+            png.replace_chunk(0, chunk_from_strings("tEXt", "text").unwrap())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_edits_reject_critical_chunks() {
+        let mut png = testing_image_png();
+        assert!(
+            png.replace_chunk(3, chunk_from_strings("IDAT", "data").unwrap())
+                .is_err()
+        );
+        assert!(
+            png.insert_chunk(
+                chunk_from_strings("PLTE", "abc").unwrap(),
+                ChunkPosition::AfterIhdr
+            )
+            .is_err()
+        );
+        assert_eq!(chunk_types(&png), chunk_types(&testing_image_png()));
+    }
+
+    #[test]
+    fn test_move_chunk() {
+        let mut png = testing_image_png();
+        png.move_chunk(5, ChunkPosition::AfterIhdr).unwrap();
+        assert_eq!(&chunk_types(&png)[1], "ruSt");
+
+        assert!(png.move_chunk(3, ChunkPosition::BeforeIend).is_err());
+    }
+
+    #[test]
+    fn test_remove_chunks() {
+        let mut png = testing_image_png();
+        png.insert_chunk(
+            chunk_from_strings("tEXt", "one").unwrap(),
+            ChunkPosition::BeforeIend,
+        )
+        .unwrap();
+        png.insert_chunk(
+            chunk_from_strings("tEXt", "two").unwrap(),
+            ChunkPosition::BeforeIend,
+        )
+        .unwrap();
+
+        let second = png.remove_nth_chunk("tEXt", 1).unwrap();
+        assert_eq!(second.data(), b"two");
+
+        assert!(png.remove_chunks("IDAT").is_err());
+        assert!(png.remove_nth_chunk("IDAT", 1).is_err());
+        assert_eq!(chunk_types(&png).iter().filter(|t| *t == "IDAT").count(), 2);
+    }
+
+    #[test]
+    fn test_strip() {
+        let mut png = testing_png();
+        let removed = png.strip(&StripPolicy::Private);
+        assert_eq!(removed.len(), 1);
+        assert_eq!(&removed[0].chunk_type().to_string(), "miDl");
+        assert_eq!(png.chunks().len(), 2);
+    }
+
+    fn testing_image_png() -> Png {
+        Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("ruST", "unsafe private").unwrap(),
+            chunk_from_strings("gAMA", "known unsafe").unwrap(),
+            chunk_from_strings("IDAT", "first").unwrap(),
+            chunk_from_strings("IDAT", "second").unwrap(),
+            chunk_from_strings("ruSt", "safe private").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ])
+    }
+
+    fn chunk_types(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_replace_critical_drops_unsafe() {
+        let mut png = testing_image_png();
+        let dropped = png
+            .replace_critical_chunks(
+                vec![chunk_from_strings("IDAT", "new").unwrap()],
+                UnsafeChunkPolicy::Drop,
+            )
+            .unwrap();
+
+        assert_eq!(dropped.len(), 1);
+        assert_eq!(&dropped[0].chunk_type().to_string(), "ruST");
+        assert_eq!(
+            chunk_types(&png),
+            vec!["IHDR", "gAMA", "IDAT", "ruSt", "IEND"]
+        );
+        assert_eq!(png.image_data(), b"new");
+    }
+
+    #[test]
+    fn test_replace_critical_preserve() {
+        let mut png = testing_image_png();
+        let dropped = png
+            .replace_critical_chunks(
+                vec![chunk_from_strings("IDAT", "new").unwrap()],
+                UnsafeChunkPolicy::Preserve,
+            )
+            .unwrap();
+
+        assert!(dropped.is_empty());
+        assert_eq!(png.chunks().len(), 6);
+    }
+
+    #[test]
+    fn test_replace_critical_unchanged_keeps_unsafe() {
+        let mut png = testing_image_png();
+        let dropped = png
+            .replace_critical_chunks(
+                vec![chunk_from_strings("IHDR", "header").unwrap()],
+                UnsafeChunkPolicy::Drop,
+            )
+            .unwrap();
+
+        assert!(dropped.is_empty());
+        assert_eq!(png.chunks().len(), 7);
+    }
+
+    #[test]
+    fn test_replace_critical_drops_stale_dependent_chunks() {
+        let rgb = PixelData::new(
+            ImageHeader {
+                width: 2,
+                height: 1,
+                bit_depth: 8,
+                color_type: ColorType::Rgb,
+                interlaced: false,
+            },
+            vec![1, 2, 3, 4, 5, 6],
+        )
+        .unwrap();
+        let mut png = Png::from_chunks(vec![
+            rgb.header().to_chunk(),
+            Chunk::new(ChunkType::new(b"sBIT"), vec![8, 8, 8]),
+            Chunk::new(ChunkType::new(b"tRNS"), vec![0, 1, 0, 2, 0, 3]),
+            Chunk::new(ChunkType::new(b"IDAT"), rgb.encode().unwrap()),
+            Chunk::new(ChunkType::new(b"IEND"), Vec::new()),
+        ]);
+
+        // same format, so both chunks still apply
+        let mut brighter = rgb.clone();
+        brighter.samples_mut()[0] = 9;
+        let dropped = png
+            .set_pixels(&brighter, UnsafeChunkPolicy::Preserve)
+            .unwrap();
+        assert!(dropped.is_empty());
+
+        let gray = PixelData::new(
+            ImageHeader {
+                color_type: ColorType::Grayscale,
+                ..*rgb.header()
+            },
+            vec![1, 2],
+        )
+        .unwrap();
+        let dropped = png.set_pixels(&gray, UnsafeChunkPolicy::Preserve).unwrap();
+        let dropped_types: Vec<String> = dropped
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(dropped_types, ["sBIT", "tRNS"]);
+        assert_eq!(chunk_types(&png), ["IHDR", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_replace_critical_inserts_missing_plte() {
+        let mut png = testing_image_png();
+        png.replace_critical_chunks(
+            vec![chunk_from_strings("PLTE", "palette").unwrap()],
+            UnsafeChunkPolicy::Preserve,
+        )
+        .unwrap();
+
+        assert_eq!(&chunk_types(&png)[3], "PLTE");
+    }
+
+    #[test]
+    fn test_replace_critical_rejects_ancillary() {
+        let mut png = testing_image_png();
+        let result = png.replace_critical_chunks(
+            vec![chunk_from_strings("tEXt", "text").unwrap()],
+            UnsafeChunkPolicy::Drop,
+        );
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
         assert!(png.is_ok());
     }
 
+    #[test]
+    fn test_pixels_from_image_file() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let pixels = PixelData::decode(&png).unwrap();
+        assert_eq!((pixels.width(), pixels.height()), (50, 50));
+
+        let mut rebuilt = Png::try_from(&PNG_FILE[..]).unwrap();
+        rebuilt
+            .set_pixels(&pixels, UnsafeChunkPolicy::Drop)
+            .unwrap();
+        assert_eq!(PixelData::decode(&rebuilt).unwrap(), pixels);
+    }
+
+    #[test]
+    fn test_split_trailing_data() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend_from_slice(b"appended");
+
+        assert!(Png::try_from(bytes.as_ref()).is_err());
+        let (png, trailing) = Png::split_trailing_data(&bytes).unwrap();
+        assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+        assert_eq!(trailing, b"appended");
+    }
+
     #[test]
     fn test_as_bytes() {
         let png = Png::try_from(&PNG_FILE[..]).unwrap();
@@ -295,7 +956,7 @@ mod tests {
     }
 
     // This is the raw bytes for a shrunken version of the `dice.png` image on Wikipedia
-    const PNG_FILE: [u8; 4803] = [
+    pub(crate) const PNG_FILE: [u8; 4803] = [
         137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 50, 0, 0, 0, 50, 8,
         6, 0, 0, 0, 30, 63, 136, 177, 0, 0, 0, 1, 115, 82, 71, 66, 0, 174, 206, 28, 233, 0, 0, 0,
         4, 103, 65, 77, 65, 0, 0, 177, 143, 11, 252, 97, 5, 0, 0, 0, 9, 112, 72, 89, 115, 0, 0, 14,
@@ -0,0 +1,412 @@
+use crate::chunk::Chunk;
+use crate::chunk_types::ChunkType;
+use crate::pixels::ImageHeader;
+use crate::png::Png;
+use crate::{Error, Result};
+
+use crc::{CRC_32_ISO_HDLC, Crc};
+use flate2::read::DeflateDecoder;
+use std::io::Read;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054b50;
+
+/// Size of the end of central directory record without its comment
+const END_OF_CENTRAL_DIRECTORY_LEN: usize = 22;
+
+/// Size of a central directory header without its name, extra field and comment
+const CENTRAL_HEADER_LEN: usize = 46;
+
+/// Size of a local file header without its name and extra field
+const LOCAL_HEADER_LEN: usize = 30;
+
+/// Where the ZIP archive goes inside the PNG
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ZipPlacement {
+    /// After the `IEND` chunk
+    Trailing,
+    /// Inside an ancillary chunk of this type, right before `IEND`. The ZIP comment is
+    /// stretched over the chunk CRC and `IEND` so the archive still ends the file.
+    Chunk(ChunkType),
+}
+
+/// One file of a ZIP archive, as listed in the central directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZipEntry {
+    pub name: String,
+    pub method: u16,
+    pub crc: u32,
+    pub compressed_size: u32,
+    pub uncompressed_size: u32,
+    pub local_header_offset: u32,
+    /// Position of this entry's central directory header in the file
+    header_position: usize,
+}
+
+/// The parts of a ZIP archive needed to move it around inside another file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZipArchive {
+    entries: Vec<ZipEntry>,
+    /// Position of the end of central directory record in the file
+    end_position: usize,
+    central_directory_offset: u32,
+    comment_length: u16,
+}
+
+impl ZipArchive {
+    /// Finds the end of central directory record at the end of `data` and reads the
+    /// central directory it points to. ZIP64 archives are not supported.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let end_position = (0..=data.len().saturating_sub(END_OF_CENTRAL_DIRECTORY_LEN))
+            .rev()
+            .take(u16::MAX as usize + 1)
+            .find(|&position| {
+                read_u32(data, position) == Some(END_OF_CENTRAL_DIRECTORY_SIGNATURE)
+                    && read_u16(data, position + 20)
+                        .map(|len| position + END_OF_CENTRAL_DIRECTORY_LEN + len as usize)
+                        == Some(data.len())
+            })
+            .ok_or("No ZIP end of central directory record found")?;
+
+        let field = |offset: usize| read_u32(data, end_position + offset).unwrap();
+        let short_field = |offset: usize| read_u16(data, end_position + offset).unwrap();
+        let entry_count = short_field(10);
+        let central_directory_offset = field(16);
+        if entry_count == u16::MAX || central_directory_offset == u32::MAX {
+            return Err("ZIP64 archives are not supported".into());
+        }
+        if short_field(4) != 0 || short_field(6) != 0 {
+            return Err("Multi-disk ZIP archives are not supported".into());
+        }
+
+        let mut entries = Vec::with_capacity(entry_count as usize);
+        let mut position = central_directory_offset as usize;
+        for _ in 0..entry_count {
+            if read_u32(data, position) != Some(CENTRAL_HEADER_SIGNATURE) {
+                return Err(format!("No central directory header at offset {}", position).into());
+            }
+            let field = |offset: usize| read_u32(data, position + offset).unwrap_or(0);
+            let short_field =
+                |offset: usize| read_u16(data, position + offset).unwrap_or(0) as usize;
+
+            let name_len = short_field(28);
+            let name = data
+                .get(position + CENTRAL_HEADER_LEN..position + CENTRAL_HEADER_LEN + name_len)
+                .ok_or("Central directory is cut off")?;
+
+            entries.push(ZipEntry {
+                name: String::from_utf8_lossy(name).into_owned(),
+                method: short_field(10) as u16,
+                crc: field(16),
+                compressed_size: field(20),
+                uncompressed_size: field(24),
+                local_header_offset: field(42),
+                header_position: position,
+            });
+            position += CENTRAL_HEADER_LEN + name_len + short_field(30) + short_field(32);
+        }
+
+        Ok(ZipArchive {
+            entries,
+            end_position,
+            central_directory_offset,
+            comment_length: short_field(20),
+        })
+    }
+
+    pub fn entries(&self) -> &[ZipEntry] {
+        &self.entries
+    }
+
+    /// Checks that every entry's local header is where the central directory says it is
+    /// and that its data decompresses to the recorded CRC. Only stored and deflated
+    /// entries can be checked.
+    pub fn verify(&self, data: &[u8]) -> Result<()> {
+        let crc = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+        for entry in &self.entries {
+            let position = entry.local_header_offset as usize;
+            let fail = |reason: &str| -> Error { format!("{}: {}", entry.name, reason).into() };
+
+            if read_u32(data, position) != Some(LOCAL_HEADER_SIGNATURE) {
+                return Err(fail("local header not found at its offset"));
+            }
+            let name_len = read_u16(data, position + 26).unwrap_or(0) as usize;
+            let extra_len = read_u16(data, position + 28).unwrap_or(0) as usize;
+            let start = position + LOCAL_HEADER_LEN + name_len + extra_len;
+            let compressed = data
+                .get(start..start + entry.compressed_size as usize)
+                .ok_or_else(|| fail("data is cut off"))?;
+
+            let contents = match entry.method {
+                0 => compressed.to_vec(),
+                8 => {
+                    let mut contents = Vec::new();
+                    DeflateDecoder::new(compressed)
+                        .read_to_end(&mut contents)
+                        .map_err(|e| fail(&e.to_string()))?;
+                    contents
+                }
+                method => {
+                    return Err(fail(&format!(
+                        "compression method {} not supported",
+                        method
+                    )));
+                }
+            };
+
+            if contents.len() != entry.uncompressed_size as usize
+                || crc.checksum(&contents) != entry.crc
+            {
+                return Err(fail("CRC mismatch"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the archive with every offset moved by `shift` bytes, for when it is placed
+    /// `shift` bytes into another file, and the comment made `extra_comment` bytes longer
+    /// to swallow whatever follows the archive
+    fn relocate(&self, data: &[u8], shift: u32, extra_comment: u16) -> Result<Vec<u8>> {
+        let overflow = || -> Error { "ZIP archive doesn't fit at this offset".into() };
+        let mut relocated = data.to_vec();
+        let write_u32 = |bytes: &mut Vec<u8>, position: usize, value: u32| {
+            bytes[position..position + 4].copy_from_slice(&value.to_le_bytes());
+        };
+
+        for entry in &self.entries {
+            let offset = entry
+                .local_header_offset
+                .checked_add(shift)
+                .ok_or_else(overflow)?;
+            write_u32(&mut relocated, entry.header_position + 42, offset);
+        }
+
+        let offset = self
+            .central_directory_offset
+            .checked_add(shift)
+            .ok_or_else(overflow)?;
+        write_u32(&mut relocated, self.end_position + 16, offset);
+
+        let comment_length = self
+            .comment_length
+            .checked_add(extra_comment)
+            .ok_or("ZIP comment would become too long")?;
+        relocated[self.end_position + 20..self.end_position + 22]
+            .copy_from_slice(&comment_length.to_le_bytes());
+
+        Ok(relocated)
+    }
+}
+
+/// Builds a file that is both `png` and the ZIP archive `zip`
+pub fn embed_zip(png: &Png, zip: &[u8], placement: &ZipPlacement) -> Result<Vec<u8>> {
+    let archive = ZipArchive::parse(zip)?;
+    if archive.end_position + END_OF_CENTRAL_DIRECTORY_LEN + archive.comment_length as usize
+        != zip.len()
+    {
+        return Err("ZIP archive has trailing data".into());
+    }
+
+    match placement {
+        ZipPlacement::Trailing => {
+            let mut bytes = png.as_bytes();
+            let shift = u32::try_from(bytes.len())?;
+            bytes.extend(archive.relocate(zip, shift, 0)?);
+            Ok(bytes)
+        }
+        ZipPlacement::Chunk(chunk_type) => {
+            if chunk_type.is_critical() {
+                return Err("The ZIP archive must go into an ancillary chunk".into());
+            }
+
+            let mut png = Png::from_chunks(png.chunks().to_vec());
+            png.append_chunk(Chunk::new(chunk_type.clone(), zip.to_vec()));
+
+            // the chunk went right before IEND, or last if there is none
+            let index = png
+                .chunks()
+                .iter()
+                .rposition(|chunk| chunk.chunk_type() == chunk_type)
+                .unwrap();
+            let chunk_offset: usize = Png::STANDARD_HEADER.len()
+                + png.chunks()[..index]
+                    .iter()
+                    .map(|chunk| chunk.as_bytes().len())
+                    .sum::<usize>();
+            let following: usize = 4 + png.chunks()[index + 1..]
+                .iter()
+                .map(|chunk| chunk.as_bytes().len())
+                .sum::<usize>();
+
+            let relocated = archive.relocate(
+                zip,
+                u32::try_from(chunk_offset + 8)?,
+                u16::try_from(following)?,
+            )?;
+            png.replace_chunk(index, Chunk::new(chunk_type.clone(), relocated))?;
+
+            Ok(png.as_bytes())
+        }
+    }
+}
+
+/// Parses `bytes` both as a PNG and as a ZIP archive and describes what was found
+pub fn verify_polyglot(bytes: &[u8]) -> Result<String> {
+    let (png, _) = Png::split_trailing_data(bytes)
+        .map_err(|e| -> Error { format!("Not a valid PNG: {}", e).into() })?;
+    png.check_ordering()
+        .map_err(|e| -> Error { format!("Not a valid PNG: {}", e).into() })?;
+    let header = ImageHeader::from_png(&png)?;
+    crate::pixels::PixelData::decode(&png)
+        .map_err(|e| -> Error { format!("PNG image data is broken: {}", e).into() })?;
+
+    let archive = ZipArchive::parse(bytes)
+        .map_err(|e| -> Error { format!("Not a valid ZIP archive: {}", e).into() })?;
+    archive
+        .verify(bytes)
+        .map_err(|e| -> Error { format!("ZIP entry is broken: {}", e).into() })?;
+
+    let names: Vec<&str> = archive.entries().iter().map(|e| e.name.as_str()).collect();
+    Ok(format!(
+        "PNG: {}\nZIP: {} entries ({})",
+        header,
+        names.len(),
+        names.join(", ")
+    ))
+}
+
+fn read_u16(data: &[u8], position: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(position..position + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(data: &[u8], position: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(position..position + 4)?.try_into().ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::tests::PNG_FILE;
+    use flate2::Compression;
+    use flate2::write::DeflateEncoder;
+    use std::io::Write;
+    use std::str::FromStr;
+
+    /// Builds a ZIP archive with one stored and one deflated file
+    fn testing_zip() -> Vec<u8> {
+        let crc = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+        let files: [(&str, &[u8], u16); 2] = [
+            ("hello.txt", b"hello polyglot", 0),
+            ("notes.txt", b"notes notes notes notes notes", 8),
+        ];
+
+        let mut zip = Vec::new();
+        let mut central = Vec::new();
+        for (name, contents, method) in files {
+            let data = if method == 8 {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(contents).unwrap();
+                encoder.finish().unwrap()
+            } else {
+                contents.to_vec()
+            };
+            let offset = zip.len() as u32;
+            let sizes = [
+                crc.checksum(contents),
+                data.len() as u32,
+                contents.len() as u32,
+            ];
+
+            zip.extend(LOCAL_HEADER_SIGNATURE.to_le_bytes());
+            zip.extend([20, 0, 0, 0]);
+            zip.extend(method.to_le_bytes());
+            zip.extend([0; 4]);
+            sizes.iter().for_each(|v| zip.extend(v.to_le_bytes()));
+            zip.extend((name.len() as u16).to_le_bytes());
+            zip.extend([0; 2]);
+            zip.extend(name.as_bytes());
+            zip.extend(&data);
+
+            central.extend(CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+            central.extend([20, 0, 20, 0, 0, 0]);
+            central.extend(method.to_le_bytes());
+            central.extend([0; 4]);
+            sizes.iter().for_each(|v| central.extend(v.to_le_bytes()));
+            central.extend((name.len() as u16).to_le_bytes());
+            central.extend([0; 12]);
+            central.extend(offset.to_le_bytes());
+            central.extend(name.as_bytes());
+        }
+
+        let central_offset = zip.len() as u32;
+        zip.extend(&central);
+        zip.extend(END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes());
+        zip.extend([0; 4]);
+        zip.extend(2u16.to_le_bytes());
+        zip.extend(2u16.to_le_bytes());
+        zip.extend((central.len() as u32).to_le_bytes());
+        zip.extend(central_offset.to_le_bytes());
+        zip.extend([0; 2]);
+        zip
+    }
+
+    fn testing_png() -> Png {
+        Png::try_from(&PNG_FILE[..]).unwrap()
+    }
+
+    #[test]
+    fn test_parse_zip() {
+        let zip = testing_zip();
+        let archive = ZipArchive::parse(&zip).unwrap();
+        let names: Vec<&str> = archive.entries().iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["hello.txt", "notes.txt"]);
+        assert!(archive.verify(&zip).is_ok());
+    }
+
+    #[test]
+    fn test_parse_not_zip() {
+        assert!(ZipArchive::parse(&PNG_FILE).is_err());
+    }
+
+    #[test]
+    fn test_trailing_polyglot() {
+        let bytes = embed_zip(&testing_png(), &testing_zip(), &ZipPlacement::Trailing).unwrap();
+        assert!(bytes.starts_with(&PNG_FILE));
+        assert!(verify_polyglot(&bytes).is_ok());
+    }
+
+    #[test]
+    fn test_chunk_polyglot() {
+        let placement = ZipPlacement::Chunk(ChunkType::from_str("arCh").unwrap());
+        let bytes = embed_zip(&testing_png(), &testing_zip(), &placement).unwrap();
+
+        // the file is a strictly valid PNG with no trailing data
+        let png = Png::try_from(bytes.as_ref()).unwrap();
+        assert!(png.chunk_by_type("arCh").is_some());
+        assert_eq!(
+            png.chunks().last().unwrap().chunk_type().to_string(),
+            "IEND"
+        );
+        assert!(verify_polyglot(&bytes).is_ok());
+    }
+
+    #[test]
+    fn test_unshifted_zip_fails_verification() {
+        let mut bytes = testing_png().as_bytes();
+        bytes.extend(testing_zip());
+        assert!(verify_polyglot(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_critical_chunk_rejected() {
+        let placement = ZipPlacement::Chunk(ChunkType::from_str("ZIPS").unwrap());
+        assert!(embed_zip(&testing_png(), &testing_zip(), &placement).is_err());
+    }
+}
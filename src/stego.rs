@@ -0,0 +1,1729 @@
+use crate::adaptive;
+use crate::apng::Animation;
+use crate::chunk::Chunk;
+use crate::chunk_types::ChunkType;
+use crate::fec::{self, FecOptions};
+use crate::palette::{MAX_PALETTE_ENTRIES, Palette, PaletteEntry, Transparency};
+use crate::pixels::{ColorType, PixelData};
+use crate::png::{ChunkPosition, Png, UnsafeChunkPolicy};
+use crate::{Error, Result};
+use rand_chacha::ChaCha20Rng;
+use rand_chacha::rand_core::{RngCore, SeedableRng};
+use sha2::Sha256;
+
+/// Marks the start of every payload written by this module
+pub const MAGIC: [u8; 4] = *b"PNGm";
+
+/// Size of the payload header in bytes
+pub const HEADER_LEN: usize = 16;
+
+/// Replaces `MAGIC` in the header of payloads protected by error correction
+pub const FEC_MAGIC: [u8; 4] = *b"PNGf";
+
+/// Check bytes protecting the header and error correction settings of a protected payload
+const FEC_HEADER_PARITY: usize = 16;
+
+/// Size of the protected header: payload header, redundancy, interleave and check bytes
+pub const FEC_HEADER_LEN: usize = HEADER_LEN + 2 + FEC_HEADER_PARITY;
+
+/// PBKDF2 rounds used to turn a passphrase into an `EmbedKey`
+const KEY_ROUNDS: u32 = 100_000;
+
+/// Largest Hamming code for matrix embedding: 255 samples carrying 8 bits
+pub const MAX_MATRIX_BITS: u8 = 8;
+
+/// The header written in front of every embedded payload. A message can be split into
+/// several parts, e.g. one per animation frame, which are put back together by `part`.
+/// `label` is the chunk type given on the command line so several messages can coexist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayloadHeader {
+    pub label: ChunkType,
+    pub part: u16,
+    pub parts: u16,
+    pub length: u32,
+}
+
+impl PayloadHeader {
+    /// Returns this header as the bytes stored in front of the payload
+    pub fn to_bytes(&self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0u8; HEADER_LEN];
+        bytes[0..4].copy_from_slice(&MAGIC);
+        bytes[4..8].copy_from_slice(&self.label.bytes());
+        bytes[8..10].copy_from_slice(&self.part.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.parts.to_be_bytes());
+        bytes[12..16].copy_from_slice(&self.length.to_be_bytes());
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for PayloadHeader {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < HEADER_LEN || bytes[0..4] != MAGIC {
+            return Err("No embedded payload found".into());
+        }
+
+        let header = PayloadHeader {
+            label: ChunkType::try_from([bytes[4], bytes[5], bytes[6], bytes[7]])?,
+            part: u16::from_be_bytes([bytes[8], bytes[9]]),
+            parts: u16::from_be_bytes([bytes[10], bytes[11]]),
+            length: u32::from_be_bytes(bytes[12..16].try_into()?),
+        };
+
+        if header.parts == 0 || header.part >= header.parts {
+            return Err("Corrupt payload header".into());
+        }
+
+        Ok(header)
+    }
+}
+
+/// One framed part of a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Payload {
+    pub header: PayloadHeader,
+    pub data: Vec<u8>,
+    /// Number of wrong bytes fixed by error correction while reading the payload
+    pub corrected: usize,
+}
+
+impl Payload {
+    /// Returns the header followed by the data
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.header.to_bytes().to_vec();
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+
+    /// Returns the payload as it is embedded. Without `fec` this is `to_bytes`, otherwise
+    /// the header and the settings get their own Reed-Solomon codeword, followed by the
+    /// protected data.
+    pub fn to_frame(&self, fec: Option<&FecOptions>) -> Vec<u8> {
+        let Some(fec) = fec else {
+            return self.to_bytes();
+        };
+
+        let mut header = self.header.to_bytes().to_vec();
+        header[0..4].copy_from_slice(&FEC_MAGIC);
+        header.extend([fec.redundancy, fec.interleave]);
+
+        let mut frame = fec::encode_block(&header, FEC_HEADER_PARITY);
+        frame.extend(fec::protect(&self.data, fec));
+        frame
+    }
+
+    /// Size of the frame starting with `prefix`, which has to hold at least
+    /// `FEC_HEADER_LEN` bytes for protected frames
+    pub fn frame_len(prefix: &[u8]) -> Result<usize> {
+        let (header, fec, _) = Payload::frame_header(prefix)?;
+        let length = header.length as usize;
+        Ok(match fec {
+            Some(fec) => FEC_HEADER_LEN + fec.protected_len(length),
+            None => HEADER_LEN + length,
+        })
+    }
+
+    /// Reads a frame written by `to_frame`, correcting errors if it is protected
+    pub fn from_frame(frame: &[u8]) -> Result<Self> {
+        let (header, fec, header_corrected) = Payload::frame_header(frame)?;
+        let Some(fec) = fec else {
+            return Payload::try_from(frame);
+        };
+
+        let protected = frame.get(FEC_HEADER_LEN..).unwrap_or_default();
+        let (data, corrected) = fec::recover(protected, header.length as usize, &fec)?;
+        Ok(Payload {
+            header,
+            data,
+            corrected: header_corrected + corrected,
+        })
+    }
+
+    /// Parses the header of a plain or protected frame, returning the error correction
+    /// settings and the number of corrected header bytes
+    fn frame_header(frame: &[u8]) -> Result<(PayloadHeader, Option<FecOptions>, usize)> {
+        if frame.starts_with(&MAGIC) {
+            return Ok((PayloadHeader::try_from(frame)?, None, 0));
+        }
+
+        let mut block = frame
+            .get(..FEC_HEADER_LEN)
+            .ok_or("No embedded payload found")?
+            .to_vec();
+        let corrected = fec::decode_block(&mut block, FEC_HEADER_PARITY)
+            .map_err(|_| "No embedded payload found")?;
+        if block[0..4] != FEC_MAGIC {
+            return Err("No embedded payload found".into());
+        }
+
+        block[0..4].copy_from_slice(&MAGIC);
+        let header = PayloadHeader::try_from(&block[..HEADER_LEN])?;
+        let fec = FecOptions::new(block[HEADER_LEN], block[HEADER_LEN + 1])?;
+        Ok((header, Some(fec), corrected))
+    }
+}
+
+impl TryFrom<&[u8]> for Payload {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        let header = PayloadHeader::try_from(bytes)?;
+        let end = HEADER_LEN + header.length as usize;
+        let data = bytes
+            .get(HEADER_LEN..end)
+            .ok_or("Payload is shorter than its header claims")?
+            .to_vec();
+
+        Ok(Payload {
+            header,
+            data,
+            corrected: 0,
+        })
+    }
+}
+
+/// A message read back from an image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extracted {
+    pub message: Vec<u8>,
+    /// Number of wrong bytes fixed by error correction, over all parts
+    pub corrected: usize,
+}
+
+/// Settings shared by the embedding functions. Extraction needs the same `key` and
+/// `adaptive` threshold to find the carriers again.
+#[derive(Debug, Clone, Default)]
+pub struct EmbedOptions {
+    pub policy: UnsafeChunkPolicy,
+    /// Protects the payload with Reed-Solomon codes if set
+    pub fec: Option<FecOptions>,
+    /// Scatters LSB payloads over the image in a keyed order if set
+    pub key: Option<EmbedKey>,
+    /// Bits hidden in each group of `2^matrix_bits - 1` samples with a Hamming code, changing
+    /// at most one of them. 0 and 1 both mean plain LSB replacement.
+    pub matrix_bits: u8,
+    /// Changes samples by adding or subtracting one at random instead of overwriting their
+    /// least significant bit, which avoids the pairs of values artifact of LSB replacement
+    pub lsb_matching: bool,
+    /// Only embeds in pixels whose `adaptive::texture` is at least this, skipping flat
+    /// regions where changes are easier to see
+    pub adaptive: Option<u32>,
+}
+
+impl EmbedOptions {
+    /// Returns these options for animation frame `index`, which gets its own key stream
+    fn for_frame(&self, index: usize) -> Self {
+        EmbedOptions {
+            key: self.key.as_ref().map(|key| key.with_stream(index as u64)),
+            ..self.clone()
+        }
+    }
+}
+
+/// How many payload bits an LSB embedding wrote and how many samples that changed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LsbStats {
+    pub bits: usize,
+    pub changed: usize,
+}
+
+impl LsbStats {
+    /// Payload bits per changed sample. Plain LSB replacement averages 2, a Hamming code
+    /// with k bits per group k / (1 - 2^-k).
+    pub fn efficiency(&self) -> f64 {
+        self.bits as f64 / self.changed.max(1) as f64
+    }
+}
+
+impl std::ops::AddAssign for LsbStats {
+    fn add_assign(&mut self, other: Self) {
+        self.bits += other.bits;
+        self.changed += other.changed;
+    }
+}
+
+impl std::fmt::Display for LsbStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Changed {} samples to embed {} bits, {:.2} bits per change",
+            self.changed,
+            self.bits,
+            self.efficiency()
+        )
+    }
+}
+
+/// Seeds the ChaCha20 generator that picks the order of the carrier samples, so the
+/// payload bits can't be located without the passphrase
+#[derive(Clone, PartialEq, Eq)]
+pub struct EmbedKey {
+    seed: [u8; 32],
+    /// Separate generator stream, e.g. one per animation frame
+    stream: u64,
+}
+
+impl EmbedKey {
+    /// Derives a key from `passphrase` with PBKDF2-HMAC-SHA256, salted with the label so
+    /// the same passphrase gives unrelated orders for different messages
+    pub fn from_passphrase(passphrase: &str, label: &ChunkType) -> Self {
+        let mut salt = MAGIC.to_vec();
+        salt.extend_from_slice(&label.bytes());
+
+        let mut seed = [0u8; 32];
+        pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), &salt, KEY_ROUNDS, &mut seed);
+        EmbedKey { seed, stream: 0 }
+    }
+
+    /// Returns the same key using generator stream `stream`
+    pub fn with_stream(&self, stream: u64) -> Self {
+        EmbedKey {
+            seed: self.seed,
+            stream,
+        }
+    }
+
+    /// Shuffles `positions` with a Fisher-Yates shuffle driven by the keyed generator
+    fn shuffle(&self, positions: &mut [usize]) {
+        let mut rng = ChaCha20Rng::from_seed(self.seed);
+        rng.set_stream(self.stream);
+
+        for i in (1..positions.len()).rev() {
+            positions.swap(i, random_below(&mut rng, i as u64 + 1) as usize);
+        }
+    }
+}
+
+impl std::fmt::Debug for EmbedKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // never print the seed
+        f.debug_struct("EmbedKey")
+            .field("stream", &self.stream)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Uniform random number in `0..bound`, rejecting the values that would bias the modulo
+fn random_below(rng: &mut ChaCha20Rng, bound: u64) -> u64 {
+    let zone = u64::MAX - u64::MAX % bound;
+    loop {
+        let value = rng.next_u64();
+        if value < zone {
+            return value % bound;
+        }
+    }
+}
+
+/// Splits `message` into `parts` framed payloads of roughly equal size
+pub fn split_message(label: &ChunkType, message: &[u8], parts: usize) -> Result<Vec<Payload>> {
+    if parts == 0 || parts > u16::MAX as usize {
+        return Err("Invalid number of payload parts".into());
+    }
+
+    let part_len = message.len().div_ceil(parts).max(1);
+    let mut payloads: Vec<Payload> = message
+        .chunks(part_len)
+        .map(|data| data.to_vec())
+        .chain(std::iter::repeat(Vec::new()))
+        .take(parts)
+        .enumerate()
+        .map(|(part, data)| Payload {
+            header: PayloadHeader {
+                label: label.clone(),
+                part: part as u16,
+                parts: parts as u16,
+                length: data.len() as u32,
+            },
+            data,
+            corrected: 0,
+        })
+        .collect();
+
+    // an empty message still needs one part to carry the header
+    if payloads.is_empty() {
+        payloads.push(Payload {
+            header: PayloadHeader {
+                label: label.clone(),
+                part: 0,
+                parts: 1,
+                length: 0,
+            },
+            data: Vec::new(),
+            corrected: 0,
+        });
+    }
+
+    Ok(payloads)
+}
+
+/// Puts the parts labelled `label` back together. Fails if any part is missing.
+pub fn join_parts(label: &ChunkType, payloads: Vec<Payload>) -> Result<Extracted> {
+    let mut parts: Vec<Payload> = payloads
+        .into_iter()
+        .filter(|payload| &payload.header.label == label)
+        .collect();
+
+    let expected = parts
+        .first()
+        .ok_or_else(|| -> Error { format!("No message labelled {} found", label).into() })?
+        .header
+        .parts;
+    parts.sort_by_key(|payload| payload.header.part);
+    parts.dedup_by_key(|payload| payload.header.part);
+
+    if parts.len() != expected as usize {
+        return Err(format!("Found {} of {} message parts", parts.len(), expected).into());
+    }
+
+    Ok(Extracted {
+        corrected: parts.iter().map(|payload| payload.corrected).sum(),
+        message: parts.into_iter().flat_map(|payload| payload.data).collect(),
+    })
+}
+
+/// Indices of the samples that carry payload bits: every color sample, skipping alpha.
+/// Indexed images are rejected since changing an index changes the color completely.
+/// With a key the samples are visited in a keyed pseudo-random order instead of in a row,
+/// with an adaptive threshold only samples of busy pixels are used.
+fn carrier_samples(pixels: &PixelData, options: &EmbedOptions) -> Result<Vec<usize>> {
+    let channels = pixels.channels();
+    let color_channels = match pixels.header().color_type {
+        ColorType::Indexed => {
+            return Err("LSB embedding doesn't work on indexed images".into());
+        }
+        ColorType::GrayscaleAlpha | ColorType::Rgba => channels - 1,
+        _ => channels,
+    };
+
+    let texture = options
+        .adaptive
+        .map(|_| adaptive::texture(pixels, color_channels));
+    let threshold = options.adaptive.unwrap_or(0);
+
+    let mut carriers: Vec<usize> = (0..pixels.samples().len())
+        .filter(|index| index % channels < color_channels)
+        .filter(|index| {
+            texture
+                .as_ref()
+                .is_none_or(|texture| texture[index / channels] >= threshold)
+        })
+        .collect();
+    if let Some(key) = &options.key {
+        key.shuffle(&mut carriers);
+    }
+
+    Ok(carriers)
+}
+
+/// Maximum number of message bytes that fit into the pixels with `options`, after the
+/// payload header. Matrix embedding and adaptive thresholds both lower the capacity.
+pub fn lsb_capacity(pixels: &PixelData, options: &EmbedOptions) -> Result<usize> {
+    let k = options.matrix_bits.clamp(1, MAX_MATRIX_BITS) as usize;
+    let bits = carrier_count(pixels, options)? / ((1 << k) - 1) * k;
+    Ok(message_capacity(bits / 8, options.fec.as_ref()))
+}
+
+/// Number of samples that can carry a payload bit with `options`
+pub fn carrier_count(pixels: &PixelData, options: &EmbedOptions) -> Result<usize> {
+    // the order doesn't change the count, so skip the shuffle
+    let unkeyed = EmbedOptions {
+        key: None,
+        ..options.clone()
+    };
+    Ok(carrier_samples(pixels, &unkeyed)?.len())
+}
+
+/// Number of message bytes left in a frame of `frame_len` bytes after the payload header
+/// and, if given, the error correction overhead
+pub fn message_capacity(frame_len: usize, fec: Option<&FecOptions>) -> usize {
+    match fec {
+        Some(fec) => fec.max_data_len(frame_len.saturating_sub(FEC_HEADER_LEN)),
+        None => frame_len.saturating_sub(HEADER_LEN),
+    }
+}
+
+/// XOR of the 1-based positions in `group` whose sample has its least significant bit set.
+/// This is the syndrome of the group's bits under the Hamming code.
+fn syndrome(samples: &[u16], group: &[usize]) -> usize {
+    group
+        .iter()
+        .enumerate()
+        .filter(|(_, index)| samples[**index] & 1 == 1)
+        .fold(0, |acc, (position, _)| acc ^ (position + 1))
+}
+
+/// Writes a payload frame into the least significant bit of the color samples, most
+/// significant bit of every byte first. With matrix embedding the carriers are split into
+/// groups of `2^k - 1` whose syndrome is the next k bits, so at most one sample in a group
+/// changes.
+pub fn embed_lsb(pixels: &mut PixelData, bytes: &[u8], options: &EmbedOptions) -> Result<LsbStats> {
+    if options.matrix_bits > MAX_MATRIX_BITS {
+        return Err(format!(
+            "Matrix embedding supports at most {} bits per group",
+            MAX_MATRIX_BITS
+        )
+        .into());
+    }
+
+    if options.lsb_matching && options.adaptive.is_some() {
+        return Err(
+            "LSB matching can't be combined with adaptive embedding, it changes the texture".into(),
+        );
+    }
+
+    let carriers = carrier_samples(pixels, options)?;
+    let k = options.matrix_bits.max(1) as usize;
+    let group_len = (1 << k) - 1;
+
+    let bits: Vec<u8> = bytes
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |shift| (byte >> shift) & 1))
+        .collect();
+    let capacity = carriers.len() / group_len * k;
+    if bits.len().div_ceil(k) * k > capacity {
+        return Err(format!(
+            "Payload needs {} bytes but only {} fit into the image",
+            bytes.len(),
+            capacity / 8
+        )
+        .into());
+    }
+
+    let mut stats = LsbStats {
+        bits: bits.len(),
+        changed: 0,
+    };
+    let max = pixels.header().max_sample();
+    let samples = pixels.samples_mut();
+    for (message, group) in bits.chunks(k).zip(carriers.chunks_exact(group_len)) {
+        // a short last group is padded with zero bits
+        let wanted = message
+            .iter()
+            .fold(0, |acc, &bit| (acc << 1) | bit as usize)
+            << (k - message.len());
+        let flip = syndrome(samples, group) ^ wanted;
+        if flip != 0 {
+            let sample = &mut samples[group[flip - 1]];
+            *sample = change_lsb(*sample, max, options.lsb_matching);
+            stats.changed += 1;
+        }
+    }
+
+    Ok(stats)
+}
+
+/// Flips the least significant bit of `sample`. With LSB matching one is added or
+/// subtracted at random instead, staying within `0..=max`.
+fn change_lsb(sample: u16, max: u16, matching: bool) -> u16 {
+    if !matching {
+        return sample ^ 1;
+    }
+
+    match sample {
+        0 => 1,
+        s if s == max => s - 1,
+        s if rand::random() => s + 1,
+        s => s - 1,
+    }
+}
+
+/// Reads a payload written by `embed_lsb` with the same key. The group size of matrix
+/// embedding isn't stored, so every size is tried until one gives a valid payload.
+pub fn extract_lsb(pixels: &PixelData, options: &EmbedOptions) -> Result<Payload> {
+    let carriers = carrier_samples(pixels, options)?;
+    let samples = pixels.samples();
+
+    let plain = read_payload(carriers.len(), |index| (samples[carriers[index]] & 1) as u8);
+    if plain.is_ok() {
+        return plain;
+    }
+
+    (2..=MAX_MATRIX_BITS as usize)
+        .find_map(|k| {
+            let groups: Vec<&[usize]> = carriers.chunks_exact((1 << k) - 1).collect();
+            read_payload(groups.len() * k, |index| {
+                ((syndrome(samples, groups[index / k]) >> (k - 1 - index % k)) & 1) as u8
+            })
+            .ok()
+        })
+        .map_or(plain, Ok)
+}
+
+/// Reads a framed payload from `bit_count` bits, most significant bit of every byte first
+fn read_payload(bit_count: usize, bit: impl Fn(usize) -> u8) -> Result<Payload> {
+    let read_bytes = |start: usize, count: usize| -> Vec<u8> {
+        (start * 8..(start + count) * 8)
+            .collect::<Vec<usize>>()
+            .chunks(8)
+            .map(|byte| byte.iter().fold(0u8, |acc, &index| (acc << 1) | bit(index)))
+            .collect()
+    };
+
+    let available = bit_count / 8;
+    if available < HEADER_LEN {
+        return Err("Image is too small to hold a message".into());
+    }
+    let frame_len = Payload::frame_len(&read_bytes(0, available.min(FEC_HEADER_LEN)))?;
+    if frame_len > available {
+        return Err("Payload is longer than the image can hold".into());
+    }
+
+    Payload::from_frame(&read_bytes(0, frame_len))
+}
+
+/// Embeds `message` into the pixels of the default image
+pub fn embed_in_pixels(
+    png: &mut Png,
+    label: &ChunkType,
+    message: &[u8],
+    options: &EmbedOptions,
+) -> Result<(Vec<Chunk>, LsbStats)> {
+    let mut pixels = PixelData::decode(png)?;
+    let payload = split_message(label, message, 1)?.remove(0);
+    let stats = embed_lsb(
+        &mut pixels,
+        &payload.to_frame(options.fec.as_ref()),
+        options,
+    )?;
+
+    Ok((png.set_pixels(&pixels, options.policy)?, stats))
+}
+
+/// Reads a message embedded by `embed_in_pixels`
+pub fn extract_from_pixels(
+    png: &Png,
+    label: &ChunkType,
+    options: &EmbedOptions,
+) -> Result<Extracted> {
+    let payload = extract_lsb(&PixelData::decode(png)?, options)?;
+    join_parts(label, vec![payload])
+}
+
+/// Splits `message` over the pixels of every animation frame
+pub fn embed_in_frames(
+    png: &mut Png,
+    label: &ChunkType,
+    message: &[u8],
+    options: &EmbedOptions,
+) -> Result<(Vec<Chunk>, LsbStats)> {
+    let header = crate::pixels::ImageHeader::from_png(png)?;
+    if header.interlaced {
+        return Err("Interlaced animations are not supported".into());
+    }
+
+    let mut animation = Animation::from_png(png)?;
+    let frame_count = animation.frames().count();
+    let payloads = split_message(label, message, frame_count)?;
+
+    let mut stats = LsbStats::default();
+    for (index, payload) in payloads.iter().enumerate() {
+        let frame = animation.frames().nth(index).unwrap();
+        let mut pixels = frame.decode(&header)?;
+        stats += embed_lsb(
+            &mut pixels,
+            &payload.to_frame(options.fec.as_ref()),
+            &options.for_frame(index),
+        )
+        .map_err(|e| -> Error { format!("Frame {}: {}", index, e).into() })?;
+        animation.set_frame_data(index, pixels.encode()?)?;
+    }
+
+    Ok((animation.apply(png, options.policy)?, stats))
+}
+
+/// Maximum number of message bytes `embed_in_frames` fits into `png`. The message is split
+/// evenly, so the frame with the fewest carriers limits every part.
+pub fn frames_lsb_capacity(png: &Png, options: &EmbedOptions) -> Result<usize> {
+    let header = crate::pixels::ImageHeader::from_png(png)?;
+    if header.interlaced {
+        return Err("Interlaced animations are not supported".into());
+    }
+
+    let animation = Animation::from_png(png)?;
+    let mut smallest = usize::MAX;
+    for frame in animation.frames() {
+        smallest = smallest.min(lsb_capacity(&frame.decode(&header)?, options)?);
+    }
+    Ok(smallest.saturating_mul(animation.frames().count()))
+}
+
+/// Reads a message embedded by `embed_in_frames`
+pub fn extract_from_frames(
+    png: &Png,
+    label: &ChunkType,
+    options: &EmbedOptions,
+) -> Result<Extracted> {
+    let header = crate::pixels::ImageHeader::from_png(png)?;
+    let animation = Animation::from_png(png)?;
+
+    let payloads = animation
+        .frames()
+        .enumerate()
+        .filter_map(|(index, frame)| {
+            extract_lsb(&frame.decode(&header).ok()?, &options.for_frame(index)).ok()
+        })
+        .collect();
+
+    join_parts(label, payloads)
+}
+
+/// Splits `message` over chunks of `chunk_type`, one placed after the image data of every
+/// animation frame so they are interleaved with the `fdAT` chunks
+pub fn embed_in_chunks(
+    png: &mut Png,
+    chunk_type: &ChunkType,
+    message: &[u8],
+    options: &EmbedOptions,
+) -> Result<()> {
+    let frame_count = Animation::from_png(png)?.frames().count();
+    let payloads = split_message(chunk_type, message, frame_count)?;
+
+    // find the chunk that ends the image data of every frame
+    let mut frame_ends = Vec::new();
+    let mut current_frame: Option<usize> = None;
+    let mut frames_seen = 0;
+    let chunks = png.chunks();
+    for (index, chunk) in chunks.iter().enumerate() {
+        match &chunk.chunk_type().bytes() {
+            b"fcTL" => {
+                current_frame = Some(frames_seen);
+                frames_seen += 1;
+            }
+            t @ (b"IDAT" | b"fdAT") => {
+                let next_is_same = chunks
+                    .get(index + 1)
+                    .is_some_and(|next| next.chunk_type().bytes() == *t);
+                if let Some(frame) = current_frame
+                    && !next_is_same
+                    && (t == b"fdAT" || frame == 0)
+                {
+                    frame_ends.push(index + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // insert from the back so the earlier positions stay valid
+    for (position, payload) in frame_ends.iter().zip(&payloads).rev() {
+        png.insert_chunk(
+            Chunk::new(chunk_type.clone(), payload.to_frame(options.fec.as_ref())),
+            ChunkPosition::Index(*position),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Reads a message from every chunk of `chunk_type` written by `embed_in_chunks`
+pub fn extract_from_chunks(png: &Png, chunk_type: &ChunkType) -> Result<Extracted> {
+    let payloads = png
+        .chunks()
+        .iter()
+        .filter(|chunk| chunk.chunk_type() == chunk_type)
+        .map(|chunk| Payload::from_frame(chunk.data()))
+        .collect::<Result<Vec<Payload>>>()?;
+
+    join_parts(chunk_type, payloads)
+}
+
+/// Appends `message` to the last `IDAT` chunk, after the Adler-32 checksum that ends the zlib
+/// stream. Decoders stop reading there, so the pixels and every chunk CRC stay valid.
+/// The decoded image doesn't change, so unknown unsafe-to-copy chunks are kept whatever
+/// `options.policy` says. Returns the chunks that were dropped, see
+/// `Png::replace_critical_chunks`.
+pub fn embed_in_zlib(
+    png: &mut Png,
+    label: &ChunkType,
+    message: &[u8],
+    options: &EmbedOptions,
+) -> Result<Vec<Chunk>> {
+    let image_data = png.image_data();
+    if PixelData::stream_len(&image_data)? < image_data.len() {
+        return Err("Image data already continues after the zlib stream".into());
+    }
+
+    let mut idat: Vec<Chunk> = png
+        .chunks()
+        .iter()
+        .filter(|chunk| chunk.chunk_type().bytes() == *b"IDAT")
+        .cloned()
+        .collect();
+    let last = idat.pop().ok_or("Image has no IDAT chunk")?;
+    let payload = split_message(label, message, 1)?.remove(0);
+    let mut data = last.data().to_vec();
+    data.extend(payload.to_frame(options.fec.as_ref()));
+    idat.push(Chunk::new(ChunkType::new(b"IDAT"), data));
+
+    png.replace_critical_chunks(idat, UnsafeChunkPolicy::Preserve)
+}
+
+/// Reads a message embedded by `embed_in_zlib`
+pub fn extract_from_zlib(png: &Png, label: &ChunkType) -> Result<Extracted> {
+    let image_data = png.image_data();
+    let hidden = &image_data[PixelData::stream_len(&image_data)?..];
+    if hidden.is_empty() {
+        return Err("Nothing follows the zlib stream".into());
+    }
+
+    join_parts(label, vec![Payload::from_frame(hidden)?])
+}
+
+/// Largest channel difference between two palette colors that still counts as a
+/// near-duplicate for `embed_in_palette_parity`
+pub const NEAR_DUPLICATE_DISTANCE: u8 = 4;
+
+/// Decoded pixels of an indexed image together with its palette and the alpha of
+/// every palette entry
+fn indexed_image(png: &Png) -> Result<(PixelData, Palette, Vec<u8>)> {
+    let pixels = PixelData::decode(png)?;
+    if pixels.header().color_type != ColorType::Indexed {
+        return Err("Palette embedding only works on indexed images".into());
+    }
+
+    let palette = Palette::from_png(png)?;
+    let transparency = Transparency::from_png(png, ColorType::Indexed)?;
+    let alpha = (0..palette.len())
+        .map(|index| transparency.as_ref().map_or(255, |t| t.alpha(index)))
+        .collect();
+
+    if pixels
+        .samples()
+        .iter()
+        .any(|&s| s as usize >= palette.len())
+    {
+        return Err("Image uses palette indices beyond the end of the palette".into());
+    }
+
+    Ok((pixels, palette, alpha))
+}
+
+/// Writes a new palette and the pixels that index it back into `png`. The `tRNS` and
+/// `hIST` chunks follow the new palette and an indexed `bKGD` is moved along with its entry.
+fn write_palette(
+    png: &mut Png,
+    pixels: &PixelData,
+    palette: &Palette,
+    alpha: &[u8],
+    remap: Option<&[usize]>,
+    policy: UnsafeChunkPolicy,
+) -> Result<Vec<Chunk>> {
+    let image_data = Chunk::new(ChunkType::new(b"IDAT"), pixels.encode()?);
+    let dropped = png.replace_critical_chunks(
+        vec![pixels.header().to_chunk(), palette.to_chunk(), image_data],
+        policy,
+    )?;
+
+    let position = |png: &Png, t: &str| {
+        png.chunks()
+            .iter()
+            .position(|c| c.chunk_type().to_string() == t)
+    };
+
+    match (Transparency::from_alpha(alpha), position(png, "tRNS")) {
+        (Some(transparency), Some(index)) => {
+            png.replace_chunk(index, transparency.to_chunk())?;
+        }
+        (Some(transparency), None) => {
+            png.insert_chunk(
+                transparency.to_chunk(),
+                ChunkPosition::After(ChunkType::new(b"PLTE")),
+            )?;
+        }
+        (None, Some(_)) => {
+            png.remove_chunks("tRNS")?;
+        }
+        (None, None) => {}
+    }
+
+    if let Some(index) = position(png, "hIST") {
+        let mut counts = vec![0u64; palette.len()];
+        for &sample in pixels.samples() {
+            counts[sample as usize] += 1;
+        }
+        // frequencies are scaled to fit 16 bits, keeping used entries non-zero
+        let max = counts
+            .iter()
+            .copied()
+            .max()
+            .unwrap_or(0)
+            .max(u16::MAX as u64);
+        let data = counts
+            .iter()
+            .map(|&count| match count {
+                0 => 0,
+                count => (count * u16::MAX as u64 / max).max(1) as u16,
+            })
+            .flat_map(u16::to_be_bytes)
+            .collect();
+        png.replace_chunk(index, Chunk::new(ChunkType::new(b"hIST"), data))?;
+    }
+
+    if let (Some(remap), Some(index)) = (remap, position(png, "bKGD"))
+        && let [entry] = png.chunks()[index].data()
+        && let Some(&moved) = remap.get(*entry as usize)
+    {
+        png.replace_chunk(
+            index,
+            Chunk::new(ChunkType::new(b"bKGD"), vec![moved as u8]),
+        )?;
+    }
+
+    Ok(dropped)
+}
+
+/// Palette indices sorted by color and alpha, the order that encodes the number zero
+fn canonical_order(palette: &Palette, alpha: &[u8]) -> Result<Vec<usize>> {
+    let key = |index: &usize| (palette.entries()[*index], alpha[*index]);
+    let mut order: Vec<usize> = (0..palette.len()).collect();
+    order.sort_by_key(key);
+
+    if order.windows(2).any(|pair| key(&pair[0]) == key(&pair[1])) {
+        return Err("Palette entries must be unique to carry data in their order".into());
+    }
+
+    Ok(order)
+}
+
+/// Divides the big-endian number in `number` by `divisor` in place and returns the remainder
+fn div_small(number: &mut [u8], divisor: u32) -> u32 {
+    let mut remainder = 0;
+    for byte in number.iter_mut() {
+        let acc = (remainder << 8) | *byte as u32;
+        *byte = (acc / divisor) as u8;
+        remainder = acc % divisor;
+    }
+    remainder
+}
+
+/// Replaces the big-endian number in `number` with `number * factor + addend`
+fn mul_add_small(number: &mut Vec<u8>, factor: u32, addend: u32) {
+    let mut carry = addend;
+    for byte in number.iter_mut().rev() {
+        let acc = *byte as u32 * factor + carry;
+        *byte = acc as u8;
+        carry = acc >> 8;
+    }
+    while carry > 0 {
+        number.insert(0, carry as u8);
+        carry >>= 8;
+    }
+}
+
+/// Number of message bytes that fit into the order of `palette_len` distinct entries,
+/// i.e. log2(palette_len!) bits minus the payload header and error correction
+pub fn palette_order_capacity(palette_len: usize, fec: Option<&FecOptions>) -> usize {
+    let bits: f64 = (2..=palette_len).map(|n| (n as f64).log2()).sum();
+    message_capacity(bits as usize / 8, fec)
+}
+
+/// Hides `message` in the order of the palette entries. The payload is read as one big
+/// number and turned into a permutation of the entries sorted by color, and the pixel
+/// indices are remapped so the image looks exactly the same.
+pub fn embed_in_palette_order(
+    png: &mut Png,
+    label: &ChunkType,
+    message: &[u8],
+    options: &EmbedOptions,
+) -> Result<Vec<Chunk>> {
+    let (mut pixels, palette, alpha) = indexed_image(png)?;
+    let canonical = canonical_order(&palette, &alpha)?;
+    let len = palette.len();
+
+    // digit k of the factorial number system lies in 0..k
+    let mut number = split_message(label, message, 1)?
+        .remove(0)
+        .to_frame(options.fec.as_ref());
+    let digits: Vec<usize> = (1..=len as u32)
+        .map(|radix| div_small(&mut number, radix) as usize)
+        .collect();
+    if number.iter().any(|&byte| byte != 0) {
+        return Err(format!(
+            "Message needs {} bytes but only {} fit into the order of {} palette entries",
+            message.len(),
+            palette_order_capacity(len, options.fec.as_ref()),
+            len
+        )
+        .into());
+    }
+
+    let mut available = canonical;
+    let order: Vec<usize> = digits
+        .iter()
+        .rev()
+        .map(|&digit| available.remove(digit))
+        .collect();
+
+    let mut remap = vec![0; len];
+    for (position, &old) in order.iter().enumerate() {
+        remap[old] = position;
+    }
+    for sample in pixels.samples_mut() {
+        *sample = remap[*sample as usize] as u16;
+    }
+
+    let new_palette = Palette::new(order.iter().map(|&i| palette.entries()[i]).collect())?;
+    let new_alpha: Vec<u8> = order.iter().map(|&i| alpha[i]).collect();
+
+    write_palette(
+        png,
+        &pixels,
+        &new_palette,
+        &new_alpha,
+        Some(&remap),
+        options.policy,
+    )
+}
+
+/// Reads a message embedded by `embed_in_palette_order`
+pub fn extract_from_palette_order(png: &Png, label: &ChunkType) -> Result<Extracted> {
+    let palette = Palette::from_png(png)?;
+    let transparency = Transparency::from_png(png, ColorType::Indexed)?;
+    let alpha: Vec<u8> = (0..palette.len())
+        .map(|index| transparency.as_ref().map_or(255, |t| t.alpha(index)))
+        .collect();
+
+    let mut available = canonical_order(&palette, &alpha)?;
+    let mut number = Vec::new();
+    for index in 0..palette.len() {
+        let digit = available.iter().position(|&i| i == index).unwrap();
+        available.remove(digit);
+        mul_add_small(&mut number, (palette.len() - index) as u32, digit as u32);
+    }
+
+    join_parts(label, vec![Payload::from_frame(&number)?])
+}
+
+/// Pairs up palette entries with the same alpha whose colors differ by at most
+/// `NEAR_DUPLICATE_DISTANCE`, nearest first. Returns the partner of every entry.
+fn near_duplicate_pairs(palette: &[PaletteEntry], alpha: &[u8]) -> Vec<Option<usize>> {
+    let mut partners = vec![None; palette.len()];
+
+    for i in 0..palette.len() {
+        if partners[i].is_some() {
+            continue;
+        }
+        let nearest = (i + 1..palette.len())
+            .filter(|&j| partners[j].is_none() && alpha[j] == alpha[i])
+            .map(|j| (palette[i].distance(&palette[j]), j))
+            .filter(|(distance, _)| *distance <= NEAR_DUPLICATE_DISTANCE)
+            .min();
+        if let Some((_, j)) = nearest {
+            partners[i] = Some(j);
+            partners[j] = Some(i);
+        }
+    }
+
+    partners
+}
+
+/// Number of pixels that can carry a bit because their color has a near-duplicate
+fn parity_carrier_count(pixels: &PixelData, partners: &[Option<usize>]) -> usize {
+    pixels
+        .samples()
+        .iter()
+        .filter(|&&s| partners[s as usize].is_some())
+        .count()
+}
+
+/// Adds near-duplicates of the most used colors without a partner to `entries` until the
+/// pixels can carry `needed_bytes` or the palette is full. Returns the final pairs.
+fn add_near_duplicates(
+    pixels: &PixelData,
+    entries: &mut Vec<PaletteEntry>,
+    alpha: &mut Vec<u8>,
+    needed_bytes: usize,
+) -> Vec<Option<usize>> {
+    let max_entries = MAX_PALETTE_ENTRIES.min(1 << pixels.header().bit_depth);
+    let mut usage = vec![0usize; MAX_PALETTE_ENTRIES];
+    for &sample in pixels.samples() {
+        usage[sample as usize] += 1;
+    }
+
+    let mut partners = near_duplicate_pairs(entries, alpha);
+    while parity_carrier_count(pixels, &partners) / 8 < needed_bytes {
+        let most_used = (0..entries.len())
+            .filter(|&i| partners[i].is_none() && usage[i] > 0)
+            .max_by_key(|&i| (usage[i], std::cmp::Reverse(i)));
+        let Some(index) = most_used.filter(|_| entries.len() < max_entries) else {
+            break;
+        };
+
+        let twin = PaletteEntry {
+            green: entries[index].green ^ 1,
+            ..entries[index]
+        };
+        entries.push(twin);
+        alpha.push(alpha[index]);
+        partners = near_duplicate_pairs(entries, alpha);
+    }
+
+    partners
+}
+
+/// Maximum number of message bytes `embed_in_palette_parity` fits into `png`, counting the
+/// near-duplicates it would add to the palette
+pub fn palette_parity_capacity(png: &Png, fec: Option<&FecOptions>) -> Result<usize> {
+    let (pixels, palette, mut alpha) = indexed_image(png)?;
+    let mut entries = palette.entries().to_vec();
+    let partners = add_near_duplicates(&pixels, &mut entries, &mut alpha, usize::MAX);
+    Ok(message_capacity(
+        parity_carrier_count(&pixels, &partners) / 8,
+        fec,
+    ))
+}
+
+/// Hides `message` in pixels whose color has a near-duplicate in the palette: the bit is 0
+/// if the pixel uses the lower index of the pair and 1 if it uses the higher one. When
+/// there aren't enough pairs, near-duplicates of the most used colors are added to the
+/// palette while it has room.
+pub fn embed_in_palette_parity(
+    png: &mut Png,
+    label: &ChunkType,
+    message: &[u8],
+    options: &EmbedOptions,
+) -> Result<Vec<Chunk>> {
+    let (mut pixels, palette, mut alpha) = indexed_image(png)?;
+    let mut entries = palette.entries().to_vec();
+
+    let bytes = split_message(label, message, 1)?
+        .remove(0)
+        .to_frame(options.fec.as_ref());
+    let partners = add_near_duplicates(&pixels, &mut entries, &mut alpha, bytes.len());
+    let carrier_bytes = parity_carrier_count(&pixels, &partners) / 8;
+    if carrier_bytes < bytes.len() {
+        return Err(format!(
+            "Message needs {} bytes but only {} fit into the palette",
+            message.len(),
+            message_capacity(carrier_bytes, options.fec.as_ref())
+        )
+        .into());
+    }
+
+    let carriers: Vec<usize> = (0..pixels.samples().len())
+        .filter(|&i| partners[pixels.samples()[i] as usize].is_some())
+        .take(bytes.len() * 8)
+        .collect();
+
+    let samples = pixels.samples_mut();
+    for (bit_index, &sample_index) in carriers.iter().enumerate() {
+        let bit = (bytes[bit_index / 8] >> (7 - bit_index % 8)) & 1;
+        let index = samples[sample_index] as usize;
+        let partner = partners[index].unwrap();
+        if (index > partner) != (bit == 1) {
+            samples[sample_index] = partner as u16;
+        }
+    }
+
+    write_palette(
+        png,
+        &pixels,
+        &Palette::new(entries)?,
+        &alpha,
+        None,
+        options.policy,
+    )
+}
+
+/// Reads a message embedded by `embed_in_palette_parity`
+pub fn extract_from_palette_parity(png: &Png, label: &ChunkType) -> Result<Extracted> {
+    let (pixels, palette, alpha) = indexed_image(png)?;
+    let partners = near_duplicate_pairs(palette.entries(), &alpha);
+
+    let bits: Vec<u8> = pixels
+        .samples()
+        .iter()
+        .filter_map(|&s| partners[s as usize].map(|partner| (s as usize > partner) as u8))
+        .collect();
+    let payload = read_payload(bits.len(), |index| bits[index])?;
+
+    join_parts(label, vec![payload])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apng::{AssembleOptions, assemble};
+    use crate::pixels::ImageHeader;
+    use std::str::FromStr;
+
+    fn label() -> ChunkType {
+        ChunkType::from_str("ruSt").unwrap()
+    }
+
+    fn testing_pixels(color_type: ColorType, bit_depth: u8) -> PixelData {
+        let header = ImageHeader {
+            width: 16,
+            height: 16,
+            bit_depth,
+            color_type,
+            interlaced: false,
+        };
+        let max = header.max_sample() as usize;
+        let samples = (0..16 * 16 * color_type.channels())
+            .map(|i| ((i * 7) % (max + 1)) as u16)
+            .collect();
+        PixelData::new(header, samples).unwrap()
+    }
+
+    fn testing_animation() -> Png {
+        let frames: Vec<Png> = (0..3)
+            .map(|_| Png::from_pixels(&testing_pixels(ColorType::Rgb, 8)).unwrap())
+            .collect();
+        assemble(&frames, None, &AssembleOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn test_payload_round_trip() {
+        let payload = split_message(&label(), b"hello", 1).unwrap().remove(0);
+        let parsed = Payload::try_from(payload.to_bytes().as_ref()).unwrap();
+        assert_eq!(parsed, payload);
+    }
+
+    #[test]
+    fn test_split_and_join() {
+        let message = b"a message split over several parts";
+        let mut parts = split_message(&label(), message, 4).unwrap();
+        assert_eq!(parts.len(), 4);
+
+        parts.reverse();
+        assert_eq!(
+            join_parts(&label(), parts.clone()).unwrap().message,
+            message
+        );
+
+        parts.remove(1);
+        assert!(join_parts(&label(), parts).is_err());
+    }
+
+    #[test]
+    fn test_join_wrong_label() {
+        let parts = split_message(&label(), b"hello", 1).unwrap();
+        assert!(join_parts(&ChunkType::from_str("niCe").unwrap(), parts).is_err());
+    }
+
+    #[test]
+    fn test_lsb_round_trip() {
+        for (color_type, bit_depth) in [
+            (ColorType::Rgb, 8),
+            (ColorType::Rgba, 16),
+            (ColorType::Grayscale, 2),
+        ] {
+            let mut pixels = testing_pixels(color_type, bit_depth);
+            let original = pixels.clone();
+            let payload = split_message(&label(), b"hidden", 1).unwrap().remove(0);
+            embed_lsb(&mut pixels, &payload.to_bytes(), &EmbedOptions::default()).unwrap();
+
+            assert_eq!(
+                extract_lsb(&pixels, &EmbedOptions::default()).unwrap(),
+                payload
+            );
+            for (a, b) in pixels.samples().iter().zip(original.samples()) {
+                assert!(a.abs_diff(*b) <= 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_protected_frame_round_trip() {
+        let fec = FecOptions::new(8, 2).unwrap();
+        let payload = split_message(&label(), b"protected message", 1)
+            .unwrap()
+            .remove(0);
+        let frame = payload.to_frame(Some(&fec));
+
+        assert_eq!(Payload::frame_len(&frame).unwrap(), frame.len());
+        assert_eq!(Payload::from_frame(&frame).unwrap(), payload);
+        assert_eq!(payload.to_frame(None), payload.to_bytes());
+    }
+
+    #[test]
+    fn test_lsb_corrects_flipped_bits() {
+        let mut pixels = testing_pixels(ColorType::Rgb, 8);
+        let fec = FecOptions::new(16, 2).unwrap();
+        let payload = split_message(&label(), b"survives edits", 1)
+            .unwrap()
+            .remove(0);
+        embed_lsb(
+            &mut pixels,
+            &payload.to_frame(Some(&fec)),
+            &EmbedOptions::default(),
+        )
+        .unwrap();
+
+        // one flipped bit in the header and a burst of eight in the data
+        let carriers = carrier_samples(&pixels, &EmbedOptions::default()).unwrap();
+        let samples = pixels.samples_mut();
+        for index in [&carriers[20..21], &carriers[FEC_HEADER_LEN * 8..][..64]].concat() {
+            samples[index] ^= 1;
+        }
+
+        let extracted = extract_lsb(&pixels, &EmbedOptions::default()).unwrap();
+        assert_eq!(extracted.data, payload.data);
+        assert_eq!(extracted.corrected, 1 + 8);
+    }
+
+    /// Pixels with noisy least significant bits, like a photo
+    fn noisy_pixels() -> PixelData {
+        let mut pixels = testing_pixels(ColorType::Rgb, 8);
+        for (i, sample) in pixels.samples_mut().iter_mut().enumerate() {
+            *sample = ((i as u32).wrapping_mul(2_654_435_761) >> 24) as u16;
+        }
+        pixels
+    }
+
+    #[test]
+    fn test_matrix_embedding_round_trip() {
+        let payload = split_message(&label(), b"small", 1).unwrap().remove(0);
+        let mut plain_pixels = noisy_pixels();
+        let plain = embed_lsb(
+            &mut plain_pixels,
+            &payload.to_bytes(),
+            &EmbedOptions::default(),
+        )
+        .unwrap();
+
+        for matrix_bits in 2..=4 {
+            let mut pixels = noisy_pixels();
+            let original = pixels.clone();
+            let options = EmbedOptions {
+                matrix_bits,
+                ..Default::default()
+            };
+            let stats = embed_lsb(&mut pixels, &payload.to_bytes(), &options).unwrap();
+
+            assert_eq!(
+                extract_lsb(&pixels, &EmbedOptions::default()).unwrap(),
+                payload
+            );
+            assert_eq!(stats.bits, plain.bits);
+            assert!(stats.changed < plain.changed);
+            let changed = pixels
+                .samples()
+                .iter()
+                .zip(original.samples())
+                .filter(|(a, b)| a != b)
+                .count();
+            assert_eq!(changed, stats.changed);
+        }
+    }
+
+    #[test]
+    fn test_lsb_matching_round_trip() {
+        for (color_type, bit_depth) in [
+            (ColorType::Rgb, 8),
+            (ColorType::Rgba, 16),
+            (ColorType::Grayscale, 2),
+        ] {
+            let mut pixels = testing_pixels(color_type, bit_depth);
+            let original = pixels.clone();
+            let options = EmbedOptions {
+                lsb_matching: true,
+                ..Default::default()
+            };
+            let payload = split_message(&label(), b"matched", 1).unwrap().remove(0);
+            embed_lsb(&mut pixels, &payload.to_bytes(), &options).unwrap();
+
+            assert_eq!(
+                extract_lsb(&pixels, &EmbedOptions::default()).unwrap(),
+                payload
+            );
+            let max = pixels.header().max_sample();
+            for (a, b) in pixels.samples().iter().zip(original.samples()) {
+                assert!(a.abs_diff(*b) <= 1 && *a <= max);
+            }
+        }
+    }
+
+    #[test]
+    fn test_adaptive_skips_flat_regions() {
+        // noise on the left half, a flat gray on the right
+        let mut pixels = noisy_pixels();
+        for (i, sample) in pixels.samples_mut().iter_mut().enumerate() {
+            if (i / 3) % 16 >= 8 {
+                *sample = 128;
+            }
+        }
+        let original = pixels.clone();
+        let options = EmbedOptions {
+            adaptive: Some(adaptive::DEFAULT_TEXTURE_THRESHOLD),
+            ..Default::default()
+        };
+
+        let full = lsb_capacity(&pixels, &EmbedOptions::default()).unwrap();
+        assert!(lsb_capacity(&pixels, &options).unwrap() < full);
+
+        let payload = split_message(&label(), b"busy", 1).unwrap().remove(0);
+        embed_lsb(&mut pixels, &payload.to_bytes(), &options).unwrap();
+        assert_eq!(extract_lsb(&pixels, &options).unwrap(), payload);
+
+        let flat_changed = pixels
+            .samples()
+            .iter()
+            .zip(original.samples())
+            .enumerate()
+            .any(|(i, (a, b))| (i / 3) % 16 > 8 && a != b);
+        assert!(!flat_changed);
+    }
+
+    #[test]
+    fn test_adaptive_rejects_matching() {
+        let mut pixels = noisy_pixels();
+        let options = EmbedOptions {
+            adaptive: Some(8),
+            lsb_matching: true,
+            ..Default::default()
+        };
+        let payload = split_message(&label(), b"busy", 1).unwrap().remove(0);
+        assert!(embed_lsb(&mut pixels, &payload.to_bytes(), &options).is_err());
+    }
+
+    #[test]
+    fn test_change_lsb() {
+        assert_eq!(change_lsb(6, 255, false), 7);
+        assert_eq!(change_lsb(0, 255, true), 1);
+        assert_eq!(change_lsb(255, 255, true), 254);
+        assert_eq!(change_lsb(u16::MAX, u16::MAX, true), u16::MAX - 1);
+        assert_eq!(change_lsb(1, 1, true), 0);
+
+        let changed: Vec<u16> = (0..64).map(|_| change_lsb(100, 255, true)).collect();
+        assert!(changed.iter().all(|&s| s == 99 || s == 101));
+        assert!(changed.contains(&99) && changed.contains(&101));
+    }
+
+    #[test]
+    fn test_matrix_embedding_too_large() {
+        let mut pixels = testing_pixels(ColorType::Rgb, 8);
+        let options = EmbedOptions {
+            matrix_bits: 4,
+            ..Default::default()
+        };
+        // 768 samples hold 51 groups of 15, i.e. 25 bytes
+        let payload = split_message(&label(), &[0; 10], 1).unwrap().remove(0);
+        assert!(embed_lsb(&mut pixels, &payload.to_bytes(), &options).is_err());
+    }
+
+    #[test]
+    fn test_syndrome() {
+        let samples = [1, 0, 1, 1, 0, 0, 0];
+        let group: Vec<usize> = (0..7).collect();
+        // positions 1, 3 and 4 are odd
+        assert_eq!(syndrome(&samples, &group), 1 ^ 3 ^ 4);
+    }
+
+    #[test]
+    fn test_lsb_skips_alpha() {
+        let mut pixels = testing_pixels(ColorType::Rgba, 8);
+        let original = pixels.clone();
+        let payload = split_message(&label(), &[0xff; 40], 1).unwrap().remove(0);
+        embed_lsb(&mut pixels, &payload.to_bytes(), &EmbedOptions::default()).unwrap();
+
+        let alpha_changed = pixels
+            .samples()
+            .iter()
+            .zip(original.samples())
+            .skip(3)
+            .step_by(4)
+            .any(|(a, b)| a != b);
+        assert!(!alpha_changed);
+    }
+
+    #[test]
+    fn test_lsb_too_large() {
+        let mut pixels = testing_pixels(ColorType::Grayscale, 8);
+        let capacity = lsb_capacity(&pixels, &EmbedOptions::default()).unwrap();
+        let payload = split_message(&label(), &vec![1; capacity + 1], 1)
+            .unwrap()
+            .remove(0);
+        assert!(embed_lsb(&mut pixels, &payload.to_bytes(), &EmbedOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_lsb_capacity_with_error_correction() {
+        let mut pixels = testing_pixels(ColorType::Rgb, 8);
+        let options = EmbedOptions {
+            fec: Some(FecOptions::new(8, 2).unwrap()),
+            ..Default::default()
+        };
+        let capacity = lsb_capacity(&pixels, &options).unwrap();
+
+        let payload = split_message(&label(), &vec![7; capacity], 1)
+            .unwrap()
+            .remove(0);
+        embed_lsb(
+            &mut pixels,
+            &payload.to_frame(options.fec.as_ref()),
+            &options,
+        )
+        .unwrap();
+        let payload = split_message(&label(), &vec![7; capacity + 1], 1)
+            .unwrap()
+            .remove(0);
+        assert!(
+            embed_lsb(
+                &mut pixels,
+                &payload.to_frame(options.fec.as_ref()),
+                &options
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_lsb_rejects_indexed() {
+        let pixels = testing_pixels(ColorType::Indexed, 8);
+        assert!(lsb_capacity(&pixels, &EmbedOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_frames_round_trip() {
+        let mut png = testing_animation();
+        let message = b"spread over every frame of the animation";
+        embed_in_frames(&mut png, &label(), message, &EmbedOptions::default()).unwrap();
+
+        assert!(Animation::from_png(&png).is_ok());
+        assert_eq!(
+            extract_from_frames(&png, &label(), &EmbedOptions::default())
+                .unwrap()
+                .message,
+            message
+        );
+    }
+
+    #[test]
+    fn test_keyed_lsb_round_trip() {
+        let options = EmbedOptions {
+            key: Some(EmbedKey::from_passphrase("correct horse", &label())),
+            ..Default::default()
+        };
+        let mut pixels = testing_pixels(ColorType::Rgb, 8);
+        let payload = split_message(&label(), b"scattered", 1).unwrap().remove(0);
+        embed_lsb(&mut pixels, &payload.to_bytes(), &options).unwrap();
+
+        assert_eq!(extract_lsb(&pixels, &options).unwrap(), payload);
+        assert!(extract_lsb(&pixels, &EmbedOptions::default()).is_err());
+        let wrong = EmbedOptions {
+            key: Some(EmbedKey::from_passphrase("battery staple", &label())),
+            ..Default::default()
+        };
+        assert!(extract_lsb(&pixels, &wrong).is_err());
+    }
+
+    #[test]
+    fn test_keyed_order_is_a_permutation() {
+        let options = EmbedOptions {
+            key: Some(EmbedKey::from_passphrase("passphrase", &label())),
+            ..Default::default()
+        };
+        let pixels = testing_pixels(ColorType::Rgba, 8);
+        let sequential = carrier_samples(&pixels, &EmbedOptions::default()).unwrap();
+        let mut keyed = carrier_samples(&pixels, &options).unwrap();
+
+        assert_ne!(keyed, sequential);
+        assert_eq!(keyed, carrier_samples(&pixels, &options).unwrap());
+        assert_ne!(
+            keyed,
+            carrier_samples(&pixels, &options.for_frame(1)).unwrap()
+        );
+        keyed.sort();
+        assert_eq!(keyed, sequential);
+    }
+
+    #[test]
+    fn test_keyed_frames_round_trip() {
+        let mut png = testing_animation();
+        let options = EmbedOptions {
+            key: Some(EmbedKey::from_passphrase("frames", &label())),
+            ..Default::default()
+        };
+        embed_in_frames(&mut png, &label(), b"one key, many frames", &options).unwrap();
+
+        let extracted = extract_from_frames(&png, &label(), &options).unwrap();
+        assert_eq!(extracted.message, b"one key, many frames");
+        assert!(extract_from_frames(&png, &label(), &EmbedOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_chunks_round_trip() {
+        let mut png = testing_animation();
+        let message = b"spread over interleaved chunks";
+        embed_in_chunks(&mut png, &label(), message, &EmbedOptions::default()).unwrap();
+
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(
+            &types[2..],
+            [
+                "fcTL", "IDAT", "ruSt", "fcTL", "fdAT", "ruSt", "fcTL", "fdAT", "ruSt", "IEND"
+            ]
+        );
+        assert!(Animation::from_png(&png).is_ok());
+        assert_eq!(
+            extract_from_chunks(&png, &label()).unwrap().message,
+            message
+        );
+    }
+
+    #[test]
+    fn test_zlib_round_trip() {
+        let pixels = testing_pixels(ColorType::Rgb, 8);
+        let mut png = Png::from_pixels(&pixels).unwrap();
+        let message = b"after the checksum";
+        embed_in_zlib(&mut png, &label(), message, &EmbedOptions::default()).unwrap();
+
+        assert_eq!(PixelData::decode(&png).unwrap(), pixels);
+        assert_eq!(extract_from_zlib(&png, &label()).unwrap().message, message);
+        assert!(embed_in_zlib(&mut png, &label(), message, &EmbedOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_zlib_keeps_unsafe_chunks() {
+        let mut png = Png::from_pixels(&testing_pixels(ColorType::Rgb, 8)).unwrap();
+        let unsafe_chunk = Chunk::new(ChunkType::from_str("abCD").unwrap(), vec![1, 2, 3]);
+        png.insert_chunk(unsafe_chunk, ChunkPosition::BeforeIend)
+            .unwrap();
+
+        let dropped =
+            embed_in_zlib(&mut png, &label(), b"message", &EmbedOptions::default()).unwrap();
+        assert!(dropped.is_empty());
+        assert!(png.chunk_by_type("abCD").is_some());
+    }
+
+    #[test]
+    fn test_zlib_without_message() {
+        let png = Png::from_pixels(&testing_pixels(ColorType::Rgb, 8)).unwrap();
+        assert!(extract_from_zlib(&png, &label()).is_err());
+    }
+
+    fn testing_indexed_png(palette_len: usize) -> Png {
+        let pixels = testing_pixels(ColorType::Indexed, 8);
+        let samples = pixels
+            .samples()
+            .iter()
+            .map(|&s| s % palette_len as u16)
+            .collect();
+        let pixels = PixelData::new(*pixels.header(), samples).unwrap();
+        let palette = Palette::new(
+            (0..palette_len)
+                .map(|i| PaletteEntry {
+                    red: (i * 37) as u8,
+                    green: (i * 11) as u8,
+                    blue: 255 - i as u8,
+                })
+                .collect(),
+        )
+        .unwrap();
+        let alpha: Vec<u8> = (0..palette_len)
+            .map(|i| if i == 1 { 0 } else { 255 })
+            .collect();
+
+        let mut png = Png::from_pixels(&pixels).unwrap();
+        png.replace_critical_chunks(vec![palette.to_chunk()], UnsafeChunkPolicy::Drop)
+            .unwrap();
+        png.insert_chunk(
+            Transparency::from_alpha(&alpha).unwrap().to_chunk(),
+            ChunkPosition::After(ChunkType::new(b"PLTE")),
+        )
+        .unwrap();
+        png
+    }
+
+    fn rendered(png: &Png) -> PixelData {
+        PixelData::decode(png)
+            .unwrap()
+            .to_rgba8(
+                png.chunk_by_type("PLTE").map(|c| c.data()),
+                png.chunk_by_type("tRNS").map(|c| c.data()),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn test_palette_order_round_trip() {
+        let mut png = testing_indexed_png(128);
+        let original = rendered(&png);
+        let message = b"hidden in the palette order";
+        embed_in_palette_order(&mut png, &label(), message, &EmbedOptions::default()).unwrap();
+
+        assert_ne!(
+            Palette::from_png(&png).unwrap(),
+            Palette::from_png(&testing_indexed_png(128)).unwrap()
+        );
+        assert_eq!(rendered(&png), original);
+        assert_eq!(
+            extract_from_palette_order(&png, &label()).unwrap().message,
+            message
+        );
+    }
+
+    #[test]
+    fn test_palette_order_too_large() {
+        let mut png = testing_indexed_png(40);
+        let capacity = palette_order_capacity(40, None);
+        let result = embed_in_palette_order(
+            &mut png,
+            &label(),
+            &vec![b'x'; capacity + 8],
+            &EmbedOptions::default(),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_palette_parity_round_trip() {
+        let mut png = testing_indexed_png(16);
+        let original = rendered(&png);
+        let message = b"parity";
+        embed_in_palette_parity(&mut png, &label(), message, &EmbedOptions::default()).unwrap();
+
+        assert!(Palette::from_png(&png).unwrap().len() > 16);
+        assert!(png.check_ordering().is_ok());
+        for (a, b) in rendered(&png).samples().iter().zip(original.samples()) {
+            assert!(a.abs_diff(*b) <= 1);
+        }
+        assert_eq!(
+            extract_from_palette_parity(&png, &label()).unwrap().message,
+            message
+        );
+    }
+
+    #[test]
+    fn test_palette_parity_reports_message_size() {
+        let mut png = testing_indexed_png(16);
+        let options = EmbedOptions {
+            fec: Some(FecOptions::new(16, 1).unwrap()),
+            ..Default::default()
+        };
+        let error = embed_in_palette_parity(&mut png, &label(), &[b'x'; 5000], &options)
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with("Message needs 5000 bytes"), "{}", error);
+    }
+
+    #[test]
+    fn test_near_duplicate_pairs() {
+        let entry = |v: u8| PaletteEntry {
+            red: v,
+            green: v,
+            blue: v,
+        };
+        let palette = [entry(0), entry(100), entry(3), entry(102), entry(200)];
+        let partners = near_duplicate_pairs(&palette, &[255; 5]);
+        assert_eq!(partners, vec![Some(2), Some(3), Some(0), Some(1), None]);
+    }
+
+    #[test]
+    fn test_big_number_helpers() {
+        let mut number = vec![0x01, 0x00];
+        assert_eq!(div_small(&mut number, 7), 256 % 7);
+        assert_eq!(number, vec![0, (256 / 7) as u8]);
+
+        let mut number = vec![0xff];
+        mul_add_small(&mut number, 256, 3);
+        assert_eq!(number, vec![0xff, 0x03]);
+    }
+}
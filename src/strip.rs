@@ -0,0 +1,106 @@
+use crate::chunk_types::ChunkType;
+
+/// Decides which ancillary chunks `Png::strip` removes. Critical chunks are never removed
+/// since the image can't be decoded without them. The APNG chunks `acTL`, `fcTL` and `fdAT`
+/// only work together, so listing one of them in `Keep` or `Drop` covers all three.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StripPolicy {
+    /// Remove every ancillary chunk
+    AllAncillary,
+    /// Remove ancillary chunks that are not safe to copy
+    UnsafeToCopy,
+    /// Remove private ancillary chunks
+    Private,
+    /// Remove every ancillary chunk except the listed types
+    Keep(Vec<ChunkType>),
+    /// Remove only the listed ancillary chunk types
+    Drop(Vec<ChunkType>),
+}
+
+impl StripPolicy {
+    /// Returns true if a chunk of type `chunk_type` should be removed under this policy
+    pub fn should_remove(&self, chunk_type: &ChunkType) -> bool {
+        if chunk_type.is_critical() {
+            return false;
+        }
+
+        match self {
+            StripPolicy::AllAncillary => true,
+            StripPolicy::UnsafeToCopy => !chunk_type.is_safe_to_copy(),
+            StripPolicy::Private => !chunk_type.is_public(),
+            StripPolicy::Keep(types) => !lists(types, chunk_type),
+            StripPolicy::Drop(types) => lists(types, chunk_type),
+        }
+    }
+}
+
+/// Chunks that make up an animation, removing only some of them breaks the file
+const ANIMATION_CHUNK_TYPES: [&[u8; 4]; 3] = [b"acTL", b"fcTL", b"fdAT"];
+
+/// Whether `types` names `chunk_type`, or another chunk of its animation group
+fn lists(types: &[ChunkType], chunk_type: &ChunkType) -> bool {
+    let animation = |t: &ChunkType| ANIMATION_CHUNK_TYPES.contains(&&t.bytes());
+    types.contains(chunk_type) || (animation(chunk_type) && types.iter().any(animation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn chunk_type(s: &str) -> ChunkType {
+        ChunkType::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_critical_never_removed() {
+        let policy = StripPolicy::Drop(vec![chunk_type("IDAT")]);
+        assert!(!policy.should_remove(&chunk_type("IDAT")));
+        assert!(!StripPolicy::AllAncillary.should_remove(&chunk_type("IHDR")));
+    }
+
+    #[test]
+    fn test_all_ancillary() {
+        assert!(StripPolicy::AllAncillary.should_remove(&chunk_type("tEXt")));
+    }
+
+    #[test]
+    fn test_unsafe_to_copy() {
+        assert!(StripPolicy::UnsafeToCopy.should_remove(&chunk_type("ruST")));
+        assert!(!StripPolicy::UnsafeToCopy.should_remove(&chunk_type("tEXt")));
+    }
+
+    #[test]
+    fn test_private() {
+        assert!(StripPolicy::Private.should_remove(&chunk_type("ruSt")));
+        assert!(!StripPolicy::Private.should_remove(&chunk_type("tEXt")));
+    }
+
+    #[test]
+    fn test_keep_and_drop_lists() {
+        let keep = StripPolicy::Keep(vec![chunk_type("pHYs")]);
+        assert!(!keep.should_remove(&chunk_type("pHYs")));
+        assert!(keep.should_remove(&chunk_type("tEXt")));
+
+        let drop = StripPolicy::Drop(vec![chunk_type("tEXt")]);
+        assert!(drop.should_remove(&chunk_type("tEXt")));
+        assert!(!drop.should_remove(&chunk_type("pHYs")));
+    }
+
+    #[test]
+    fn test_animation_chunks_stay_together() {
+        let drop = StripPolicy::Drop(vec![chunk_type("fcTL")]);
+        for t in ["acTL", "fcTL", "fdAT"] {
+            assert!(drop.should_remove(&chunk_type(t)));
+        }
+        assert!(!drop.should_remove(&chunk_type("tEXt")));
+
+        let keep = StripPolicy::Keep(vec![chunk_type("acTL")]);
+        for t in ["acTL", "fcTL", "fdAT"] {
+            assert!(!keep.should_remove(&chunk_type(t)));
+        }
+
+        let keep = StripPolicy::Keep(vec![chunk_type("pHYs")]);
+        assert!(keep.should_remove(&chunk_type("fdAT")));
+    }
+}
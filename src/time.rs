@@ -0,0 +1,236 @@
+use crate::chunk::Chunk;
+use crate::chunk_types::ChunkType;
+use crate::png::{ChunkPosition, Png};
+use crate::{Error, Result};
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// The contents of a `tIME` chunk: the last modification of the image, in UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Time {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    /// Up to 60 to allow for leap seconds
+    pub second: u8,
+}
+
+impl Time {
+    /// Creates a time, failing if any field is out of range
+    pub fn new(year: u16, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> Result<Self> {
+        if !(1..=12).contains(&month) {
+            return Err(format!("Invalid month {}", month).into());
+        }
+        if day == 0 || day > days_in_month(year, month) {
+            return Err(format!("Invalid day {} for {}-{:02}", day, year, month).into());
+        }
+        if hour > 23 || minute > 59 || second > 60 {
+            return Err(format!("Invalid time {:02}:{:02}:{:02}", hour, minute, second).into());
+        }
+
+        Ok(Time {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        })
+    }
+
+    /// The current time of the system clock
+    pub fn now() -> Result<Self> {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| "System clock is before 1970")?
+            .as_secs();
+        Time::from_unix(seconds)
+    }
+
+    /// Converts seconds since 1970-01-01 00:00:00 UTC
+    pub fn from_unix(seconds: u64) -> Result<Self> {
+        let (days, time_of_day) = (seconds / SECONDS_PER_DAY, seconds % SECONDS_PER_DAY);
+
+        // civil_from_days by Howard Hinnant, counting in 400 year eras starting in March
+        let days = days as i64 + 719_468;
+        let era = days / 146_097;
+        let day_of_era = days % 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+        let month = if shifted_month < 10 {
+            shifted_month + 3
+        } else {
+            shifted_month - 9
+        };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+        Time::new(
+            u16::try_from(year).map_err(|_| "Year doesn't fit a tIME chunk")?,
+            month as u8,
+            day as u8,
+            (time_of_day / 3600) as u8,
+            (time_of_day / 60 % 60) as u8,
+            (time_of_day % 60) as u8,
+        )
+    }
+
+    /// Reads the `tIME` chunk of `png`, if it has one
+    pub fn from_png(png: &Png) -> Result<Option<Self>> {
+        png.chunk_by_type("tIME")
+            .map(|chunk| Time::try_from(chunk.data()))
+            .transpose()
+    }
+
+    /// Returns this time as a `tIME` chunk
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = self.year.to_be_bytes().to_vec();
+        data.extend([self.month, self.day, self.hour, self.minute, self.second]);
+        Chunk::new(ChunkType::new(b"tIME"), data)
+    }
+
+    /// Stores this time in `png`, replacing its `tIME` chunk or adding one before `IEND`.
+    /// Returns the time it replaced.
+    pub fn apply(&self, png: &mut Png) -> Result<Option<Time>> {
+        let old = Time::from_png(png).ok().flatten();
+        png.set_chunk(self.to_chunk(), ChunkPosition::BeforeIend)?;
+        Ok(old)
+    }
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    let leap = year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl TryFrom<&[u8]> for Time {
+    type Error = Error;
+
+    fn try_from(data: &[u8]) -> Result<Self> {
+        let [y0, y1, month, day, hour, minute, second] = data else {
+            return Err(format!("tIME must be 7 bytes long, not {}", data.len()).into());
+        };
+        Time::new(
+            u16::from_be_bytes([*y0, *y1]),
+            *month,
+            *day,
+            *hour,
+            *minute,
+            *second,
+        )
+    }
+}
+
+impl FromStr for Time {
+    type Err = Error;
+
+    /// Parses `YYYY-MM-DD`, optionally followed by `THH:MM:SS` or ` HH:MM:SS` and a `Z`
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid =
+            || -> Error { format!("Invalid time {:?}, expected YYYY-MM-DDTHH:MM:SS", s).into() };
+        let s = s.trim().trim_end_matches(['Z', 'z']);
+        let (date, time) = s.split_once(['T', 't', ' ']).unwrap_or((s, "00:00:00"));
+
+        let numbers = |part: &str, separator: char| -> Result<Vec<u16>> {
+            part.split(separator)
+                .map(|n| n.parse::<u16>().map_err(|_| invalid()))
+                .collect()
+        };
+        let (date, time) = (numbers(date, '-')?, numbers(time, ':')?);
+        let ([year, month, day], [hour, minute, second]) = (&date[..], &time[..]) else {
+            return Err(invalid());
+        };
+        let narrow = |n: &u16| u8::try_from(*n).map_err(|_| invalid());
+
+        Time::new(
+            *year,
+            narrow(month)?,
+            narrow(day)?,
+            narrow(hour)?,
+            narrow(minute)?,
+            narrow(second)?,
+        )
+    }
+}
+
+impl fmt::Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::tests::PNG_FILE;
+
+    #[test]
+    fn test_chunk_round_trip() {
+        let time = Time::new(2024, 2, 29, 23, 59, 60).unwrap();
+        let chunk = time.to_chunk();
+        assert_eq!(chunk.data(), &[0x07, 0xe8, 2, 29, 23, 59, 60]);
+        assert_eq!(Time::try_from(chunk.data()).unwrap(), time);
+    }
+
+    #[test]
+    fn test_range_validation() {
+        assert!(Time::new(2023, 2, 29, 0, 0, 0).is_err());
+        assert!(Time::new(2000, 2, 29, 0, 0, 0).is_ok());
+        assert!(Time::new(1900, 2, 29, 0, 0, 0).is_err());
+        assert!(Time::new(2024, 13, 1, 0, 0, 0).is_err());
+        assert!(Time::new(2024, 4, 31, 0, 0, 0).is_err());
+        assert!(Time::new(2024, 1, 1, 24, 0, 0).is_err());
+        assert!(Time::try_from(&[0x07, 0xe8, 1, 1, 0, 0][..]).is_err());
+    }
+
+    #[test]
+    fn test_from_unix() {
+        assert_eq!(
+            Time::from_unix(0).unwrap(),
+            Time::new(1970, 1, 1, 0, 0, 0).unwrap()
+        );
+        assert_eq!(
+            Time::from_unix(951_782_400 + 3661).unwrap(),
+            Time::new(2000, 2, 29, 1, 1, 1).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        let time: Time = "2026-10-18T09:05:00Z".parse().unwrap();
+        assert_eq!(time, Time::new(2026, 10, 18, 9, 5, 0).unwrap());
+        assert_eq!(time.to_string(), "2026-10-18T09:05:00Z");
+        assert_eq!("2026-10-18".parse::<Time>().unwrap().hour, 0);
+        assert!("2026-10-18T09:05".parse::<Time>().is_err());
+        assert!("yesterday".parse::<Time>().is_err());
+    }
+
+    #[test]
+    fn test_apply_replaces_existing_time() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let first = Time::new(2020, 1, 1, 0, 0, 0).unwrap();
+        let second = Time::new(2021, 6, 15, 12, 0, 0).unwrap();
+
+        assert_eq!(first.apply(&mut png).unwrap(), None);
+        assert_eq!(second.apply(&mut png).unwrap(), Some(first));
+        assert_eq!(Time::from_png(&png).unwrap(), Some(second));
+        assert!(png.check_ordering().is_ok());
+    }
+}
@@ -0,0 +1,226 @@
+use crate::color::ColorInfo;
+use crate::hdr::{ContentLightLevel, MasteringDisplay};
+use crate::ordering::ordering_violations;
+use crate::pixels::{ImageHeader, PixelData};
+use crate::png::Png;
+
+use flate2::read::ZlibDecoder;
+use std::fmt;
+use std::io::Read;
+
+/// Problems found in a PNG file. Errors make the file invalid, warnings point out data
+/// that decoders ignore but that is still allowed.
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl ValidationReport {
+    /// Checks the structure of `bytes`: chunk layout and CRCs, chunk ordering, conflicting
+    /// color space chunks, HDR metadata, the image header and whether the image data decompresses to the
+    /// size the header promises
+    pub fn new(bytes: &[u8]) -> Self {
+        let mut report = ValidationReport::default();
+
+        let (png, trailing) = match Png::split_trailing_data(bytes) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                report.errors.push(e.to_string());
+                return report;
+            }
+        };
+        if !trailing.is_empty() {
+            report
+                .warnings
+                .push(format!("{} bytes of data after IEND", trailing.len()));
+        }
+        if png.chunk_by_type("IEND").is_none() {
+            report.errors.push("Missing IEND chunk".to_string());
+        }
+        report.errors.extend(ordering_violations(png.chunks()));
+        match ColorInfo::from_png(&png) {
+            Ok(color) => report.warnings.extend(color.conflicts()),
+            Err(e) => report.errors.push(e.to_string()),
+        }
+        match MasteringDisplay::from_png(&png) {
+            Ok(display) => report
+                .warnings
+                .extend(display.iter().flat_map(MasteringDisplay::problems)),
+            Err(e) => report.errors.push(format!("Invalid mDCV chunk: {}", e)),
+        }
+        match ContentLightLevel::from_png(&png) {
+            Ok(level) => report
+                .warnings
+                .extend(level.iter().flat_map(ContentLightLevel::problems)),
+            Err(e) => report.errors.push(format!("Invalid cLLI chunk: {}", e)),
+        }
+
+        let header = match ImageHeader::from_png(&png) {
+            Ok(header) => header,
+            Err(e) => {
+                report.errors.push(e.to_string());
+                return report;
+            }
+        };
+        report.check_image_data(&png, header);
+
+        report
+    }
+
+    fn check_image_data(&mut self, png: &Png, header: ImageHeader) {
+        let raw_len = match header.raw_len() {
+            Ok(len) => len,
+            Err(e) => {
+                self.errors.push(e.to_string());
+                return;
+            }
+        };
+        let image_data = png.image_data();
+        if image_data.is_empty() {
+            self.errors.push("Missing IDAT chunk".to_string());
+            return;
+        }
+
+        let stream_len = match PixelData::stream_len(&image_data) {
+            Ok(len) => len,
+            Err(e) => {
+                self.errors.push(format!("Invalid zlib stream: {}", e));
+                return;
+            }
+        };
+        if stream_len < image_data.len() {
+            self.warnings.push(format!(
+                "{} bytes of data after the end of the zlib stream",
+                image_data.len() - stream_len
+            ));
+        }
+
+        // read one byte more than expected so oversized data is noticed without inflating it all
+        let mut raw = Vec::new();
+        if let Err(e) = ZlibDecoder::new(&image_data[..])
+            .take((raw_len as u64).saturating_add(1))
+            .read_to_end(&mut raw)
+        {
+            self.errors.push(format!("Invalid zlib stream: {}", e));
+            return;
+        }
+        if raw.len() != raw_len {
+            self.errors.push(format!(
+                "Image data decompresses to {} bytes, expected {}",
+                if raw.len() > raw_len {
+                    format!("more than {}", raw_len)
+                } else {
+                    raw.len().to_string()
+                },
+                raw_len
+            ));
+        } else if let Err(e) = PixelData::decode_stream(header, &image_data) {
+            self.errors.push(e.to_string());
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", if self.is_valid() { "Valid" } else { "Invalid" })?;
+        for error in &self.errors {
+            writeln!(f, "  Error: {}", error)?;
+        }
+        for warning in &self.warnings {
+            writeln!(f, "  Warning: {}", warning)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_types::ChunkType;
+    use crate::png::tests::PNG_FILE;
+
+    /// Swaps a chunk directly, without the checks `Png` applies to critical chunk edits
+    fn with_chunk(png: &Png, index: usize, chunk: Chunk) -> Png {
+        let mut chunks = png.chunks().to_vec();
+        chunks[index] = chunk;
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_valid_file() {
+        let report = ValidationReport::new(&PNG_FILE);
+        assert!(report.is_valid(), "{}", report);
+        assert!(report.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_data_after_zlib_stream_is_a_warning() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let index = png
+            .chunks()
+            .iter()
+            .rposition(|c| c.chunk_type().bytes() == *b"IDAT")
+            .unwrap();
+        let mut data = png.chunks()[index].data().to_vec();
+        data.extend(b"hidden");
+        let png = with_chunk(&png, index, Chunk::new(ChunkType::new(b"IDAT"), data));
+
+        let report = ValidationReport::new(&png.as_bytes());
+        assert!(report.is_valid(), "{}", report);
+        assert_eq!(report.warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_inconsistent_hdr_metadata_is_a_warning() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let level = ContentLightLevel {
+            max_cll: 100,
+            max_fall: 200,
+        };
+        level.apply(&mut png).unwrap();
+
+        let report = ValidationReport::new(&png.as_bytes());
+        assert!(report.is_valid(), "{}", report);
+        assert_eq!(report.warnings, level.problems());
+
+        png.set_chunk(
+            Chunk::new(ChunkType::new(b"mDCV"), vec![0; 8]),
+            crate::png::ChunkPosition::AfterIhdr,
+        )
+        .unwrap();
+        assert!(!ValidationReport::new(&png.as_bytes()).is_valid());
+    }
+
+    #[test]
+    fn test_truncated_image_data() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let index = png
+            .chunks()
+            .iter()
+            .position(|c| c.chunk_type().bytes() == *b"IDAT")
+            .unwrap();
+        let data = png.chunks()[index].data()[..10].to_vec();
+        let png = with_chunk(&png, index, Chunk::new(ChunkType::new(b"IDAT"), data));
+
+        assert!(!ValidationReport::new(&png.as_bytes()).is_valid());
+    }
+
+    #[test]
+    fn test_huge_dimensions_are_an_error() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let mut ihdr = png.chunk_by_type("IHDR").unwrap().data().to_vec();
+        // 16 bit RGBA at the largest width and height PNG allows
+        ihdr[0..10].copy_from_slice(&[0x7f, 0xff, 0xff, 0xff, 0x7f, 0xff, 0xff, 0xff, 16, 6]);
+        let png = with_chunk(&png, 0, Chunk::new(ChunkType::new(b"IHDR"), ihdr));
+
+        let report = ValidationReport::new(&png.as_bytes());
+        assert!(!report.is_valid());
+        assert_eq!(report.errors, ["Image dimensions are too large"]);
+    }
+}